@@ -1,9 +1,9 @@
 use crate::{
-    block::{ChainedInstance, BlockError},
+    block::{BlockError, ChainedInstance},
     chain::Chain,
     error::{DataBaseError, SerdeError},
     record::Record,
-    Hash
+    Hash,
 };
 
 pub mod puzzles;
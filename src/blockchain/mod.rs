@@ -1 +1 @@
-pub mod consensus;
\ No newline at end of file
+pub mod consensus;
@@ -0,0 +1,363 @@
+//! Short, checksummed addresses of public keys.
+//!
+//! An [`Address`] is a 20-byte hash of a `PublicKey` and its algorithm, written in the Bech32m
+//! format of BIP 350 after a human-readable prefix that names the chain, e.g. `test1q...`. The
+//! checksum detects any typo of up to four characters, and addresses of different chains cannot be
+//! mistaken for each other.
+//!
+//! Addresses are serialized as their string form, so records can refer to parties by address.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{address::Address, Signer};
+//!
+//! let keypair = blockify::generate_ed25519_keypair();
+//! let address = keypair.public_key().address("bank").unwrap();
+//! assert!(address.to_string().starts_with("bank1"));
+//!
+//! let parsed = Address::parse_for(&address.to_string(), "bank").unwrap();
+//! assert_eq!(address, parsed);
+//! assert!(parsed.matches(&keypair.public_key()));
+//!
+//! assert!(Address::parse_for(&address.to_string(), "shop").is_err());
+//! ```
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{canonical, PublicKey};
+
+/// The tag hashed together with every public key, separating addresses from other hashes.
+pub const ADDRESS_TAG: &str = "blockify-address";
+
+/// The length of the hash of the public key in an address.
+pub const ADDRESS_LEN: usize = 20;
+
+const MAX_LEN: usize = 90;
+const SEPARATOR: char = '1';
+const CHECKSUM_LEN: usize = 6;
+const CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+const GENERATOR: [u32; 5] = [
+    0x3b6a_57b2,
+    0x2650_8e6d,
+    0x1ea1_19fa,
+    0x3d42_33dd,
+    0x2a14_62b3,
+];
+
+/// An error that can occur while creating or parsing an `Address`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressError {
+    /// The prefix is empty, too long, or has characters other than lowercase ASCII letters and digits.
+    InvalidPrefix,
+    /// The address has no separator, or is too short or too long.
+    InvalidLength,
+    /// The address has a character outside the Bech32 alphabet.
+    InvalidCharacter,
+    /// The address mixes upper and lower case characters.
+    MixedCase,
+    /// The checksum does not match, usually because of a typo.
+    InvalidChecksum,
+    /// The address belongs to a chain with a different prefix.
+    PrefixMismatch,
+}
+
+crate::impl_display_error!(AddressError);
+
+/// The address of a `PublicKey` on the chain identified by a prefix.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address {
+    prefix: String,
+    hash: [u8; ADDRESS_LEN],
+}
+
+impl Address {
+    /// Creates the address of `pubkey` with the chain prefix `prefix`.
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidPrefix` - If `prefix` is not 1 to 20 lowercase ASCII letters and digits.
+    pub fn new(prefix: &str, pubkey: &PublicKey) -> Result<Address, AddressError> {
+        check_prefix(prefix)?;
+        Ok(Address {
+            prefix: prefix.to_owned(),
+            hash: key_hash(pubkey),
+        })
+    }
+
+    /// Parses an address in its string form.
+    ///
+    /// Addresses written entirely in upper case are accepted as well.
+    ///
+    /// # Errors
+    ///
+    /// * `InvalidLength` - If `address` is too short, too long or has no separator.
+    /// * `InvalidPrefix` - If the prefix is not valid, see [`Address::new`].
+    /// * `InvalidCharacter` - If `address` has a character outside the Bech32 alphabet.
+    /// * `MixedCase` - If `address` mixes upper and lower case characters.
+    /// * `InvalidChecksum` - If the checksum does not match.
+    pub fn parse(address: &str) -> Result<Address, AddressError> {
+        let (prefix, data) = decode(address)?;
+        check_prefix(&prefix)?;
+        let hash = convert_bits(&data, 5, 8, false)
+            .and_then(|bytes| <[u8; ADDRESS_LEN]>::try_from(bytes).ok())
+            .ok_or(AddressError::InvalidLength)?;
+        Ok(Address { prefix, hash })
+    }
+
+    /// Parses an address like [`Address::parse`] and checks that it has the prefix `prefix`.
+    pub fn parse_for(address: &str, prefix: &str) -> Result<Address, AddressError> {
+        let address = Self::parse(address)?;
+        match address.prefix == prefix {
+            true => Ok(address),
+            false => Err(AddressError::PrefixMismatch),
+        }
+    }
+
+    /// Returns the prefix of the chain this address belongs to.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Returns the hash of the public key.
+    pub fn as_bytes(&self) -> &[u8; ADDRESS_LEN] {
+        &self.hash
+    }
+
+    /// Returns `true` if this is the address of `pubkey`.
+    pub fn matches(&self, pubkey: &PublicKey) -> bool {
+        self.hash == key_hash(pubkey)
+    }
+}
+
+impl PublicKey {
+    /// Returns the address of this `PublicKey` with the chain prefix `prefix`, see [`Address::new`].
+    pub fn address(&self, prefix: &str) -> Result<Address, AddressError> {
+        Address::new(prefix, self)
+    }
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let data = convert_bits(&self.hash, 8, 5, true).expect("padding never fails");
+        f.write_str(&encode(&self.prefix, &data))
+    }
+}
+
+impl std::str::FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Address::parse(s)
+    }
+}
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let address = String::deserialize(deserializer)?;
+        Address::parse(&address).map_err(serde::de::Error::custom)
+    }
+}
+
+fn key_hash(pubkey: &PublicKey) -> [u8; ADDRESS_LEN] {
+    let tagged = (ADDRESS_TAG, pubkey.algorithm(), pubkey.as_bytes());
+    let encoded = canonical::encode(&tagged).expect("public keys always have a canonical encoding");
+    let mut hash = [0; ADDRESS_LEN];
+    hash.copy_from_slice(&crate::hash_bytes(&encoded)[..ADDRESS_LEN]);
+    hash
+}
+
+fn check_prefix(prefix: &str) -> Result<(), AddressError> {
+    let valid = (1..=20).contains(&prefix.len())
+        && prefix
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit());
+    match valid {
+        true => Ok(()),
+        false => Err(AddressError::InvalidPrefix),
+    }
+}
+
+fn polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    values.into_iter().fold(1, |checksum, value| {
+        let top = checksum >> 25;
+        let checksum = (checksum & 0x1ff_ffff) << 5 ^ value as u32;
+        GENERATOR
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| (top >> i) & 1 == 1)
+            .fold(checksum, |checksum, (_, g)| checksum ^ g)
+    })
+}
+
+/// Expands the prefix into the values that are covered by the checksum
+fn expand_prefix(prefix: &str) -> impl Iterator<Item = u8> + '_ {
+    let high = prefix.bytes().map(|b| b >> 5);
+    let low = prefix.bytes().map(|b| b & 31);
+    high.chain([0]).chain(low)
+}
+
+/// Encodes 5-bit `data` after `prefix` as a Bech32m string
+fn encode(prefix: &str, data: &[u8]) -> String {
+    let values = expand_prefix(prefix)
+        .chain(data.iter().copied())
+        .chain([0; CHECKSUM_LEN]);
+    let checksum = polymod(values) ^ BECH32M_CONST;
+    let checksum = (0..CHECKSUM_LEN).map(|i| ((checksum >> (5 * (5 - i))) & 31) as u8);
+
+    let mut encoded = format!("{prefix}{SEPARATOR}");
+    encoded.extend(
+        data.iter()
+            .copied()
+            .chain(checksum)
+            .map(|value| CHARSET[value as usize] as char),
+    );
+    encoded
+}
+
+/// Decodes a Bech32m string into its prefix and 5-bit data, without the checksum
+fn decode(encoded: &str) -> Result<(String, Vec<u8>), AddressError> {
+    if encoded.len() > MAX_LEN {
+        return Err(AddressError::InvalidLength);
+    }
+    if !encoded.bytes().all(|b| (33..=126).contains(&b)) {
+        return Err(AddressError::InvalidCharacter);
+    }
+    let lower = encoded.to_ascii_lowercase();
+    if lower != encoded && encoded.to_ascii_uppercase() != encoded {
+        return Err(AddressError::MixedCase);
+    }
+
+    let (prefix, data) = lower
+        .rsplit_once(SEPARATOR)
+        .ok_or(AddressError::InvalidLength)?;
+    if prefix.is_empty() || data.len() < CHECKSUM_LEN {
+        return Err(AddressError::InvalidLength);
+    }
+    let data = data
+        .bytes()
+        .map(|b| {
+            CHARSET
+                .iter()
+                .position(|c| *c == b)
+                .map(|value| value as u8)
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or(AddressError::InvalidCharacter)?;
+
+    if polymod(expand_prefix(prefix).chain(data.iter().copied())) != BECH32M_CONST {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok((
+        prefix.to_owned(),
+        data[..data.len() - CHECKSUM_LEN].to_vec(),
+    ))
+}
+
+/// Regroups `data` from groups of `from` bits into groups of `to` bits
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut converted = vec![];
+    let max = (1 << to) - 1;
+    for value in data {
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((acc >> bits) & max) as u8);
+        }
+    }
+    if pad && bits > 0 {
+        converted.push(((acc << (to - bits)) & max) as u8);
+    } else if !pad && (bits >= from || (acc << (to - bits)) & max != 0) {
+        return None;
+    }
+    Some(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Address, AddressError};
+    use crate::{KeyPairAlgorithm, PublicKey};
+
+    #[test]
+    fn bech32m_test() {
+        // valid test vectors of BIP 350
+        for vector in [
+            "A1LQFN3A",
+            "a1lqfn3a",
+            "an83characterlonghumanreadablepartthatcontainsthetheexcludedcharactersbioandnumber11sg7hg6",
+            "abcdef1l7aum6echk45nj3s0wdvt2fg8x9yrzpqzd3ryx",
+            "split1checkupstagehandshakeupstreamerranterredcaperredlc445v",
+            "?1v759aa",
+        ] {
+            let (prefix, data) = decode(vector).unwrap();
+            assert_eq!(vector.to_lowercase(), encode(&prefix, &data));
+        }
+        // invalid test vectors of BIP 350
+        for vector in [
+            "\x201xj0phk",
+            "qyrz8wqd2c9m",
+            "1qyrz8wqd2c9m",
+            "y1b0jsk6g",
+            "lt1igcx5c0",
+            "in1muywd",
+            "mm1crxm3i",
+            "au1s5cgom",
+            "M1VUXWEZ",
+            "16plkw9",
+            "1p2gdwpf",
+        ] {
+            assert!(decode(vector).is_err(), "{vector:?}");
+        }
+    }
+
+    #[test]
+    fn address_test() {
+        // RFC 8032, section 7.1, test 1
+        let bytes = hex::decode("d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a")
+            .unwrap();
+        let pubkey = PublicKey::new(bytes.clone().into(), KeyPairAlgorithm::ED25519);
+        let address = pubkey.address("test").unwrap();
+        let encoded = address.to_string();
+        assert_eq!("test1udq0vrspyft4s79kqud87slq9p54trqvx8m79x", encoded);
+
+        assert_eq!(address, encoded.parse().unwrap());
+        assert_eq!(address, encoded.to_uppercase().parse().unwrap());
+        assert!(address.matches(&pubkey));
+        assert_eq!(
+            Err(AddressError::PrefixMismatch),
+            Address::parse_for(&encoded, "main")
+        );
+
+        // the algorithm is part of the address
+        let other = PublicKey::new(bytes.into(), KeyPairAlgorithm::BLS12_381);
+        assert!(!address.matches(&other));
+
+        // a single typo is always caught
+        let mut typo = encoded.clone().into_bytes();
+        typo[10] = if typo[10] == b'q' { b'p' } else { b'q' };
+        assert_eq!(
+            Err(AddressError::InvalidChecksum),
+            Address::parse(std::str::from_utf8(&typo).unwrap())
+        );
+        assert_eq!(
+            Err(AddressError::MixedCase),
+            Address::parse(&encoded.replacen('t', "T", 1))
+        );
+        assert_eq!(Err(AddressError::InvalidPrefix), pubkey.address("Test"));
+
+        let json = serde_json::to_string(&address).unwrap();
+        assert_eq!(format!("\"{encoded}\""), json);
+        assert_eq!(address, serde_json::from_str::<Address>(&json).unwrap());
+        assert!(serde_json::from_str::<Address>(&json.replacen('y', "z", 1)).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Verification of many signatures at once.
+//!
+//! Every signature is checked with [`PublicKey::verify`], in parallel across threads, so the
+//! reported indices always agree with single verification.
+//!
+//! Ed25519 batch verification, such as `ed25519_dalek::verify_batch`, is deliberately not used. It
+//! checks a random linear combination of the verification equations, which can accept a signature
+//! with a small-order public key or `R` that single verification rejects. A block would then verify
+//! as a whole but not record by record.
+
+use rayon::prelude::*;
+
+use super::{DigitalSignature, PublicKey};
+
+/// A message together with its signature and the public key expected to verify it.
+#[derive(Debug, Clone, Copy)]
+pub struct SignedMessage<'a> {
+    pub msg: &'a [u8],
+    pub signature: &'a DigitalSignature,
+    pub signer: &'a PublicKey,
+}
+
+impl<'a> SignedMessage<'a> {
+    pub fn new(msg: &'a [u8], signature: &'a DigitalSignature, signer: &'a PublicKey) -> Self {
+        Self {
+            msg,
+            signature,
+            signer,
+        }
+    }
+
+    fn verify(&self) -> bool {
+        self.signer.verify(self.msg, self.signature).is_ok()
+    }
+}
+
+/// Verifies every message in `batch` and returns the indices of those that failed, in ascending order.
+///
+/// # Examples
+///
+/// ```
+/// use blockify::batch::{verify_batch, SignedMessage};
+///
+/// let keypair = blockify::generate_ed25519_keypair();
+/// let signature = keypair.sign(b"hello").unwrap();
+/// let pubkey = keypair.into_public_key();
+///
+/// let batch = [
+///     SignedMessage::new(b"hello", &signature, &pubkey),
+///     SignedMessage::new(b"goodbye", &signature, &pubkey),
+/// ];
+/// assert_eq!(vec![1], verify_batch(&batch));
+/// ```
+pub fn verify_batch(batch: &[SignedMessage]) -> Vec<usize> {
+    // collecting a parallel iterator keeps the order of its items, so the indices stay sorted
+    (0..batch.len())
+        .into_par_iter()
+        .filter(|&index| !batch[index].verify())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_batch, SignedMessage};
+    use crate::{
+        generate_ecdsa_keypair, generate_ed25519_keypair, DigitalSignature, EcdsaSigningAlgorithm,
+        KeyPairAlgorithm, PublicKey,
+    };
+
+    #[test]
+    fn batch_test() {
+        let keypairs = (0..150)
+            .map(|i| match i % 3 {
+                0 => generate_ecdsa_keypair(EcdsaSigningAlgorithm::P256_SHA256_ASN1),
+                _ => generate_ed25519_keypair(),
+            })
+            .collect::<Vec<_>>();
+        let messages = (0..150)
+            .map(|i| format!("message {i}").into_bytes())
+            .collect::<Vec<_>>();
+        let mut signatures = keypairs
+            .iter()
+            .zip(&messages)
+            .map(|(keypair, msg)| keypair.sign(msg).unwrap())
+            .collect::<Vec<_>>();
+        let pubkeys = keypairs
+            .into_iter()
+            .map(|keypair| keypair.into_public_key())
+            .collect::<Vec<_>>();
+
+        let failed = |signatures: &[crate::DigitalSignature]| {
+            let batch = (0..150)
+                .map(|i| SignedMessage::new(&messages[i], &signatures[i], &pubkeys[i]))
+                .collect::<Vec<_>>();
+            verify_batch(&batch)
+        };
+        assert!(failed(&signatures).is_empty());
+
+        // two ECDSA and one Ed25519 signature over the wrong message, plus a malformed one
+        signatures.swap(3, 6);
+        signatures[100] = signatures[101].clone();
+        signatures[149] = vec![0; 3].into();
+        assert_eq!(vec![3, 6, 100, 149], failed(&signatures));
+    }
+
+    #[test]
+    fn small_order_test() {
+        // a public key of order 4, with R the base point and S = 1, satisfies the verification
+        // equation up to a small-order point, which a randomized batch check may miss
+        let key = PublicKey::new(Box::new([0; 32]), KeyPairAlgorithm::ED25519);
+        let mut signature = [0x66; 64];
+        signature[0] = 0x58;
+        signature[32..].copy_from_slice(&[0; 32]);
+        signature[32] = 1;
+        let signature = DigitalSignature::from(signature.to_vec());
+
+        let keypair = generate_ed25519_keypair();
+        let messages = (0..32)
+            .map(|i| format!("message {i}").into_bytes())
+            .collect::<Vec<_>>();
+        let signatures = messages
+            .iter()
+            .map(|msg| keypair.sign(msg).unwrap())
+            .collect::<Vec<_>>();
+        let pubkey = keypair.into_public_key();
+
+        let forged = (0..32)
+            .filter(|&i| key.verify(&messages[i], &signature).is_err())
+            .collect::<Vec<_>>();
+        assert!(!forged.is_empty());
+        for i in forged {
+            let mut batch = (0..32)
+                .map(|j| SignedMessage::new(&messages[j], &signatures[j], &pubkey))
+                .collect::<Vec<_>>();
+            batch[i] = SignedMessage::new(&messages[i], &signature, &key);
+            assert_eq!(vec![i], verify_batch(&batch));
+        }
+    }
+}
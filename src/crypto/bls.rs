@@ -0,0 +1,365 @@
+//! BLS signatures over the BLS12-381 curve.
+//!
+//! `KeyPairAlgorithm::BLS12_381` keys sign like any other key, but their signatures can also be
+//! aggregated into a single 96-byte signature:
+//!
+//! * [`verify_aggregate`] checks an aggregate of signatures over different messages, such as the
+//!   signatures of all the records in a block.
+//! * [`verify_aggregate_same_message`] checks an aggregate of signatures over one message, such as
+//!   a block header co-signed by a set of validators.
+//!
+//! Public keys are 48-byte compressed G1 points and signatures 96-byte compressed G2 points, as in
+//! the message augmentation ciphersuite of the IRTF BLS signature draft: every signature covers the
+//! public key of its signer followed by the message. Two signers therefore never sign the same
+//! message, so no signer can choose a public key that cancels out the keys of others, and
+//! aggregates are sound without proofs of possession, even when several signers sign the same
+//! content.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{bls, generate_bls_keypair, Signer, SigningDomain};
+//!
+//! let validators = (0..3).map(|_| generate_bls_keypair()).collect::<Vec<_>>();
+//! let keys = validators.iter().map(|v| v.public_key()).collect::<Vec<_>>();
+//!
+//! let header = SigningDomain::new("mainnet", "Block", "header").message(b"block hash");
+//! let signatures = validators
+//!     .iter()
+//!     .map(|v| v.sign(&header).unwrap())
+//!     .collect::<Vec<_>>();
+//! let aggregate = bls::aggregate(&signatures).unwrap();
+//!
+//! assert_eq!(96, aggregate.buffer().len());
+//! assert!(bls::verify_aggregate_same_message(&header, &keys, &aggregate).is_ok());
+//! assert!(bls::verify_aggregate_same_message(&header, &keys[1..], &aggregate).is_err());
+//! ```
+
+use blst::{
+    min_pk::{AggregateSignature, PublicKey as BlsPublicKey, SecretKey, Signature},
+    BLST_ERROR,
+};
+
+use super::{
+    AuthKeyPair, DigitalSignature, KeyError, KeyPairAlgorithm, PublicKey, SigningError,
+    VerificationError,
+};
+
+/// The domain separation tag of signatures.
+pub const SIGNATURE_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_AUG_";
+
+/// Derives a BLS12-381 `AuthKeyPair` from at least 32 bytes of secret key material.
+///
+/// The same material always derives the same key pair. The private key is stored as the 32-byte
+/// big-endian secret scalar and the public key as the compressed G1 point.
+///
+/// # Errors
+///
+/// * `KeyRejected` - If `ikm` is shorter than 32 bytes.
+pub fn bls_keypair_from_ikm(ikm: &[u8]) -> Result<AuthKeyPair, KeyError> {
+    let secret = SecretKey::key_gen(ikm, &[]).map_err(|_| KeyError::KeyRejected)?;
+    Ok(keypair(&secret))
+}
+
+/// Builds a BLS12-381 `AuthKeyPair` from its 32-byte big-endian secret scalar.
+///
+/// # Errors
+///
+/// * `KeyRejected` - If `secret` is not a valid non-zero scalar.
+pub fn bls_keypair_from_secret(secret: &[u8]) -> Result<AuthKeyPair, KeyError> {
+    Ok(keypair(&secret_key(secret)?))
+}
+
+fn keypair(secret: &SecretKey) -> AuthKeyPair {
+    AuthKeyPair::new(
+        secret.to_bytes().into(),
+        secret.sk_to_pk().compress().into(),
+        KeyPairAlgorithm::BLS12_381,
+    )
+}
+
+fn secret_key(bytes: &[u8]) -> Result<SecretKey, KeyError> {
+    SecretKey::from_bytes(bytes).map_err(|_| KeyError::KeyRejected)
+}
+
+/// Parses a compressed public key, rejecting the identity and points outside the subgroup
+fn public_key(bytes: &[u8]) -> Result<BlsPublicKey, KeyError> {
+    BlsPublicKey::key_validate(bytes).map_err(|_| KeyError::KeyRejected)
+}
+
+fn signature(signature: &DigitalSignature) -> Result<Signature, VerificationError> {
+    Signature::sig_validate(signature.buffer(), false)
+        .map_err(|_| VerificationError::InvalidSignature)
+}
+
+/// Parses the public keys of `signers`, which must all be BLS12-381 keys
+fn signer_keys(signers: &[PublicKey]) -> Result<Vec<BlsPublicKey>, VerificationError> {
+    signers
+        .iter()
+        .map(|signer| match signer.algorithm() {
+            KeyPairAlgorithm::BLS12_381 => {
+                public_key(signer.as_bytes()).map_err(|_| VerificationError::BadKey)
+            }
+            _ => Err(VerificationError::BadKey),
+        })
+        .collect()
+}
+
+/// Prefixes every message with the public key of its signer, as the signature covers both
+fn augment(messages: &[&[u8]], signers: &[PublicKey]) -> Vec<Vec<u8>> {
+    messages
+        .iter()
+        .zip(signers)
+        .map(|(msg, signer)| [signer.as_bytes(), msg].concat())
+        .collect()
+}
+
+fn check(result: BLST_ERROR) -> Result<(), VerificationError> {
+    match result {
+        BLST_ERROR::BLST_SUCCESS => Ok(()),
+        BLST_ERROR::BLST_VERIFY_FAIL => Err(VerificationError::NoMatch),
+        BLST_ERROR::BLST_BAD_ENCODING | BLST_ERROR::BLST_POINT_NOT_ON_CURVE => {
+            Err(VerificationError::InvalidSignature)
+        }
+        BLST_ERROR::BLST_PK_IS_INFINITY | BLST_ERROR::BLST_POINT_NOT_IN_GROUP => {
+            Err(VerificationError::BadKey)
+        }
+        _ => Err(VerificationError::Unspecified),
+    }
+}
+
+/// Checks that `bytes` is a valid public key
+pub(crate) fn check_public_key(bytes: &[u8]) -> Result<(), KeyError> {
+    public_key(bytes).map(|_| ())
+}
+
+pub(crate) fn sign(msg: &[u8], private_key: &[u8]) -> Result<DigitalSignature, SigningError> {
+    let secret = secret_key(private_key).map_err(|_| SigningError::KeyRejected)?;
+    let signer = secret.sk_to_pk().compress();
+    Ok(secret
+        .sign(msg, SIGNATURE_DST, &signer)
+        .compress()
+        .to_vec()
+        .into())
+}
+
+pub(crate) fn verify(
+    msg: &[u8],
+    signature: &DigitalSignature,
+    signer: &[u8],
+) -> Result<(), VerificationError> {
+    let key = public_key(signer).map_err(|_| VerificationError::BadKey)?;
+    let signature = self::signature(signature)?;
+    check(signature.verify(false, msg, SIGNATURE_DST, signer, &key, false))
+}
+
+/// Aggregates BLS12-381 signatures into a single signature of the same size.
+///
+/// # Errors
+///
+/// * `InvalidSignature` - If `signatures` is empty or one of them is not a valid signature.
+pub fn aggregate(signatures: &[DigitalSignature]) -> Result<DigitalSignature, VerificationError> {
+    let signatures = signatures
+        .iter()
+        .map(signature)
+        .collect::<Result<Vec<_>, _>>()?;
+    let signatures = signatures.iter().collect::<Vec<_>>();
+    let aggregate = AggregateSignature::aggregate(&signatures, false)
+        .map_err(|_| VerificationError::InvalidSignature)?;
+    Ok(aggregate.to_signature().compress().to_vec().into())
+}
+
+/// Verifies an aggregate of signatures where the `i`-th signer signed the `i`-th message.
+///
+/// The messages may repeat, since every signature also covers the key of its signer.
+///
+/// # Errors
+///
+/// * `BadKey` - If one of the signers is not a valid BLS12-381 key.
+/// * `InvalidSignature` - If `aggregate` is not a valid signature.
+/// * `NoMatch` - If the aggregate does not match, or there are no messages or not one per signer.
+pub fn verify_aggregate(
+    messages: &[&[u8]],
+    signers: &[PublicKey],
+    aggregate: &DigitalSignature,
+) -> Result<(), VerificationError> {
+    if messages.len() != signers.len() {
+        return Err(VerificationError::NoMatch);
+    }
+    let keys = signer_keys(signers)?;
+    let keys = keys.iter().collect::<Vec<_>>();
+    let aggregate = signature(aggregate)?;
+    let messages = augment(messages, signers);
+    let messages = messages.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    check(aggregate.aggregate_verify(false, &messages, SIGNATURE_DST, &keys, false))
+}
+
+/// Verifies an aggregate of signatures where all `signers` signed the same `msg`.
+///
+/// This is [`verify_aggregate`] with `msg` for every signer.
+///
+/// # Errors
+///
+/// * `BadKey` - If one of the signers is not a valid BLS12-381 key.
+/// * `InvalidSignature` - If `aggregate` is not a valid signature.
+/// * `NoMatch` - If there are no signers or the aggregate does not match.
+pub fn verify_aggregate_same_message(
+    msg: &[u8],
+    signers: &[PublicKey],
+    aggregate: &DigitalSignature,
+) -> Result<(), VerificationError> {
+    let messages = vec![msg; signers.len()];
+    verify_aggregate(&messages, signers, aggregate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{generate_bls_keypair, Signer};
+
+    #[test]
+    fn keypair_test() {
+        let keypair = bls_keypair_from_ikm(&[7; 32]).unwrap();
+        assert_eq!(32, keypair.private_key_bytes().len());
+        assert_eq!(48, keypair.public_key_bytes().len());
+        assert_eq!(
+            keypair.public_key_bytes(),
+            bls_keypair_from_ikm(&[7; 32]).unwrap().public_key_bytes()
+        );
+        assert_eq!(
+            keypair.public_key_bytes(),
+            bls_keypair_from_secret(keypair.private_key_bytes())
+                .unwrap()
+                .public_key_bytes()
+        );
+        assert_eq!(
+            Err(KeyError::KeyRejected),
+            bls_keypair_from_ikm(&[7; 31]).map(|_| ())
+        );
+        assert_eq!(
+            Err(KeyError::KeyRejected),
+            bls_keypair_from_secret(&[0; 32]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn sign_test() {
+        let keypair = generate_bls_keypair();
+        let signature = keypair.sign(b"hi").unwrap();
+        assert_eq!(96, signature.buffer().len());
+
+        let pubkey = keypair.public_key();
+        assert!(pubkey.verify(b"hi", &signature).is_ok());
+        assert_eq!(
+            Err(VerificationError::NoMatch),
+            pubkey.verify(b"ho", &signature)
+        );
+        assert_eq!(
+            Err(VerificationError::NoMatch),
+            generate_bls_keypair()
+                .public_key()
+                .verify(b"hi", &signature)
+        );
+        assert_eq!(
+            Err(VerificationError::InvalidSignature),
+            pubkey.verify(b"hi", &DigitalSignature::from(vec![0; 96]))
+        );
+    }
+
+    #[test]
+    fn aggregate_test() {
+        let keypairs = (0..4).map(|_| generate_bls_keypair()).collect::<Vec<_>>();
+        let keys = keypairs.iter().map(|k| k.public_key()).collect::<Vec<_>>();
+        let messages = (0..4u8).map(|i| vec![i; 10]).collect::<Vec<_>>();
+        let messages = messages.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        let signatures = keypairs
+            .iter()
+            .zip(&messages)
+            .map(|(keypair, msg)| keypair.sign(msg).unwrap())
+            .collect::<Vec<_>>();
+        let aggregate = aggregate(&signatures).unwrap();
+        assert_eq!(96, aggregate.buffer().len());
+        assert!(verify_aggregate(&messages, &keys, &aggregate).is_ok());
+
+        // every signature still verifies on its own
+        for ((key, msg), signature) in keys.iter().zip(&messages).zip(&signatures) {
+            assert!(key.verify(msg, signature).is_ok());
+        }
+
+        let mut swapped = messages.clone();
+        swapped.swap(0, 1);
+        assert!(verify_aggregate(&swapped, &keys, &aggregate).is_err());
+        assert!(verify_aggregate(&messages[1..], &keys[1..], &aggregate).is_err());
+        assert!(verify_aggregate(&messages, &keys[1..], &aggregate).is_err());
+        assert!(super::aggregate(&[]).is_err());
+
+        let ed25519 = crate::generate_ed25519_keypair().public_key();
+        let mut mixed = keys.clone();
+        mixed[0] = ed25519;
+        assert_eq!(
+            Err(VerificationError::BadKey),
+            verify_aggregate(&messages, &mixed, &aggregate)
+        );
+    }
+
+    #[test]
+    fn same_message_test() {
+        let keypairs = (0..5).map(|_| generate_bls_keypair()).collect::<Vec<_>>();
+        let keys = keypairs.iter().map(|k| k.public_key()).collect::<Vec<_>>();
+        let signatures = keypairs
+            .iter()
+            .map(|keypair| keypair.sign(b"header").unwrap())
+            .collect::<Vec<_>>();
+        let aggregate = aggregate(&signatures).unwrap();
+
+        assert!(verify_aggregate_same_message(b"header", &keys, &aggregate).is_ok());
+        assert!(verify_aggregate_same_message(b"headers", &keys, &aggregate).is_err());
+        assert!(verify_aggregate_same_message(b"header", &keys[..4], &aggregate).is_err());
+        assert!(verify_aggregate_same_message(b"header", &[], &aggregate).is_err());
+    }
+
+    #[test]
+    fn rogue_key_test() {
+        use blst::min_pk::AggregatePublicKey;
+
+        let honest = generate_bls_keypair().public_key();
+        let attacker = SecretKey::key_gen(&[9; 32], &[]).unwrap();
+
+        // the rogue key is the attacker's key minus the honest one, so that the two add up to a key
+        // the attacker owns
+        let mut rogue = AggregatePublicKey::from_public_key(&attacker.sk_to_pk());
+        rogue.sub_aggregate(&AggregatePublicKey::from_public_key(
+            &BlsPublicKey::from_bytes(honest.as_bytes()).unwrap(),
+        ));
+        let rogue = rogue.to_public_key();
+        let keys = [
+            honest,
+            PublicKey::new(rogue.compress().into(), KeyPairAlgorithm::BLS12_381),
+        ];
+
+        // without the keys in the messages the attacker could forge an aggregate including the
+        // honest signer, with messages augmented by the keys they cannot
+        let forged = attacker.sign(b"vote", SIGNATURE_DST, &[]);
+        let plain = [b"vote".as_slice(); 2];
+        let points = keys
+            .iter()
+            .map(|key| BlsPublicKey::from_bytes(key.as_bytes()).unwrap())
+            .collect::<Vec<_>>();
+        let points = points.iter().collect::<Vec<_>>();
+        assert_eq!(
+            BLST_ERROR::BLST_SUCCESS,
+            forged.aggregate_verify(false, &plain, SIGNATURE_DST, &points, false)
+        );
+
+        let forged = DigitalSignature::from(forged.compress().to_vec());
+        assert!(verify_aggregate_same_message(b"vote", &keys, &forged).is_err());
+        for aug in [keys[0].as_bytes(), keys[1].as_bytes()] {
+            let forged = attacker
+                .sign(b"vote", SIGNATURE_DST, aug)
+                .compress()
+                .to_vec();
+            assert!(verify_aggregate(&plain, &keys, &forged.into()).is_err());
+        }
+    }
+}
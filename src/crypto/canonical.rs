@@ -0,0 +1,576 @@
+//! The canonical encoding of values that are hashed or signed.
+//!
+//! Signatures and hashes are computed over bytes, so the encoding that turns a value into those bytes
+//! is part of the protocol. This module defines that encoding independently of any serialization
+//! crate, so that another implementation, or a later version of this one, produces identical bytes for
+//! the same value.
+//!
+//! # Format, version 1
+//!
+//! An encoding starts with the version byte [`VERSION`], followed by the encoding of the value.
+//! Values are encoded through their `serde::Serialize` implementation as follows. All integers are
+//! big-endian, and every length or count is a `u64`.
+//!
+//! | serde type                     | encoding                                                          |
+//! |--------------------------------|-------------------------------------------------------------------|
+//! | `bool`                         | one byte, `0x00` or `0x01`                                        |
+//! | `u8` ... `u128`                | the integer in its own width                                      |
+//! | `i8` ... `i128`                | the two's complement integer in its own width                     |
+//! | `f32`, `f64`                   | the IEEE 754 bits, with every NaN as the quiet NaN and `-0.0` as `0.0` |
+//! | `char`                         | the scalar value as a `u32`                                       |
+//! | string, bytes                  | the byte length, then the bytes (UTF-8 for strings)               |
+//! | `None`, `Some(v)`              | `0x00`, or `0x01` then `v`                                        |
+//! | unit, unit struct              | nothing                                                           |
+//! | newtype struct                 | the inner value                                                   |
+//! | sequence                       | the element count, then the elements                              |
+//! | tuple, tuple struct            | the elements                                                      |
+//! | enum variant                   | the variant index as a `u32`, then its content encoded as above   |
+//! | struct, struct variant content | the field count, then each field name (as a string) and value, sorted by name |
+//! | map                            | the entry count, then each key and value, sorted by encoded key   |
+//!
+//! Fields skipped by serde are not encoded. Sorting struct fields by name means that reordering the
+//! fields of a struct does not change its encoding, while renaming one does. Maps with two equal
+//! encoded keys cannot be encoded.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::canonical;
+//!
+//! let bytes = canonical::encode(&(7u16, "hi")).unwrap();
+//! assert_eq!(vec![1, 0, 7, 0, 0, 0, 0, 0, 0, 0, 2, b'h', b'i'], bytes);
+//! ```
+
+use serde::{ser, Serialize};
+
+use crate::error::SerdeError;
+
+/// The version of the encoding, written as the first byte of every encoding.
+pub const VERSION: u8 = 1;
+
+/// Returns the canonical encoding of `value`, starting with the version byte.
+pub fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SerdeError> {
+    let mut bytes = vec![VERSION];
+    bytes.extend(encode_value(value).map_err(|_| SerdeError::SerializationError)?);
+    Ok(bytes)
+}
+
+fn encode_value<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    value.serialize(Encoder)
+}
+
+fn length(len: usize) -> [u8; 8] {
+    (len as u64).to_be_bytes()
+}
+
+fn with_length(bytes: &[u8]) -> Vec<u8> {
+    let mut out = length(bytes.len()).to_vec();
+    out.extend_from_slice(bytes);
+    out
+}
+
+#[derive(Debug)]
+struct Error(String);
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+struct Encoder;
+
+impl ser::Serializer for Encoder {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    type SerializeSeq = Seq;
+    type SerializeTuple = Seq;
+    type SerializeTupleStruct = Seq;
+    type SerializeTupleVariant = Seq;
+    type SerializeMap = Map;
+    type SerializeStruct = Struct;
+    type SerializeStructVariant = Struct;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<Vec<u8>, Error> {
+        Ok(vec![v as u8])
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Vec<u8>, Error> {
+        Ok(vec![v])
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Vec<u8>, Error> {
+        Ok(v.to_be_bytes().to_vec())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Vec<u8>, Error> {
+        let v = if v.is_nan() {
+            f32::NAN
+        } else if v == 0.0 {
+            0.0
+        } else {
+            v
+        };
+        Ok(v.to_bits().to_be_bytes().to_vec())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Vec<u8>, Error> {
+        let v = if v.is_nan() {
+            f64::NAN
+        } else if v == 0.0 {
+            0.0
+        } else {
+            v
+        };
+        Ok(v.to_bits().to_be_bytes().to_vec())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Vec<u8>, Error> {
+        Ok((v as u32).to_be_bytes().to_vec())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Vec<u8>, Error> {
+        Ok(with_length(v.as_bytes()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(with_length(v))
+    }
+
+    fn serialize_none(self) -> Result<Vec<u8>, Error> {
+        Ok(vec![0])
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, Error> {
+        let mut out = vec![1];
+        out.extend(encode_value(value)?);
+        Ok(out)
+    }
+
+    fn serialize_unit(self) -> Result<Vec<u8>, Error> {
+        Ok(vec![])
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Vec<u8>, Error> {
+        Ok(vec![])
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<Vec<u8>, Error> {
+        Ok(variant_index.to_be_bytes().to_vec())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Vec<u8>, Error> {
+        encode_value(value)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<Vec<u8>, Error> {
+        let mut out = variant_index.to_be_bytes().to_vec();
+        out.extend(encode_value(value)?);
+        Ok(out)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Seq, Error> {
+        Ok(Seq::new(vec![], true))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Seq, Error> {
+        Ok(Seq::new(vec![], false))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Seq, Error> {
+        Ok(Seq::new(vec![], false))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Seq, Error> {
+        Ok(Seq::new(variant_index.to_be_bytes().to_vec(), false))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Map, Error> {
+        Ok(Map {
+            entries: vec![],
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Struct, Error> {
+        Ok(Struct::new(vec![]))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Struct, Error> {
+        Ok(Struct::new(variant_index.to_be_bytes().to_vec()))
+    }
+}
+
+/// Sequences, tuples and tuple variants; only sequences are prefixed by their element count
+struct Seq {
+    header: Vec<u8>,
+    counted: bool,
+    count: usize,
+    elements: Vec<u8>,
+}
+
+impl Seq {
+    fn new(header: Vec<u8>, counted: bool) -> Self {
+        Self {
+            header,
+            counted,
+            count: 0,
+            elements: vec![],
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.elements.extend(encode_value(value)?);
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let mut out = self.header;
+        if self.counted {
+            out.extend(length(self.count));
+        }
+        out.extend(self.elements);
+        out
+    }
+}
+
+impl ser::SerializeSeq for Seq {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTuple for Seq {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleStruct for Seq {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeTupleVariant for Seq {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        Ok(self.finish())
+    }
+}
+
+/// Maps, whose entries are sorted by their encoded keys once all of them are known
+struct Map {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    key: Option<Vec<u8>>,
+}
+
+impl ser::SerializeMap for Map {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(encode_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error("map value without a key".to_owned()))?;
+        self.entries.push((key, encode_value(value)?));
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<Vec<u8>, Error> {
+        self.entries.sort_by(|a, b| a.0.cmp(&b.0));
+        if self.entries.windows(2).any(|pair| pair[0].0 == pair[1].0) {
+            return Err(Error("duplicate map key".to_owned()));
+        }
+        let mut out = length(self.entries.len()).to_vec();
+        for (key, value) in self.entries {
+            out.extend(key);
+            out.extend(value);
+        }
+        Ok(out)
+    }
+}
+
+/// Structs and struct variants, whose fields are sorted by name
+struct Struct {
+    header: Vec<u8>,
+    fields: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl Struct {
+    fn new(header: Vec<u8>) -> Self {
+        Self {
+            header,
+            fields: vec![],
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.fields.push((key, encode_value(value)?));
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.fields.sort_by(|a, b| a.0.cmp(b.0));
+        let mut out = self.header;
+        out.extend(length(self.fields.len()));
+        for (name, value) in self.fields {
+            out.extend(with_length(name.as_bytes()));
+            out.extend(value);
+        }
+        out
+    }
+}
+
+impl ser::SerializeStruct for Struct {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        Ok(self.finish())
+    }
+}
+
+impl ser::SerializeStructVariant for Struct {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        Ok(self.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use serde::Serialize;
+
+    use super::encode;
+
+    #[derive(Serialize)]
+    struct Point {
+        y: u8,
+        x: u8,
+        #[serde(skip)]
+        #[allow(dead_code)]
+        label: String,
+    }
+
+    #[derive(Serialize)]
+    struct ReorderedPoint {
+        x: u8,
+        y: u8,
+    }
+
+    #[derive(Serialize)]
+    enum Shape {
+        Empty,
+        Circle(u8),
+        Line(u8, u8),
+        Square { side: u8 },
+    }
+
+    #[derive(Serialize)]
+    struct Meters(u32);
+
+    fn hex<T: Serialize + ?Sized>(value: &T) -> String {
+        hex::encode(encode(value).unwrap())
+    }
+
+    #[test]
+    fn vectors_test() {
+        assert_eq!("0101", hex(&true));
+        assert_eq!("0100", hex(&false));
+        assert_eq!("01ff", hex(&255u8));
+        assert_eq!("011234", hex(&0x1234u16));
+        assert_eq!("01fffffffe", hex(&-2i32));
+        assert_eq!("010000000000000001", hex(&1u64));
+        assert_eq!("01ffffffffffffffffffffffffffffffff", hex(&-1i128));
+        assert_eq!("013ff0000000000000", hex(&1.0f64));
+        assert_eq!("010000000000000000", hex(&-0.0f64));
+        assert_eq!("017ff8000000000000", hex(&-f64::NAN));
+        assert_eq!("017fc00000", hex(&f32::NAN));
+        assert_eq!("0100000041", hex(&'A'));
+        assert_eq!("0100000000000000026869", hex("hi"));
+        assert_eq!("010000000000000000", hex(""));
+        assert_eq!("0100", hex(&None::<u8>));
+        assert_eq!("010107", hex(&Some(7u8)));
+        assert_eq!("01", hex(&()));
+        assert_eq!("0100000000000000020102", hex(&vec![1u8, 2]));
+        assert_eq!("01010002", hex(&(1u8, 2u16)));
+        assert_eq!("0100000064", hex(&Meters(100)));
+        assert_eq!("0100000000", hex(&Shape::Empty));
+        assert_eq!("010000000105", hex(&Shape::Circle(5)));
+        assert_eq!("01000000020304", hex(&Shape::Line(3, 4)));
+        assert_eq!(
+            "01000000030000000000000001000000000000000473696465 09".replace(' ', ""),
+            hex(&Shape::Square { side: 9 })
+        );
+        assert_eq!(
+            "01000000000000000200000000000000017801000000000000000179 02".replace(' ', ""),
+            hex(&Point {
+                y: 2,
+                x: 1,
+                label: "ignored".to_owned()
+            })
+        );
+        assert_eq!(
+            "0100000000000000020000000000000001610200000000000000016201",
+            hex(&BTreeMap::from([("b", 1u8), ("a", 2u8)]))
+        );
+    }
+
+    #[test]
+    fn record_hash_test() {
+        use crate::record::Record;
+
+        let record = String::from("hi");
+        assert_eq!(
+            "0100000000000000026869",
+            hex::encode(record.signing_bytes().unwrap())
+        );
+        assert_eq!(
+            "384de97285ad77a5395902e42b811303e7de39f3c0feb632db5a8222935963ad",
+            record.hash().to_hex()
+        );
+    }
+
+    #[test]
+    fn independence_test() {
+        // field order in the type definition does not matter
+        let point = Point {
+            y: 2,
+            x: 1,
+            label: String::new(),
+        };
+        assert_eq!(encode(&point), encode(&ReorderedPoint { x: 1, y: 2 }));
+
+        // neither does the iteration order of a map
+        let entries = (0..100u32).map(|i| (i, i * 2)).collect::<Vec<_>>();
+        let hashed = entries.iter().copied().collect::<HashMap<_, _>>();
+        let sorted = entries.iter().copied().rev().collect::<BTreeMap<_, _>>();
+        assert_eq!(encode(&hashed), encode(&sorted));
+    }
+}
@@ -0,0 +1,177 @@
+//! The hash algorithms available for hashing records, blocks and Merkle trees.
+//!
+//! Every [`Hash`](struct@Hash) records the [`HashAlgorithm`] that produced it, so two hashes
+//! computed with different algorithms never compare equal even when their bytes happen to match. A
+//! chain picks its algorithm once, see [`crate::SqliteChain::with_hash_algorithm`], and hashes the Merkle leaves of
+//! its records with it as well, see [`crate::record::Record::hash_with`].
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::digest::HashAlgorithm;
+//!
+//! let sha = HashAlgorithm::Sha256.digest(b"abc");
+//! let blake = HashAlgorithm::Blake3.digest(b"abc");
+//!
+//! assert_eq!(HashAlgorithm::Blake3, blake.algorithm());
+//! assert_ne!(sha, blake);
+//! ```
+
+use blake2::{digest::consts::U32, Blake2b};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512_256};
+
+use super::Hash;
+
+/// A hash algorithm with a 256 bit output.
+#[allow(non_camel_case_types)]
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum HashAlgorithm {
+    /// SHA-256, as used by this crate before hash algorithms were configurable.
+    #[default]
+    Sha256,
+    /// SHA-512 truncated to 256 bits.
+    Sha512_256,
+    /// BLAKE2b with a 256 bit output.
+    Blake2b_256,
+    /// BLAKE3 with its default 256 bit output.
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// The length in bytes of the hashes produced by every algorithm.
+    pub const OUTPUT_LEN: usize = 32;
+
+    /// Every available algorithm.
+    pub const ALL: [HashAlgorithm; 4] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::Sha512_256,
+        HashAlgorithm::Blake2b_256,
+        HashAlgorithm::Blake3,
+    ];
+
+    /// Returns a new incremental hasher for this algorithm.
+    pub fn hasher(self) -> Hasher {
+        let state = match self {
+            HashAlgorithm::Sha256 => HasherState::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512_256 => HasherState::Sha512_256(Sha512_256::new()),
+            HashAlgorithm::Blake2b_256 => HasherState::Blake2b(Blake2b::new()),
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::new(blake3::Hasher::new())),
+        };
+        Hasher { state }
+    }
+
+    /// Hashes `bytes`.
+    pub fn digest<B: AsRef<[u8]>>(self, bytes: B) -> Hash {
+        self.digest_all([bytes])
+    }
+
+    /// Hashes the concatenation of all `values`.
+    pub fn digest_all<V: AsRef<[u8]>, T: IntoIterator<Item = V>>(self, values: T) -> Hash {
+        let mut hasher = self.hasher();
+        for value in values {
+            hasher.update(value);
+        }
+        hasher.finalize()
+    }
+
+    /// Returns the all-zero hash of this algorithm, used where no previous hash exists.
+    pub fn zero(self) -> Hash {
+        Hash::with_algorithm(vec![0; Self::OUTPUT_LEN].into_boxed_slice(), self)
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// An incremental hasher producing a `Hash` tagged with its algorithm.
+pub struct Hasher {
+    state: HasherState,
+}
+
+enum HasherState {
+    Sha256(Sha256),
+    Sha512_256(Sha512_256),
+    Blake2b(Blake2b<U32>),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    /// Feeds `bytes` into the hasher.
+    pub fn update<B: AsRef<[u8]>>(&mut self, bytes: B) {
+        let bytes = bytes.as_ref();
+        match &mut self.state {
+            HasherState::Sha256(hasher) => hasher.update(bytes),
+            HasherState::Sha512_256(hasher) => hasher.update(bytes),
+            HasherState::Blake2b(hasher) => hasher.update(bytes),
+            HasherState::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+        }
+    }
+
+    /// Returns the algorithm of this hasher.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self.state {
+            HasherState::Sha256(_) => HashAlgorithm::Sha256,
+            HasherState::Sha512_256(_) => HashAlgorithm::Sha512_256,
+            HasherState::Blake2b(_) => HashAlgorithm::Blake2b_256,
+            HasherState::Blake3(_) => HashAlgorithm::Blake3,
+        }
+    }
+
+    /// Completes the computation and returns the hash.
+    pub fn finalize(self) -> Hash {
+        let algorithm = self.algorithm();
+        let bytes = match self.state {
+            HasherState::Sha256(hasher) => hasher.finalize().to_vec(),
+            HasherState::Sha512_256(hasher) => hasher.finalize().to_vec(),
+            HasherState::Blake2b(hasher) => hasher.finalize().to_vec(),
+            HasherState::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+        Hash::with_algorithm(bytes.into_boxed_slice(), algorithm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashAlgorithm;
+
+    #[test]
+    fn vectors_test() {
+        let vectors = [
+            (
+                HashAlgorithm::Sha256,
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                HashAlgorithm::Sha512_256,
+                "53048e2681941ef99b2e29b76b4c7dabe4c2d0c634fc6d46e0e2f13107e7af23",
+            ),
+            (
+                HashAlgorithm::Blake2b_256,
+                "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319",
+            ),
+            (
+                HashAlgorithm::Blake3,
+                "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+            ),
+        ];
+        for (algorithm, expected) in vectors {
+            let hash = algorithm.digest(b"abc");
+            assert_eq!(expected, hash.to_hex(), "{algorithm}");
+            assert_eq!(algorithm, hash.algorithm());
+            assert_eq!(hash, algorithm.digest_all([&b"a"[..], b"bc"]));
+        }
+
+        // equal bytes under different algorithms are different hashes
+        let sha = HashAlgorithm::Sha256.zero();
+        let blake = HashAlgorithm::Blake3.zero();
+        assert_eq!(sha.as_bytes(), blake.as_bytes());
+        assert_ne!(sha, blake);
+    }
+}
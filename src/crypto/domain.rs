@@ -0,0 +1,104 @@
+//! Domain separation of record signatures.
+//!
+//! Records are never signed on their own. The signed message is the [`canonical`]
+//! encoding of a fixed tag, a [`SigningDomain`] and the record bytes, so a signature made for one
+//! chain, record type or purpose does not verify for any other. In particular a `SignedRecord` cannot
+//! be replayed on a different chain.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{domain::SigningDomain, Signer};
+//!
+//! let keypair = blockify::generate_ed25519_keypair();
+//! let domain = SigningDomain::new("mainnet", "Vote", SigningDomain::RECORD);
+//! let signature = keypair.sign(&domain.message(b"vote for 2")).unwrap();
+//!
+//! let pubkey = keypair.public_key();
+//! assert!(pubkey.verify_in(&domain, b"vote for 2", &signature).is_ok());
+//!
+//! let testnet = SigningDomain::new("testnet", "Vote", SigningDomain::RECORD);
+//! assert!(pubkey.verify_in(&testnet, b"vote for 2", &signature).is_err());
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use super::canonical;
+use crate::record::Record;
+
+/// The tag that starts every signed message, separating it from any other use of the same key.
+pub const SIGNATURE_TAG: &str = "blockify-signature";
+
+/// The context a signature is bound to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SigningDomain {
+    chain_id: String,
+    record_type: String,
+    purpose: String,
+}
+
+impl SigningDomain {
+    /// The purpose of the signature on a `SignedRecord`.
+    pub const RECORD: &'static str = "record";
+
+    /// The purpose of the partial signatures on a `MultiSignedRecord`.
+    pub const MULTISIG: &'static str = "multisig";
+
+    /// Creates a domain for signatures on `chain_id` over records of type `record_type`, made for `purpose`.
+    pub fn new(chain_id: &str, record_type: &str, purpose: &str) -> Self {
+        Self {
+            chain_id: chain_id.to_owned(),
+            record_type: record_type.to_owned(),
+            purpose: purpose.to_owned(),
+        }
+    }
+
+    /// Returns the domain of the signature on a `SignedRecord<R>` on `chain_id`.
+    pub fn record<R: Record>(chain_id: &str) -> Self {
+        Self::new(chain_id, R::TYPE_ID, Self::RECORD)
+    }
+
+    /// Returns the identifier of the chain.
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    /// Returns the identifier of the record type, see [`Record::TYPE_ID`].
+    pub fn record_type(&self) -> &str {
+        &self.record_type
+    }
+
+    /// Returns the purpose of the signature.
+    pub fn purpose(&self) -> &str {
+        &self.purpose
+    }
+
+    /// Returns the message that is actually signed for `msg` in this domain.
+    pub fn message(&self, msg: &[u8]) -> Vec<u8> {
+        let tagged = (
+            SIGNATURE_TAG,
+            &self.chain_id,
+            &self.record_type,
+            &self.purpose,
+            msg,
+        );
+        canonical::encode(&tagged).expect("strings and bytes always have a canonical encoding")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SigningDomain;
+
+    #[test]
+    fn message_test() {
+        let domain = SigningDomain::record::<String>("test");
+        assert_eq!(
+            "010000000000000012626c6f636b6966792d7369676e61747572650000000000000004746573740000000000000006537472696e6700000000000000067265636f726400000000000000026869",
+            hex::encode(domain.message(b"hi"))
+        );
+
+        let other = SigningDomain::new("test", "String", SigningDomain::MULTISIG);
+        assert_ne!(domain.message(b"hi"), other.message(b"hi"));
+    }
+}
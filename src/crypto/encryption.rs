@@ -0,0 +1,357 @@
+//! Public key encryption of data for a set of recipients.
+//!
+//! Data is sealed into an [`Envelope`]: it is encrypted once with AES-256-GCM under a random content
+//! key, and that content key is wrapped separately for every recipient. Wrapping uses X25519 key
+//! agreement between a fresh ephemeral key and the recipient's [`EncryptionPublicKey`], HKDF-SHA256
+//! to derive a key-encryption key, and AES-256-GCM again. Any recipient can open the envelope with
+//! their [`EncryptionKeyPair`], without learning who else it was sealed for beyond their public keys.
+//!
+//! Encryption keys are separate from the signing keys of an `AuthKeyPair`.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::encryption::{Envelope, EncryptionKeyPair};
+//!
+//! let alice = EncryptionKeyPair::generate();
+//! let bob = EncryptionKeyPair::generate();
+//! let eve = EncryptionKeyPair::generate();
+//!
+//! let envelope = Envelope::seal(&[alice.public_key(), bob.public_key()], b"for your eyes only", b"").unwrap();
+//!
+//! assert_eq!(b"for your eyes only".to_vec(), *envelope.open(&bob, b"").unwrap());
+//! assert!(envelope.open(&eve, b"").is_err());
+//! ```
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hkdf::{Salt, HKDF_SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use x25519_dalek::StaticSecret;
+use zeroize::Zeroizing;
+
+use crate::error::SerdeError;
+
+const CONTENT_KEY_LEN: usize = 32;
+const KEY_WRAP_INFO: &[u8] = b"blockify-envelope-key-wrap";
+
+/// An error that can occur while encrypting or decrypting an envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionError {
+    /// An envelope must be sealed for at least one recipient.
+    NoRecipients,
+    /// A recipient key is a low order point, which would make the shared secret predictable.
+    InvalidKey,
+    /// The key is not one of the recipients of the envelope.
+    NotRecipient,
+    /// The envelope has been tampered with or does not match the associated data.
+    DecryptionFailed,
+    /// The data was encrypted in a format version this crate does not understand.
+    UnsupportedVersion(u32),
+    SerdeError(SerdeError),
+    Unspecified,
+}
+
+crate::impl_display_error!(EncryptionError);
+
+impl From<ring::error::Unspecified> for EncryptionError {
+    fn from(_: ring::error::Unspecified) -> Self {
+        EncryptionError::Unspecified
+    }
+}
+
+impl From<SerdeError> for EncryptionError {
+    fn from(value: SerdeError) -> Self {
+        EncryptionError::SerdeError(value)
+    }
+}
+
+/// The public half of an X25519 encryption key, to which data can be encrypted.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EncryptionPublicKey {
+    bytes: [u8; 32],
+}
+
+impl EncryptionPublicKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.bytes
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.bytes)
+    }
+}
+
+impl From<&StaticSecret> for EncryptionPublicKey {
+    fn from(value: &StaticSecret) -> Self {
+        Self::new(x25519_dalek::PublicKey::from(value).to_bytes())
+    }
+}
+
+/// An X25519 key pair for decrypting data sent to its public key.
+///
+/// The secret key is wiped from memory when the key pair is dropped and is never shown by its
+/// `Debug` implementation.
+#[derive(Clone)]
+pub struct EncryptionKeyPair {
+    secret: StaticSecret,
+    public_key: EncryptionPublicKey,
+}
+
+impl EncryptionKeyPair {
+    /// Generates a new random key pair.
+    pub fn generate() -> Self {
+        let bytes = Zeroizing::new(crate::random_bytes::<32>());
+        Self::from_secret_bytes(*bytes)
+    }
+
+    /// Creates a key pair from the 32 bytes of an X25519 secret key.
+    pub fn from_secret_bytes(bytes: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(bytes);
+        let public_key = EncryptionPublicKey::from(&secret);
+        Self { secret, public_key }
+    }
+
+    /// Returns the public key of this key pair.
+    pub fn public_key(&self) -> EncryptionPublicKey {
+        self.public_key
+    }
+
+    /// Returns the bytes of the secret key.
+    pub fn secret_bytes(&self) -> &[u8; 32] {
+        self.secret.as_bytes()
+    }
+}
+
+impl std::fmt::Debug for EncryptionKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionKeyPair")
+            .field("public_key", &self.public_key)
+            .field("secret", &"<redacted>")
+            .finish()
+    }
+}
+
+/// The content key of an envelope, encrypted for one recipient.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    recipient: EncryptionPublicKey,
+    ciphertext: Vec<u8>,
+}
+
+impl WrappedKey {
+    /// Returns the public key of the recipient.
+    pub fn recipient(&self) -> &EncryptionPublicKey {
+        &self.recipient
+    }
+}
+
+/// Data encrypted for a set of recipients.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    ephemeral: EncryptionPublicKey,
+    recipients: Vec<WrappedKey>,
+    nonce: [u8; NONCE_LEN],
+    ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Encrypts `plaintext` for every key in `recipients`.
+    ///
+    /// The `aad` is authenticated but not encrypted, and must be given again to open the envelope.
+    pub fn seal(
+        recipients: &[EncryptionPublicKey],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Self, EncryptionError> {
+        if recipients.is_empty() {
+            return Err(EncryptionError::NoRecipients);
+        }
+        let rng = SystemRandom::new();
+        let mut content_key = Zeroizing::new([0; CONTENT_KEY_LEN]);
+        rng.fill(&mut content_key[..])?;
+
+        // one ephemeral key per envelope, so every key-encryption key below is used exactly once
+        let ephemeral = EncryptionKeyPair::generate();
+        let recipients = recipients
+            .iter()
+            .map(|recipient| {
+                let key =
+                    key_encryption_key(&ephemeral, recipient, &ephemeral.public_key, recipient)?;
+                let mut ciphertext = content_key.to_vec();
+                key.seal_in_place_append_tag(
+                    Nonce::assume_unique_for_key([0; NONCE_LEN]),
+                    Aad::empty(),
+                    &mut ciphertext,
+                )?;
+                Ok(WrappedKey {
+                    recipient: *recipient,
+                    ciphertext,
+                })
+            })
+            .collect::<Result<Vec<_>, EncryptionError>>()?;
+
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill(&mut nonce)?;
+        let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &content_key[..])?);
+        let mut ciphertext = plaintext.to_vec();
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut ciphertext,
+        )?;
+
+        Ok(Self {
+            ephemeral: ephemeral.public_key,
+            recipients,
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Decrypts the envelope with the key pair of one of its recipients.
+    ///
+    /// # Errors
+    ///
+    /// * `NotRecipient` - If the envelope was not sealed for `keypair`.
+    /// * `DecryptionFailed` - If the envelope or `aad` has been altered.
+    pub fn open(
+        &self,
+        keypair: &EncryptionKeyPair,
+        aad: &[u8],
+    ) -> Result<Zeroizing<Vec<u8>>, EncryptionError> {
+        let wrapped = self
+            .recipients
+            .iter()
+            .find(|wrapped| wrapped.recipient == keypair.public_key)
+            .ok_or(EncryptionError::NotRecipient)?;
+
+        let key = key_encryption_key(
+            keypair,
+            &self.ephemeral,
+            &self.ephemeral,
+            &keypair.public_key,
+        )?;
+        let mut content_key = Zeroizing::new(wrapped.ciphertext.clone());
+        let content_key = key
+            .open_in_place(
+                Nonce::assume_unique_for_key([0; NONCE_LEN]),
+                Aad::empty(),
+                &mut content_key,
+            )
+            .map_err(|_| EncryptionError::DecryptionFailed)?;
+
+        let key = LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, content_key)
+                .map_err(|_| EncryptionError::DecryptionFailed)?,
+        );
+        let mut buffer = Zeroizing::new(self.ciphertext.clone());
+        let len = key
+            .open_in_place(
+                Nonce::assume_unique_for_key(self.nonce),
+                Aad::from(aad),
+                &mut buffer,
+            )
+            .map_err(|_| EncryptionError::DecryptionFailed)?
+            .len();
+        buffer.truncate(len);
+        Ok(buffer)
+    }
+
+    /// Returns the public keys of the recipients.
+    pub fn recipients(&self) -> impl Iterator<Item = &EncryptionPublicKey> {
+        self.recipients.iter().map(WrappedKey::recipient)
+    }
+
+    /// Returns `true` if the envelope was sealed for `recipient`.
+    pub fn is_recipient(&self, recipient: &EncryptionPublicKey) -> bool {
+        self.recipients().any(|key| key == recipient)
+    }
+}
+
+/// Derives the key that wraps the content key for `recipient`, on either side of the key agreement.
+///
+/// When sealing, `own` is the ephemeral key pair and `peer` the recipient. When opening, `own` is
+/// the recipient's key pair and `peer` the ephemeral key.
+fn key_encryption_key(
+    own: &EncryptionKeyPair,
+    peer: &EncryptionPublicKey,
+    ephemeral: &EncryptionPublicKey,
+    recipient: &EncryptionPublicKey,
+) -> Result<LessSafeKey, EncryptionError> {
+    let shared = own
+        .secret
+        .diffie_hellman(&x25519_dalek::PublicKey::from(peer.bytes));
+    if !shared.was_contributory() {
+        return Err(EncryptionError::InvalidKey);
+    }
+
+    let mut salt = ephemeral.bytes.to_vec();
+    salt.extend_from_slice(&recipient.bytes);
+    let prk = Salt::new(HKDF_SHA256, &salt).extract(shared.as_bytes());
+    let key = prk.expand(&[KEY_WRAP_INFO], &AES_256_GCM)?;
+    Ok(LessSafeKey::new(UnboundKey::from(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EncryptionError, EncryptionKeyPair, EncryptionPublicKey, Envelope};
+
+    #[test]
+    fn envelope_test() {
+        let recipients = (0..3)
+            .map(|_| EncryptionKeyPair::generate())
+            .collect::<Vec<_>>();
+        let public_keys = recipients
+            .iter()
+            .map(EncryptionKeyPair::public_key)
+            .collect::<Vec<_>>();
+
+        let envelope = Envelope::seal(&public_keys, b"confidential", b"header").unwrap();
+        for recipient in &recipients {
+            assert!(envelope.is_recipient(&recipient.public_key()));
+            let plaintext = envelope.open(recipient, b"header").unwrap();
+            assert_eq!(b"confidential", &plaintext[..]);
+        }
+        assert!(!envelope
+            .ciphertext
+            .windows(12)
+            .any(|w| w == b"confidential"));
+
+        let outsider = EncryptionKeyPair::generate();
+        assert_eq!(
+            Err(EncryptionError::NotRecipient),
+            envelope.open(&outsider, b"header")
+        );
+        assert_eq!(
+            Err(EncryptionError::DecryptionFailed),
+            envelope.open(&recipients[0], b"other header")
+        );
+
+        let mut tampered = envelope.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert_eq!(
+            Err(EncryptionError::DecryptionFailed),
+            tampered.open(&recipients[1], b"header")
+        );
+
+        // a key restored from its secret bytes opens the envelope too
+        let restored = EncryptionKeyPair::from_secret_bytes(*recipients[2].secret_bytes());
+        assert!(envelope.open(&restored, b"header").is_ok());
+
+        assert_eq!(
+            Err(EncryptionError::NoRecipients),
+            Envelope::seal(&[], b"", b"").map(|_| ())
+        );
+        assert_eq!(
+            Err(EncryptionError::InvalidKey),
+            Envelope::seal(&[EncryptionPublicKey::new([0; 32])], b"", b"").map(|_| ())
+        );
+    }
+}
@@ -0,0 +1,562 @@
+//! Hierarchical deterministic derivation of `AuthKeyPair`s from a single seed.
+//!
+//! Keys are derived with SLIP-10: Ed25519 keys support hardened derivation only, while ECDSA keys
+//! over P-256 follow BIP32 and support both hardened and normal children. A [`Seed`] can be created
+//! from raw bytes or from a BIP39 [`Mnemonic`], so that a single phrase backs up every derived key.
+//!
+//! SLIP-10 only defines derivation for Ed25519, P-256 and secp256k1, and this crate cannot sign
+//! with secp256k1 keys. Deriving ECDSA keys over P-384, RSA keys or BLS keys fails with
+//! [`DerivationError::UnsupportedAlgorithm`]; such keys have to be generated and backed up one by one.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{hd::{DerivationPath, Mnemonic}, KeyPairAlgorithm};
+//!
+//! let mnemonic = Mnemonic::generate(24).unwrap();
+//! let seed = mnemonic.to_seed("");
+//!
+//! let path: DerivationPath = "m/44'/0'/1'".parse().unwrap();
+//! let device = seed.derive_keypair(&path, KeyPairAlgorithm::ED25519).unwrap();
+//!
+//! // the same phrase always yields the same keys
+//! let restored = Mnemonic::from_phrase(&mnemonic.phrase()).unwrap().to_seed("");
+//! let again = restored.derive_keypair(&path, KeyPairAlgorithm::ED25519).unwrap();
+//! assert_eq!(device.public_key_bytes(), again.public_key_bytes());
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use p256::elliptic_curve::{
+    ff::{Field, PrimeField},
+    sec1::ToEncodedPoint,
+};
+use ring::hmac;
+use zeroize::Zeroizing;
+
+use super::{
+    encoding::{ecdsa_keypair_from_scalar, ed25519_keypair_from_seed},
+    AuthKeyPair, EcdsaSigningAlgorithm, KeyError, KeyPairAlgorithm,
+};
+
+/// An error that can occur while deriving keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DerivationError {
+    /// The derivation path is not of the form `m/0'/1/...`.
+    InvalidPath,
+    /// The seed is shorter than 16 or longer than 64 bytes.
+    InvalidSeedLength,
+    /// The mnemonic phrase has an unknown word, a wrong word count or a bad checksum.
+    InvalidMnemonic,
+    /// Ed25519 keys can only be derived at hardened indices.
+    HardenedOnly,
+    /// The algorithm does not support hierarchical derivation.
+    UnsupportedAlgorithm,
+    Key(KeyError),
+}
+
+crate::impl_display_error!(DerivationError);
+
+impl From<KeyError> for DerivationError {
+    fn from(value: KeyError) -> Self {
+        DerivationError::Key(value)
+    }
+}
+
+/// The index of a child key, which is either hardened or normal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChildIndex(u32);
+
+impl ChildIndex {
+    const HARDENED: u32 = 1 << 31;
+
+    /// Creates a hardened index, or `None` if `index` is not below 2^31.
+    pub fn hardened(index: u32) -> Option<Self> {
+        (index < Self::HARDENED).then_some(Self(index | Self::HARDENED))
+    }
+
+    /// Creates a normal index, or `None` if `index` is not below 2^31.
+    pub fn normal(index: u32) -> Option<Self> {
+        (index < Self::HARDENED).then_some(Self(index))
+    }
+
+    /// Returns `true` if this is a hardened index.
+    pub fn is_hardened(self) -> bool {
+        self.0 & Self::HARDENED != 0
+    }
+
+    /// Returns the index without the hardened flag.
+    pub fn index(self) -> u32 {
+        self.0 & !Self::HARDENED
+    }
+}
+
+impl fmt::Display for ChildIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.index())?;
+        if self.is_hardened() {
+            write!(f, "'")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ChildIndex {
+    type Err = DerivationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, hardened) = match s.strip_suffix(['\'', 'h', 'H']) {
+            Some(index) => (index, true),
+            None => (s, false),
+        };
+        if index.is_empty() || !index.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(DerivationError::InvalidPath);
+        }
+        let index = index.parse().map_err(|_| DerivationError::InvalidPath)?;
+        let child = match hardened {
+            true => ChildIndex::hardened(index),
+            false => ChildIndex::normal(index),
+        };
+        child.ok_or(DerivationError::InvalidPath)
+    }
+}
+
+/// A path of child indices from the master key, written as `m/44'/0'/1`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DerivationPath(Vec<ChildIndex>);
+
+impl DerivationPath {
+    /// Creates a path from a list of child indices.
+    pub fn new(indices: Vec<ChildIndex>) -> Self {
+        Self(indices)
+    }
+
+    /// Returns the child indices of this path, starting below the master key.
+    pub fn indices(&self) -> &[ChildIndex] {
+        &self.0
+    }
+
+    /// Returns a new path with `index` appended to this one.
+    pub fn child(&self, index: ChildIndex) -> Self {
+        let mut indices = self.0.clone();
+        indices.push(index);
+        Self(indices)
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{index}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = DerivationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('/');
+        if parts.next() != Some("m") {
+            return Err(DerivationError::InvalidPath);
+        }
+        parts
+            .map(ChildIndex::from_str)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// A BIP39 mnemonic phrase in English.
+pub struct Mnemonic(bip39::Mnemonic);
+
+impl Mnemonic {
+    /// Generates a random mnemonic of 12, 15, 18, 21 or 24 words.
+    pub fn generate(word_count: usize) -> Result<Self, DerivationError> {
+        if !matches!(word_count, 12 | 15 | 18 | 21 | 24) {
+            return Err(DerivationError::InvalidMnemonic);
+        }
+        let entropy = Zeroizing::new(crate::random_bytes_vec(word_count / 3 * 4));
+        Self::from_entropy(&entropy)
+    }
+
+    /// Creates the mnemonic that encodes `entropy`, which must be 16 to 32 bytes long.
+    pub fn from_entropy(entropy: &[u8]) -> Result<Self, DerivationError> {
+        bip39::Mnemonic::from_entropy(entropy)
+            .map(Self)
+            .map_err(|_| DerivationError::InvalidMnemonic)
+    }
+
+    /// Parses a mnemonic phrase and checks its checksum.
+    pub fn from_phrase(phrase: &str) -> Result<Self, DerivationError> {
+        bip39::Mnemonic::parse(phrase)
+            .map(Self)
+            .map_err(|_| DerivationError::InvalidMnemonic)
+    }
+
+    /// Returns the words of this mnemonic separated by spaces.
+    pub fn phrase(&self) -> String {
+        self.0.to_string()
+    }
+
+    /// Computes the seed of this mnemonic, protected by an optional `passphrase`.
+    pub fn to_seed(&self, passphrase: &str) -> Seed {
+        Seed(Zeroizing::new(self.0.to_seed(passphrase).to_vec()))
+    }
+}
+
+impl fmt::Debug for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Mnemonic(<redacted>)")
+    }
+}
+
+/// The master seed from which every key of a hierarchy is derived.
+#[derive(Clone)]
+pub struct Seed(Zeroizing<Vec<u8>>);
+
+impl Seed {
+    /// Creates a seed from raw bytes, which must be 16 to 64 bytes long.
+    pub fn new(bytes: &[u8]) -> Result<Self, DerivationError> {
+        if !(16..=64).contains(&bytes.len()) {
+            return Err(DerivationError::InvalidSeedLength);
+        }
+        Ok(Self(Zeroizing::new(bytes.to_vec())))
+    }
+
+    /// Returns the raw bytes of this seed, which must be kept as secret as the derived keys.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the master key of `algorithm` for this seed.
+    ///
+    /// # Errors
+    ///
+    /// * `UnsupportedAlgorithm` - If `algorithm` is not Ed25519 or ECDSA over P-256.
+    pub fn master_key(&self, algorithm: KeyPairAlgorithm) -> Result<ExtendedKey, DerivationError> {
+        ExtendedKey::master(self, algorithm)
+    }
+
+    /// Derives the `AuthKeyPair` of `algorithm` at `path`.
+    ///
+    /// # Errors
+    ///
+    /// * `UnsupportedAlgorithm` - If `algorithm` is not Ed25519 or ECDSA over P-256.
+    /// * `HardenedOnly` - If `algorithm` is Ed25519 and `path` has a normal index.
+    pub fn derive_keypair(
+        &self,
+        path: &DerivationPath,
+        algorithm: KeyPairAlgorithm,
+    ) -> Result<AuthKeyPair, DerivationError> {
+        self.master_key(algorithm)?.derive_path(path)?.keypair()
+    }
+}
+
+impl fmt::Debug for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Seed(<redacted>)")
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Curve {
+    Ed25519,
+    P256(EcdsaSigningAlgorithm),
+}
+
+impl Curve {
+    fn of(algorithm: KeyPairAlgorithm) -> Result<Curve, DerivationError> {
+        match algorithm {
+            KeyPairAlgorithm::ED25519 => Ok(Curve::Ed25519),
+            KeyPairAlgorithm::ECDSA(
+                algo @ (EcdsaSigningAlgorithm::P256_SHA256_FIXED
+                | EcdsaSigningAlgorithm::P256_SHA256_ASN1),
+            ) => Ok(Curve::P256(algo)),
+            // SLIP-10 defines no derivation over P-384
+            KeyPairAlgorithm::ECDSA(
+                EcdsaSigningAlgorithm::P384_SHA384_FIXED | EcdsaSigningAlgorithm::P384_SHA384_ASN1,
+            ) => Err(DerivationError::UnsupportedAlgorithm),
+            KeyPairAlgorithm::RSA(_) | KeyPairAlgorithm::BLS12_381 => {
+                Err(DerivationError::UnsupportedAlgorithm)
+            }
+        }
+    }
+
+    fn hmac_key(self) -> &'static [u8] {
+        match self {
+            Curve::Ed25519 => b"ed25519 seed",
+            Curve::P256(_) => b"Nist256p1 seed",
+        }
+    }
+}
+
+/// A private key together with the chain code needed to derive its children.
+#[derive(Clone)]
+pub struct ExtendedKey {
+    curve: Curve,
+    key: Zeroizing<[u8; 32]>,
+    chain_code: [u8; 32],
+    depth: u8,
+}
+
+impl ExtendedKey {
+    /// Returns the master key of `algorithm` for `seed`.
+    pub fn master(seed: &Seed, algorithm: KeyPairAlgorithm) -> Result<Self, DerivationError> {
+        let curve = Curve::of(algorithm)?;
+        let key = hmac::Key::new(hmac::HMAC_SHA512, curve.hmac_key());
+
+        let mut data = Zeroizing::new(seed.as_bytes().to_vec());
+        loop {
+            let (il, ir) = split(&key, &data);
+            // ECDSA keys must be valid non-zero scalars, otherwise the output is hashed again
+            if let Curve::P256(_) = curve {
+                if p256_scalar(&il).is_none() {
+                    data = Zeroizing::new(il.iter().chain(&ir).copied().collect());
+                    continue;
+                }
+            }
+            return Ok(Self {
+                curve,
+                key: il,
+                chain_code: ir,
+                depth: 0,
+            });
+        }
+    }
+
+    /// Derives the child key at `index`.
+    ///
+    /// # Errors
+    ///
+    /// * `HardenedOnly` - If this is an Ed25519 key and `index` is not hardened.
+    pub fn derive_child(&self, index: ChildIndex) -> Result<Self, DerivationError> {
+        let key = hmac::Key::new(hmac::HMAC_SHA512, &self.chain_code);
+        let mut data = Zeroizing::new(Vec::with_capacity(37));
+        if index.is_hardened() {
+            data.push(0);
+            data.extend_from_slice(&*self.key);
+        } else {
+            match self.curve {
+                Curve::Ed25519 => return Err(DerivationError::HardenedOnly),
+                Curve::P256(_) => data.extend_from_slice(&self.p256_public_key(true)?),
+            }
+        }
+        data.extend_from_slice(&index.0.to_be_bytes());
+
+        loop {
+            let (il, ir) = split(&key, &data);
+            let child = match self.curve {
+                Curve::Ed25519 => Some(il),
+                Curve::P256(_) => p256_scalar(&il).and_then(|il| {
+                    let parent = p256_scalar(&self.key)?;
+                    let child = il + parent;
+                    let child: [u8; 32] = child.to_repr().into();
+                    p256_scalar(&child).map(|_| Zeroizing::new(child))
+                }),
+            };
+            match child {
+                Some(child) => {
+                    return Ok(Self {
+                        curve: self.curve,
+                        key: child,
+                        chain_code: ir,
+                        depth: self.depth.saturating_add(1),
+                    })
+                }
+                // an invalid child is skipped by hashing again, as described in SLIP-10
+                None => {
+                    data = Zeroizing::new(vec![1]);
+                    data.extend_from_slice(&ir);
+                    data.extend_from_slice(&index.0.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    /// Derives the key at `path` below this key.
+    pub fn derive_path(&self, path: &DerivationPath) -> Result<Self, DerivationError> {
+        path.indices()
+            .iter()
+            .try_fold(self.clone(), |key, index| key.derive_child(*index))
+    }
+
+    /// Returns the chain code of this key.
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    /// Returns the number of derivations between the master key and this key.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// Returns the raw private key, which is an Ed25519 seed or a P-256 scalar.
+    pub fn private_key_bytes(&self) -> &[u8] {
+        &*self.key
+    }
+
+    /// Converts this key into an `AuthKeyPair` usable for signing.
+    pub fn keypair(&self) -> Result<AuthKeyPair, DerivationError> {
+        let keypair = match self.curve {
+            Curve::Ed25519 => ed25519_keypair_from_seed(&*self.key)?,
+            Curve::P256(algo) => {
+                ecdsa_keypair_from_scalar(&*self.key, &self.p256_public_key(false)?, algo)?
+            }
+        };
+        Ok(keypair)
+    }
+
+    fn p256_public_key(&self, compress: bool) -> Result<Vec<u8>, DerivationError> {
+        let scalar = p256_scalar(&self.key).ok_or(KeyError::KeyRejected)?;
+        let point = (p256::ProjectivePoint::GENERATOR * scalar).to_affine();
+        Ok(point.to_encoded_point(compress).as_bytes().to_vec())
+    }
+}
+
+impl fmt::Debug for ExtendedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtendedKey")
+            .field("key", &"<redacted>")
+            .field("chain_code", &hex::encode(self.chain_code))
+            .field("depth", &self.depth)
+            .finish()
+    }
+}
+
+/// Computes HMAC-SHA512 of `data` and splits it into its left and right halves
+fn split(key: &hmac::Key, data: &[u8]) -> (Zeroizing<[u8; 32]>, [u8; 32]) {
+    let tag = hmac::sign(key, data);
+    let (left, right) = tag.as_ref().split_at(32);
+    let mut il = Zeroizing::new([0; 32]);
+    il.copy_from_slice(left);
+    let mut ir = [0; 32];
+    ir.copy_from_slice(right);
+    (il, ir)
+}
+
+/// Parses a non-zero scalar below the order of P-256
+fn p256_scalar(bytes: &[u8; 32]) -> Option<p256::Scalar> {
+    let scalar: Option<p256::Scalar> =
+        p256::Scalar::from_repr(p256::FieldBytes::clone_from_slice(bytes)).into();
+    scalar.filter(|scalar| !bool::from(scalar.is_zero()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChildIndex, DerivationError, DerivationPath, Mnemonic, Seed};
+    use crate::{EcdsaSigningAlgorithm, KeyPairAlgorithm, RsaSigningAlgorithm};
+
+    const SEED: &str = "000102030405060708090a0b0c0d0e0f";
+
+    fn check(algorithm: KeyPairAlgorithm, path: &str, chain_code: &str, private_key: &str) {
+        let seed = Seed::new(&hex::decode(SEED).unwrap()).unwrap();
+        let key = seed
+            .master_key(algorithm)
+            .and_then(|master| master.derive_path(&path.parse().unwrap()))
+            .expect("couldn't derive key");
+        assert_eq!(chain_code, hex::encode(key.chain_code()), "{path}");
+        assert_eq!(private_key, hex::encode(key.private_key_bytes()), "{path}");
+
+        let keypair = key.keypair().expect("couldn't build key pair");
+        let signature = keypair.sign(b"message").unwrap();
+        let pubkey = keypair.into_public_key();
+        assert!(pubkey.verify(b"message", &signature).is_ok());
+    }
+
+    #[test]
+    fn slip10_ed25519_test() {
+        let algorithm = KeyPairAlgorithm::ED25519;
+        check(
+            algorithm,
+            "m",
+            "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb",
+            "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+        );
+        check(
+            algorithm,
+            "m/0'",
+            "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69",
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+        );
+
+        let seed = Seed::new(&hex::decode(SEED).unwrap()).unwrap();
+        let master = seed.master_key(algorithm).unwrap();
+        assert_eq!(
+            DerivationError::HardenedOnly,
+            master
+                .derive_child(ChildIndex::normal(0).unwrap())
+                .unwrap_err()
+        );
+    }
+
+    #[test]
+    fn slip10_p256_test() {
+        let algorithm = KeyPairAlgorithm::ECDSA(EcdsaSigningAlgorithm::P256_SHA256_FIXED);
+        check(
+            algorithm,
+            "m",
+            "beeb672fe4621673f722f38529c07392fecaa61015c80c34f29ce8b41b3cb6ea",
+            "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2",
+        );
+        check(
+            algorithm,
+            "m/0'",
+            "3460cea53e6a6bb5fb391eeef3237ffd8724bf0a40e94943c98b83825342ee11",
+            "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c",
+        );
+        check(
+            algorithm,
+            "m/0'/1",
+            "4187afff1aafa8445010097fb99d23aee9f599450c7bd140b6826ac22ba21d0c",
+            "284e9d38d07d21e4e281b645089a94f4cf5a5a81369acf151a1c3a57f18b2129",
+        );
+
+        let seed = Seed::new(&hex::decode(SEED).unwrap()).unwrap();
+        let unsupported = [
+            KeyPairAlgorithm::ECDSA(EcdsaSigningAlgorithm::P384_SHA384_FIXED),
+            KeyPairAlgorithm::ECDSA(EcdsaSigningAlgorithm::P384_SHA384_ASN1),
+            KeyPairAlgorithm::RSA(RsaSigningAlgorithm::PKCS1_2048_8192_SHA256),
+            KeyPairAlgorithm::BLS12_381,
+        ];
+        for algorithm in unsupported {
+            assert_eq!(
+                DerivationError::UnsupportedAlgorithm,
+                seed.master_key(algorithm).unwrap_err()
+            );
+        }
+    }
+
+    #[test]
+    fn mnemonic_test() {
+        let mnemonic = Mnemonic::from_entropy(&[0; 16]).unwrap();
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        assert_eq!(phrase, mnemonic.phrase());
+        assert_eq!(
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+            hex::encode(mnemonic.to_seed("TREZOR").as_bytes())
+        );
+
+        assert!(Mnemonic::from_phrase(&phrase.replace("about", "abandon")).is_err());
+        assert_eq!(
+            24,
+            Mnemonic::generate(24).unwrap().phrase().split(' ').count()
+        );
+        assert!(Mnemonic::generate(13).is_err());
+    }
+
+    #[test]
+    fn path_test() {
+        let path: DerivationPath = "m/44'/0h/7".parse().unwrap();
+        assert_eq!("m/44'/0'/7", path.to_string());
+        assert_eq!(3, path.indices().len());
+        assert!(path.indices()[1].is_hardened());
+        assert!(!path.indices()[2].is_hardened());
+
+        for invalid in ["", "44'/0'", "m/", "m/a", "m/2147483648", "m/-1"] {
+            assert!(invalid.parse::<DerivationPath>().is_err(), "{invalid}");
+        }
+    }
+}
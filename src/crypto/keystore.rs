@@ -0,0 +1,525 @@
+//! Password-protected storage for `AuthKeyPair`s.
+//!
+//! A [`KeyStoreFile`] holds one private key encrypted with AES-256-GCM under a key derived from a
+//! password with PBKDF2-HMAC-SHA256. It is serialized as versioned JSON that records the KDF
+//! parameters, the cipher and its nonce, the authentication tag (`mac`) and the algorithm of the key.
+//! The algorithm and the public key are stored in the clear but are authenticated along with the
+//! ciphertext, so they cannot be altered without failing decryption.
+//!
+//! A [`KeyStore`] manages a directory of such files, one per named key.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::keystore::{KdfParams, KeyStoreFile};
+//!
+//! let keypair = blockify::generate_ed25519_keypair();
+//!
+//! let file = KeyStoreFile::encrypt_with(&keypair, b"correct horse", KdfParams::new(1_000)).unwrap();
+//! let json = file.to_json();
+//!
+//! let file = KeyStoreFile::from_json(&json).unwrap();
+//! assert!(file.decrypt(b"wrong horse").is_err());
+//!
+//! let loaded = file.decrypt(b"correct horse").unwrap();
+//! assert_eq!(keypair.public_key_bytes(), loaded.public_key_bytes());
+//! ```
+
+use std::{
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+};
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use super::{AuthKeyPair, KeyPairAlgorithm, PublicKey};
+
+/// The version of the keystore file format written by this crate
+pub const KEYSTORE_VERSION: u32 = 1;
+
+const KDF_PBKDF2_SHA256: &str = "pbkdf2-hmac-sha256";
+const CIPHER_AES_256_GCM: &str = "aes-256-gcm";
+const DERIVED_KEY_LEN: usize = 32;
+const SALT_LEN: usize = 32;
+const FILE_EXTENSION: &str = "json";
+
+/// An error that can occur while encrypting, decrypting or managing stored keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStoreError {
+    /// The password is wrong or the file has been tampered with.
+    DecryptionFailed,
+    /// The file was written with a format version this crate does not understand.
+    UnsupportedVersion(u32),
+    /// The file uses a KDF or cipher this crate does not support.
+    UnsupportedScheme,
+    /// The file asks for more PBKDF2 iterations than [`KdfParams::MAX_ITERATIONS`].
+    TooManyIterations(u32),
+    /// The file is not a valid keystore file.
+    Malformed,
+    /// The key name is empty or contains characters that are not allowed in a file name.
+    InvalidName,
+    /// No key is stored under the given name.
+    NotFound,
+    /// A key is already stored under the given name.
+    AlreadyExists,
+    /// Reading or writing the keystore directory failed.
+    Io(std::io::ErrorKind),
+    /// The cryptographic backend failed without giving a reason, for example when no randomness is available.
+    Unspecified,
+}
+
+crate::impl_display_error!(KeyStoreError);
+
+impl From<std::io::Error> for KeyStoreError {
+    fn from(value: std::io::Error) -> Self {
+        match value.kind() {
+            std::io::ErrorKind::NotFound => KeyStoreError::NotFound,
+            std::io::ErrorKind::AlreadyExists => KeyStoreError::AlreadyExists,
+            kind => KeyStoreError::Io(kind),
+        }
+    }
+}
+
+impl From<ring::error::Unspecified> for KeyStoreError {
+    fn from(_: ring::error::Unspecified) -> Self {
+        KeyStoreError::Unspecified
+    }
+}
+
+/// The parameters of the password-based key derivation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    iterations: u32,
+    salt: String,
+    dklen: usize,
+}
+
+impl KdfParams {
+    /// The number of PBKDF2 iterations used by default.
+    pub const DEFAULT_ITERATIONS: u32 = 600_000;
+
+    /// The largest number of PBKDF2 iterations a key is derived with, so that a keystore file
+    /// cannot make decryption run for hours.
+    pub const MAX_ITERATIONS: u32 = 10_000_000;
+
+    /// Creates parameters with the given iteration count and a fresh random salt.
+    ///
+    /// Keys are only derived with up to [`KdfParams::MAX_ITERATIONS`] iterations.
+    pub fn new(iterations: u32) -> Self {
+        Self {
+            iterations,
+            salt: hex::encode(crate::random_bytes::<SALT_LEN>()),
+            dklen: DERIVED_KEY_LEN,
+        }
+    }
+
+    /// Returns the number of PBKDF2 iterations.
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+
+    fn derive(&self, password: &[u8]) -> Result<Zeroizing<Vec<u8>>, KeyStoreError> {
+        if self.iterations > Self::MAX_ITERATIONS {
+            return Err(KeyStoreError::TooManyIterations(self.iterations));
+        }
+        let iterations = NonZeroU32::new(self.iterations).ok_or(KeyStoreError::Malformed)?;
+        let salt = hex::decode(&self.salt).map_err(|_| KeyStoreError::Malformed)?;
+        if self.dklen != DERIVED_KEY_LEN {
+            return Err(KeyStoreError::UnsupportedScheme);
+        }
+
+        let mut key = Zeroizing::new(vec![0; DERIVED_KEY_LEN]);
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            password,
+            &mut key,
+        );
+        Ok(key)
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_ITERATIONS)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CipherParams {
+    nonce: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CryptoSection {
+    kdf: String,
+    kdfparams: KdfParams,
+    cipher: String,
+    cipherparams: CipherParams,
+    ciphertext: String,
+    mac: String,
+}
+
+/// A private key encrypted under a password, in the versioned keystore file format.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyStoreFile {
+    version: u32,
+    algorithm: KeyPairAlgorithm,
+    public_key: String,
+    crypto: CryptoSection,
+}
+
+impl KeyStoreFile {
+    /// Encrypts `keypair` under `password` using the default KDF parameters.
+    pub fn encrypt(keypair: &AuthKeyPair, password: &[u8]) -> Result<Self, KeyStoreError> {
+        Self::encrypt_with(keypair, password, KdfParams::default())
+    }
+
+    /// Encrypts `keypair` under `password` using the given KDF parameters.
+    pub fn encrypt_with(
+        keypair: &AuthKeyPair,
+        password: &[u8],
+        kdfparams: KdfParams,
+    ) -> Result<Self, KeyStoreError> {
+        let key = kdfparams.derive(password)?;
+        let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key)?);
+
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce)?;
+
+        let algorithm = keypair.algorithm();
+        let public_key = hex::encode(keypair.public_key_bytes());
+        let aad = associated_data(KEYSTORE_VERSION, algorithm, &public_key)?;
+
+        let mut buffer = Zeroizing::new(keypair.private_key_bytes().to_vec());
+        let tag = key.seal_in_place_separate_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(&aad),
+            &mut buffer,
+        )?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            algorithm,
+            public_key,
+            crypto: CryptoSection {
+                kdf: KDF_PBKDF2_SHA256.to_owned(),
+                kdfparams,
+                cipher: CIPHER_AES_256_GCM.to_owned(),
+                cipherparams: CipherParams {
+                    nonce: hex::encode(nonce),
+                },
+                ciphertext: hex::encode(&buffer),
+                mac: hex::encode(tag.as_ref()),
+            },
+        })
+    }
+
+    /// Decrypts the stored private key with `password`.
+    ///
+    /// # Errors
+    ///
+    /// * `DecryptionFailed` - If the password is wrong or the file has been altered.
+    /// * `UnsupportedVersion` - If the file was written in an unknown format version.
+    pub fn decrypt(&self, password: &[u8]) -> Result<AuthKeyPair, KeyStoreError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(KeyStoreError::UnsupportedVersion(self.version));
+        }
+        let crypto = &self.crypto;
+        if crypto.kdf != KDF_PBKDF2_SHA256 || crypto.cipher != CIPHER_AES_256_GCM {
+            return Err(KeyStoreError::UnsupportedScheme);
+        }
+
+        let nonce = decode_hex(&crypto.cipherparams.nonce)?;
+        let nonce =
+            Nonce::try_assume_unique_for_key(&nonce).map_err(|_| KeyStoreError::Malformed)?;
+        let aad = associated_data(self.version, self.algorithm, &self.public_key)?;
+
+        let key = crypto.kdfparams.derive(password)?;
+        let key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key)?);
+
+        let mut buffer = Zeroizing::new(decode_hex(&crypto.ciphertext)?);
+        buffer.extend(decode_hex(&crypto.mac)?);
+        let private_key = key
+            .open_in_place(nonce, Aad::from(&aad), &mut buffer)
+            .map_err(|_| KeyStoreError::DecryptionFailed)?;
+
+        Ok(AuthKeyPair::new(
+            Box::from(&*private_key),
+            decode_hex(&self.public_key)?.into_boxed_slice(),
+            self.algorithm,
+        ))
+    }
+
+    /// Returns the algorithm of the stored key.
+    pub fn algorithm(&self) -> KeyPairAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the public key of the stored key pair, which does not require the password.
+    pub fn public_key(&self) -> Result<PublicKey, KeyStoreError> {
+        let bytes = decode_hex(&self.public_key)?;
+        Ok(PublicKey::new(bytes.into_boxed_slice(), self.algorithm))
+    }
+
+    /// Serializes this file into JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("a keystore file is always serializable")
+    }
+
+    /// Parses a keystore file from JSON.
+    pub fn from_json(json: &str) -> Result<Self, KeyStoreError> {
+        serde_json::from_str(json).map_err(|_| KeyStoreError::Malformed)
+    }
+}
+
+/// The header fields that are authenticated along with the ciphertext
+fn associated_data(
+    version: u32,
+    algorithm: KeyPairAlgorithm,
+    public_key: &str,
+) -> Result<Vec<u8>, KeyStoreError> {
+    super::canonical::encode(&(version, algorithm, public_key))
+        .map_err(|_| KeyStoreError::Malformed)
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, KeyStoreError> {
+    hex::decode(value).map_err(|_| KeyStoreError::Malformed)
+}
+
+/// A directory of password-protected keys, each stored in its own keystore file under a name.
+pub struct KeyStore {
+    dir: PathBuf,
+    iterations: Option<u32>,
+}
+
+impl KeyStore {
+    /// Opens the keystore in `dir`, creating the directory if it is absent.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, KeyStoreError> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            iterations: None,
+        })
+    }
+
+    /// Uses `iterations` PBKDF2 iterations for keys stored from now on, each with a fresh salt.
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = Some(iterations);
+        self
+    }
+
+    /// Returns the names of all stored keys in alphabetical order.
+    pub fn list(&self) -> Result<Vec<String>, KeyStoreError> {
+        let mut names = vec![];
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(FILE_EXTENSION) {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|n| n.to_str()) {
+                names.push(name.to_owned());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Returns `true` if a key is stored under `name`.
+    pub fn contains(&self, name: &str) -> Result<bool, KeyStoreError> {
+        Ok(self.path(name)?.exists())
+    }
+
+    /// Encrypts `keypair` under `password` and stores it under `name`.
+    ///
+    /// Fails with `AlreadyExists` rather than replacing a stored key.
+    pub fn store(
+        &self,
+        name: &str,
+        keypair: &AuthKeyPair,
+        password: &[u8],
+    ) -> Result<(), KeyStoreError> {
+        let path = self.path(name)?;
+        let kdfparams = match self.iterations {
+            Some(iterations) => KdfParams::new(iterations),
+            None => KdfParams::default(),
+        };
+        let file = KeyStoreFile::encrypt_with(keypair, password, kdfparams)?;
+        write_new(&path, file.to_json().as_bytes())
+    }
+
+    /// Returns the keystore file stored under `name` without decrypting it.
+    pub fn file(&self, name: &str) -> Result<KeyStoreFile, KeyStoreError> {
+        let json = std::fs::read_to_string(self.path(name)?)?;
+        KeyStoreFile::from_json(&json)
+    }
+
+    /// Loads and decrypts the key stored under `name`.
+    pub fn load(&self, name: &str, password: &[u8]) -> Result<AuthKeyPair, KeyStoreError> {
+        self.file(name)?.decrypt(password)
+    }
+
+    /// Renames the key stored under `from` to `to`.
+    ///
+    /// Fails with `AlreadyExists` rather than replacing a stored key, even when another process
+    /// stores a key under `to` at the same time.
+    pub fn rename(&self, from: &str, to: &str) -> Result<(), KeyStoreError> {
+        let (from, to) = (self.path(from)?, self.path(to)?);
+        // linking never replaces an existing file, unlike `fs::rename`
+        std::fs::hard_link(&from, &to)?;
+        std::fs::remove_file(from)?;
+        Ok(())
+    }
+
+    /// Deletes the key stored under `name`.
+    pub fn delete(&self, name: &str) -> Result<(), KeyStoreError> {
+        std::fs::remove_file(self.path(name)?)?;
+        Ok(())
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf, KeyStoreError> {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(KeyStoreError::InvalidName);
+        }
+        Ok(self.dir.join(format!("{name}.{FILE_EXTENSION}")))
+    }
+}
+
+/// Writes a file that must not exist yet, readable only by its owner where supported
+fn write_new(path: &Path, contents: &[u8]) -> Result<(), KeyStoreError> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KdfParams, KeyStore, KeyStoreError, KeyStoreFile};
+
+    #[test]
+    fn file_test() {
+        let keypair = crate::generate_ed25519_keypair();
+        let file = KeyStoreFile::encrypt_with(&keypair, b"password", KdfParams::new(1_000))
+            .expect("couldn't encrypt key pair");
+
+        let json = file.to_json();
+        assert!(!json.contains(&hex::encode(keypair.private_key_bytes())));
+
+        let file = KeyStoreFile::from_json(&json).expect("couldn't parse keystore file");
+        assert_eq!(keypair.algorithm(), file.algorithm());
+        assert_eq!(
+            keypair.public_key_bytes(),
+            file.public_key().unwrap().as_bytes()
+        );
+
+        let loaded = file
+            .decrypt(b"password")
+            .expect("couldn't decrypt key pair");
+        assert_eq!(keypair.private_key_bytes(), loaded.private_key_bytes());
+        assert_eq!(
+            Err(KeyStoreError::DecryptionFailed),
+            file.decrypt(b"passw0rd").map(|_| ())
+        );
+
+        // the algorithm and public key are authenticated along with the ciphertext
+        let other = crate::generate_ed25519_keypair();
+        let tampered = json.replace(
+            &hex::encode(keypair.public_key_bytes()),
+            &hex::encode(other.public_key_bytes()),
+        );
+        let tampered = KeyStoreFile::from_json(&tampered).unwrap();
+        assert_eq!(
+            Err(KeyStoreError::DecryptionFailed),
+            tampered.decrypt(b"password").map(|_| ())
+        );
+
+        // a file cannot ask for an unbounded amount of work
+        let costly = json.replace("\"iterations\": 1000", "\"iterations\": 4294967295");
+        let costly = KeyStoreFile::from_json(&costly).unwrap();
+        assert_eq!(
+            Err(KeyStoreError::TooManyIterations(u32::MAX)),
+            costly.decrypt(b"password").map(|_| ())
+        );
+        let params = KdfParams::new(KdfParams::MAX_ITERATIONS + 1);
+        assert_eq!(
+            Err(KeyStoreError::TooManyIterations(
+                KdfParams::MAX_ITERATIONS + 1
+            )),
+            KeyStoreFile::encrypt_with(&keypair, b"password", params).map(|_| ())
+        );
+
+        let future = json.replace("\"version\": 1", "\"version\": 2");
+        let future = KeyStoreFile::from_json(&future).unwrap();
+        assert_eq!(
+            Err(KeyStoreError::UnsupportedVersion(2)),
+            future.decrypt(b"password").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn directory_test() {
+        let dir = "target2/tests/keystore/";
+        let _ = std::fs::remove_dir_all(dir);
+        let store = KeyStore::open(dir)
+            .expect("couldn't open keystore")
+            .with_iterations(1_000);
+
+        let alice = crate::generate_ed25519_keypair();
+        let bob = crate::generate_ecdsa_keypair(crate::EcdsaSigningAlgorithm::P256_SHA256_FIXED);
+        store.store("alice", &alice, b"alice's password").unwrap();
+        store.store("bob", &bob, b"bob's password").unwrap();
+        assert_eq!(
+            Err(KeyStoreError::AlreadyExists),
+            store.store("alice", &bob, b"password")
+        );
+        assert_eq!(vec!["alice", "bob"], store.list().unwrap());
+
+        let loaded = store.load("bob", b"bob's password").unwrap();
+        assert_eq!(bob.public_key_bytes(), loaded.public_key_bytes());
+        assert_eq!(bob.algorithm(), loaded.algorithm());
+
+        store.rename("alice", "carol").unwrap();
+        assert_eq!(vec!["bob", "carol"], store.list().unwrap());
+        assert_eq!(
+            Err(KeyStoreError::AlreadyExists),
+            store.rename("bob", "carol")
+        );
+        assert!(store.contains("bob").unwrap());
+        assert_eq!(Err(KeyStoreError::NotFound), store.rename("dave", "erin"));
+        let loaded = store.load("carol", b"alice's password").unwrap();
+        assert_eq!(alice.public_key_bytes(), loaded.public_key_bytes());
+
+        store.delete("bob").unwrap();
+        assert_eq!(vec!["carol"], store.list().unwrap());
+        assert_eq!(
+            Err(KeyStoreError::NotFound),
+            store.load("bob", b"bob's password").map(|_| ())
+        );
+        assert_eq!(
+            Err(KeyStoreError::InvalidName),
+            store.store("../alice", &alice, b"password")
+        );
+    }
+}
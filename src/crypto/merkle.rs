@@ -1,69 +1,273 @@
+//! Binary Merkle trees over the hashes of records.
+//!
+//! The tree has the shape of RFC 6962: the first `k` leaves form the left subtree and the rest the
+//! right one, where `k` is the largest power of two smaller than the number of leaves. Equivalently,
+//! every level pairs up its nodes from the left, and an unpaired last node moves up unchanged.
+//!
+//! Leaves and inner nodes are hashed with different prefixes, so a leaf can never be passed off as
+//! an inner node:
+//!
+//! * a leaf is hashed as `H(0x00 || leaf)`,
+//! * an inner node is hashed as `H(0x01 || left || right)`,
+//! * the root of the empty tree is `H("")`.
+//!
+//! `H` is the [`HashAlgorithm`] of the tree. The same leaves therefore always give the same root,
+//! which anyone holding the records of a block can recompute.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{merkle::{self, MerkleTree}, HashAlgorithm};
+//!
+//! let leaves = ["a", "b", "c"].map(|leaf| blockify::hash(&leaf));
+//!
+//! let mut tree = MerkleTree::new();
+//! for leaf in &leaves {
+//!     tree.push(leaf);
+//! }
+//!
+//! let algorithm = HashAlgorithm::Sha256;
+//! let ab = merkle::node_hash(
+//!     algorithm,
+//!     &merkle::leaf_hash(algorithm, &leaves[0]),
+//!     &merkle::leaf_hash(algorithm, &leaves[1]),
+//! );
+//! let root = merkle::node_hash(algorithm, &ab, &merkle::leaf_hash(algorithm, &leaves[2]));
+//! assert_eq!(&root, tree.root());
+//! assert_eq!(tree, MerkleTree::from_leaves(algorithm, &leaves));
+//!
+//! // prove that the second leaf is in the tree without revealing the others
+//! let proof = tree.proof(1).unwrap();
+//! assert!(merkle::verify_proof(tree.root(), &leaves[1], &proof).is_ok());
+//! assert!(merkle::verify_proof(tree.root(), &leaves[2], &proof).is_err());
+//! ```
+
 use serde::{Deserialize, Serialize};
 
-use super::Hash;
+use super::{Hash, HashAlgorithm};
+
+pub mod sparse;
+
+/// The prefix of hashed leaves.
+pub const LEAF_PREFIX: u8 = 0x00;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MerkleNode {
-    hash: Hash,
-    left: Option<Box<MerkleNode>>,
-    right: Option<Box<MerkleNode>>,
-    center: Option<Box<MerkleNode>>,
+/// The prefix of hashed inner nodes.
+pub const NODE_PREFIX: u8 = 0x01;
+
+/// Returns the hash of the leaf node holding `leaf`.
+pub fn leaf_hash(algorithm: HashAlgorithm, leaf: &[u8]) -> Hash {
+    algorithm.digest_all([&[LEAF_PREFIX][..], leaf])
 }
 
-impl MerkleNode {
-    pub fn build(
-        hash: Hash,
-        left: Option<MerkleNode>,
-        center: Option<MerkleNode>,
-        right: Option<MerkleNode>,
-    ) -> Self {
-        Self {
-            hash,
-            left: left.map(Box::new),
-            center: center.map(Box::new),
-            right: right.map(Box::new),
-        }
+/// Returns the hash of the inner node with the children `left` and `right`.
+pub fn node_hash(algorithm: HashAlgorithm, left: &Hash, right: &Hash) -> Hash {
+    algorithm.digest_all([&[NODE_PREFIX][..], left, right])
+}
+
+/// Returns the root of the empty tree.
+pub fn empty_root(algorithm: HashAlgorithm) -> Hash {
+    algorithm.digest(b"")
+}
+
+/// Verifies that `leaf` is included in the tree with the root `root`, as shown by `proof`.
+///
+/// `leaf` is the unhashed leaf, i.e. the hash of the record, and the nodes are hashed with the
+/// algorithm of `root`.
+pub fn verify_proof(root: &Hash, leaf: &Hash, proof: &MerkleProof) -> Result<(), MerkleError> {
+    match proof.root(root.algorithm(), leaf)? == *root {
+        true => Ok(()),
+        false => Err(MerkleError::RootMismatch),
     }
+}
 
-    pub fn new() -> Self {
-        Self {
-            hash: super::random_sha256(),
-            left: None,
-            center: None,
-            right: None,
+/// Verifies that `leaves` are included in the tree with the root `root`, as shown by `proof`.
+///
+/// `leaves` are the unhashed leaves at [`MerkleMultiProof::indices`], in the same order.
+pub fn verify_multi_proof(
+    root: &Hash,
+    leaves: &[Hash],
+    proof: &MerkleMultiProof,
+) -> Result<(), MerkleError> {
+    match proof.root(root.algorithm(), leaves)? == *root {
+        true => Ok(()),
+        false => Err(MerkleError::RootMismatch),
+    }
+}
+
+/// The types of error that can occur when working with Merkle trees and proofs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MerkleError {
+    /// The path of the proof does not fit its index and size.
+    MalformedProof,
+    /// The proof leads to a different root.
+    RootMismatch,
+    /// The layers do not form a Merkle tree.
+    InvalidTree,
+}
+
+crate::impl_display_error!(MerkleError);
+
+/// A proof that a leaf is included in a Merkle tree.
+///
+/// The proof holds the position of the leaf, the number of leaves in the tree and the siblings of
+/// the nodes on the path from the leaf to the root, starting at the leaf. Its size is logarithmic
+/// in the number of leaves, so it can be handed out as a receipt instead of the whole block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    index: usize,
+    size: usize,
+    path: Vec<Hash>,
+}
+
+impl MerkleProof {
+    /// Creates the proof for the leaf at `index` of a tree with `size` leaves from the siblings on
+    /// its path, starting at the leaf.
+    pub fn new(index: usize, size: usize, path: Vec<Hash>) -> Self {
+        Self { index, size, path }
+    }
+
+    /// Returns the index of the proven leaf.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the number of leaves in the tree.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the sibling hashes on the path from the leaf to the root.
+    pub fn path(&self) -> &[Hash] {
+        &self.path
+    }
+
+    /// Computes the root of the tree that `leaf` is included in according to this proof.
+    pub fn root(&self, algorithm: HashAlgorithm, leaf: &Hash) -> Result<Hash, MerkleError> {
+        if self.index >= self.size {
+            return Err(MerkleError::MalformedProof);
+        }
+
+        let mut siblings = self.path.iter();
+        let mut sibling = || siblings.next().ok_or(MerkleError::MalformedProof);
+        let (mut index, mut size) = (self.index, self.size);
+        let mut hash = leaf_hash(algorithm, leaf);
+        while size > 1 {
+            if index % 2 == 1 {
+                hash = node_hash(algorithm, sibling()?, &hash);
+            } else if index + 1 < size {
+                hash = node_hash(algorithm, &hash, sibling()?);
+            }
+            index /= 2;
+            size = size.div_ceil(2);
+        }
+
+        match siblings.next() {
+            Some(_) => Err(MerkleError::MalformedProof),
+            None => Ok(hash),
         }
     }
 
-    pub fn dummy() -> Self {
-        Self::new()
+    /// Verifies that `leaf` is included in the tree with the root `root`.
+    ///
+    /// This is the same as [`verify_proof`].
+    pub fn verify(&self, root: &Hash, leaf: &Hash) -> Result<(), MerkleError> {
+        verify_proof(root, leaf, self)
+    }
+}
+
+/// A proof that several leaves are included in a Merkle tree.
+///
+/// Paths of nearby leaves share most of their nodes, so instead of a path per leaf, the proof holds
+/// every node that is needed but cannot be computed from the leaves only once. The nodes are in the
+/// order the verifier needs them: level by level from the leaves up, and from left to right within
+/// a level.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleMultiProof {
+    indices: Vec<usize>,
+    size: usize,
+    hashes: Vec<Hash>,
+}
+
+impl MerkleMultiProof {
+    /// Returns the indices of the proven leaves in ascending order.
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
     }
 
-    /// Returns the hash of the node.
-    pub fn hash(&self) -> &Hash {
-        &self.hash
+    /// Returns the number of leaves in the tree.
+    pub fn size(&self) -> usize {
+        self.size
     }
 
-    /// Returns a reference to the left child of the node.
-    pub fn left(&self) -> &Option<Box<MerkleNode>> {
-        &self.left
+    /// Returns the hashes of the nodes needed besides the leaves.
+    pub fn hashes(&self) -> &[Hash] {
+        &self.hashes
     }
 
-    /// Returns a reference to the right child of the node.
-    pub fn right(&self) -> &Option<Box<MerkleNode>> {
-        &self.right
+    /// Computes the root of the tree that `leaves` are included in according to this proof.
+    ///
+    /// `leaves` are the unhashed leaves at [`MerkleMultiProof::indices`], in the same order.
+    pub fn root(&self, algorithm: HashAlgorithm, leaves: &[Hash]) -> Result<Hash, MerkleError> {
+        let ascending = self.indices.windows(2).all(|pair| pair[0] < pair[1]);
+        match self.indices.last() {
+            Some(&last) if ascending && last < self.size && leaves.len() == self.indices.len() => {}
+            _ => return Err(MerkleError::MalformedProof),
+        }
+
+        let mut hashes = self.hashes.iter();
+        let mut nodes = self
+            .indices
+            .iter()
+            .zip(leaves)
+            .map(|(&index, leaf)| (index, leaf_hash(algorithm, leaf)))
+            .collect::<Vec<_>>();
+        let mut size = self.size;
+        while size > 1 {
+            let mut parents = Vec::with_capacity(nodes.len());
+            let mut known = nodes.into_iter().peekable();
+            while let Some((index, hash)) = known.next() {
+                let parent = if index % 2 == 1 {
+                    let left = hashes.next().ok_or(MerkleError::MalformedProof)?;
+                    node_hash(algorithm, left, &hash)
+                } else if index + 1 == size {
+                    hash
+                } else if let Some((_, right)) = known.next_if(|(next, _)| *next == index + 1) {
+                    node_hash(algorithm, &hash, &right)
+                } else {
+                    let right = hashes.next().ok_or(MerkleError::MalformedProof)?;
+                    node_hash(algorithm, &hash, right)
+                };
+                parents.push((index / 2, parent));
+            }
+            nodes = parents;
+            size = size.div_ceil(2);
+        }
+
+        match (hashes.next(), nodes.pop()) {
+            (None, Some((_, root))) => Ok(root),
+            _ => Err(MerkleError::MalformedProof),
+        }
     }
 
-    pub fn center(&self) -> &Option<Box<MerkleNode>> {
-        &self.center
+    /// Verifies that `leaves` are included in the tree with the root `root`.
+    ///
+    /// This is the same as [`verify_multi_proof`].
+    pub fn verify(&self, root: &Hash, leaves: &[Hash]) -> Result<(), MerkleError> {
+        verify_multi_proof(root, leaves, self)
     }
 }
 
 /// A Merkle tree.
 ///
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The tree keeps the hashes of all its nodes level by level, so pushing a leaf only rehashes the
+/// nodes on the path from the new leaf to the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct MerkleTree {
-    root: MerkleNode,
-    size: usize,
+    // the hashed leaves first, then each level of inner nodes up to the root
+    layers: Vec<Vec<Hash>>,
+    root: Hash,
+    #[serde(default)]
+    algorithm: HashAlgorithm,
 }
 
 impl std::hash::Hash for MerkleTree {
@@ -73,77 +277,528 @@ impl std::hash::Hash for MerkleTree {
 }
 
 impl MerkleTree {
-    /// Creates a new Merkle tree from the given leaf node hashes.
+    /// Creates a new, empty Merkle tree hashed with SHA-256.
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        let left = MerkleNode::dummy();
-        let center = MerkleNode::dummy();
-        let right = MerkleNode::dummy();
-        let dummy_root = MerkleNode::build(
-            super::random_sha256(),
-            Some(left),
-            Some(center),
-            Some(right),
-        );
+        Self::with_algorithm(HashAlgorithm::Sha256)
+    }
+
+    /// Creates a new, empty Merkle tree whose nodes are hashed with `algorithm`.
+    pub fn with_algorithm(algorithm: HashAlgorithm) -> Self {
+        Self {
+            layers: vec![vec![]],
+            root: empty_root(algorithm),
+            algorithm,
+        }
+    }
+
+    /// Builds the tree of `leaves` at once.
+    pub fn from_leaves<L, I>(algorithm: HashAlgorithm, leaves: I) -> Self
+    where
+        L: AsRef<[u8]>,
+        I: IntoIterator<Item = L>,
+    {
+        let leaves = leaves
+            .into_iter()
+            .map(|leaf| leaf_hash(algorithm, leaf.as_ref()))
+            .collect();
+        Self::from_hashed_leaves(algorithm, leaves)
+    }
+
+    /// Restores a tree from its layers, as returned by [`MerkleTree::layers`].
+    ///
+    /// Fails with `InvalidTree` unless every inner node is the hash of its children.
+    pub fn from_layers(
+        algorithm: HashAlgorithm,
+        layers: Vec<Vec<Hash>>,
+    ) -> Result<Self, MerkleError> {
+        let leaves = layers.first().ok_or(MerkleError::InvalidTree)?;
+        let tree = Self::from_hashed_leaves(algorithm, leaves.clone());
+        match tree.layers == layers {
+            true => Ok(tree),
+            false => Err(MerkleError::InvalidTree),
+        }
+    }
 
+    fn from_hashed_leaves(algorithm: HashAlgorithm, leaves: Vec<Hash>) -> Self {
+        let mut layers = vec![leaves];
+        while let Some(layer) = layers.last().filter(|layer| layer.len() > 1) {
+            let parents = layer
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(algorithm, left, right),
+                    [single] => single.clone(),
+                    _ => unreachable!(),
+                })
+                .collect();
+            layers.push(parents);
+        }
+
+        let root = match layers.last().and_then(|layer| layer.first()) {
+            Some(root) => root.clone(),
+            None => empty_root(algorithm),
+        };
         Self {
-            root: dummy_root,
-            size: 0,
+            layers,
+            root,
+            algorithm,
         }
     }
 
+    /// Returns the hash algorithm of the tree.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
     /// Returns the Merkle root of the tree.
     pub fn root(&self) -> &Hash {
-        &self.root.hash
+        &self.root
     }
 
+    /// Appends `hash` as the last leaf of the tree.
     pub fn push(&mut self, hash: &Hash) {
-        self.size += 1;
+        self.layers[0].push(leaf_hash(self.algorithm, hash));
+        self.update(self.size() - 1);
+    }
+
+    /// Removes the last leaf of the tree.
+    ///
+    /// Returns `false` if the tree is empty.
+    pub fn pop(&mut self) -> bool {
+        if self.size() == 0 {
+            return false;
+        }
+        self.truncate(self.size() - 1);
+        true
+    }
+
+    /// Shortens the tree to its first `len` leaves.
+    ///
+    /// Has no effect if the tree has `len` leaves or fewer.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.size() {
+            self.layers[0].truncate(len);
+            self.update(len.saturating_sub(1));
+        }
+    }
+
+    /// Replaces the leaf at `index` with `hash`.
+    ///
+    /// Returns `false` if there is no leaf at `index`.
+    pub fn replace(&mut self, index: usize, hash: &Hash) -> bool {
+        match self.layers[0].get_mut(index) {
+            Some(leaf) => *leaf = leaf_hash(self.algorithm, hash),
+            None => return false,
+        }
+        self.update(index);
+        true
+    }
+
+    /// Removes the leaf at `index` and shifts the leaves after it to the left.
+    ///
+    /// Like [`Vec::remove`], this preserves the order of the leaves, so the paths of all leaves
+    /// after `index` have to be rehashed. Returns `false` if there is no leaf at `index`.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if index >= self.size() {
+            return false;
+        }
+        let mut leaves = std::mem::take(&mut self.layers[0]);
+        leaves.remove(index);
+        *self = Self::from_hashed_leaves(self.algorithm, leaves);
+        true
+    }
 
-        let left_hash = self.root.left().as_deref().unwrap().hash();
+    /// Removes the leaf at `index` and moves the last leaf into its place.
+    ///
+    /// Like [`Vec::swap_remove`], this does not preserve the order of the leaves, but only the
+    /// paths of the two leaves have to be rehashed. Returns `false` if there is no leaf at `index`.
+    pub fn swap_remove(&mut self, index: usize) -> bool {
+        if index >= self.size() {
+            return false;
+        }
+        self.layers[0].swap_remove(index);
+        // the shape of the tree changes at its end, then the moved leaf gets a new path
+        self.update(self.size().saturating_sub(1));
+        if index < self.size() {
+            self.update(index);
+        }
+        true
+    }
 
-        if let None = &self.root.center {
-            let new_hash = super::sha_all([hash, left_hash, self.root()]);
+    /// Returns the levels of the tree, from the hashed leaves up to the root.
+    pub fn layers(&self) -> &[Vec<Hash>] {
+        &self.layers
+    }
 
-            let mut new_node = MerkleNode::build(new_hash, None, None, None);
+    /// Returns the number of leaves in the tree.
+    pub fn size(&self) -> usize {
+        self.layers[0].len()
+    }
 
-            new_node.left = self.root.left().clone();
+    /// Returns the proof that the leaf at `index` is included in the tree, or `None` if there is no
+    /// such leaf.
+    pub fn proof(&self, index: usize) -> Option<MerkleProof> {
+        if index >= self.size() {
+            return None;
+        }
 
-            self.root.left = None;
-            new_node.center = Some(Box::new(self.root.clone()));
+        let mut path = vec![];
+        let mut position = index;
+        for layer in &self.layers[..self.layers.len() - 1] {
+            // the last node of a level has no sibling if the level is odd
+            if let Some(sibling) = layer.get(position ^ 1) {
+                path.push(sibling.clone());
+            }
+            position /= 2;
+        }
+        Some(MerkleProof {
+            index,
+            size: self.size(),
+            path,
+        })
+    }
 
-            self.root = new_node;
-        } else if let None = self.root.right {
-            let center_hash = self.root.center().as_deref().unwrap().hash();
-            let new_hash = super::sha_all([hash, left_hash, center_hash, self.root()]);
+    /// Returns the proof that the leaves at `indices` are included in the tree, or `None` if there
+    /// are no indices or no leaf at one of them.
+    ///
+    /// The indices may be in any order and repeat; the proof holds them sorted and deduplicated.
+    pub fn multi_proof(&self, indices: &[usize]) -> Option<MerkleMultiProof> {
+        let mut indices = indices.to_vec();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices.last()? >= &self.size() {
+            return None;
+        }
 
-            let mut new_node = MerkleNode::build(new_hash, None, None, None);
+        let mut hashes = vec![];
+        let mut known = indices.clone();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let mut positions = known.iter().peekable();
+            while let Some(&index) = positions.next() {
+                // siblings that are known themselves or can be computed are left out
+                if index % 2 == 1 {
+                    hashes.push(layer[index - 1].clone());
+                } else if index + 1 < layer.len()
+                    && positions.next_if(|&&next| next == index + 1).is_none()
+                {
+                    hashes.push(layer[index + 1].clone());
+                }
+            }
+            known = known.iter().map(|index| index / 2).collect();
+            known.dedup();
+        }
 
-            new_node.left = self.root.left().clone();
-            new_node.center = self.root.center().clone();
+        Some(MerkleMultiProof {
+            indices,
+            size: self.size(),
+            hashes,
+        })
+    }
 
-            self.root.left = None;
-            self.root.center = None;
-            new_node.center = Some(Box::new(self.root.clone()));
+    /// Rehashes the nodes on the path from the leaf at `index` to the root.
+    ///
+    /// Levels are also cut to the length the number of leaves gives them, so after removing leaves
+    /// from the end, updating the path of the new last leaf restores the whole tree. The index of an
+    /// empty tree is ignored.
+    fn update(&mut self, mut index: usize) {
+        let mut level = 0;
+        while self.layers[level].len() > 1 {
+            let layer = &self.layers[level];
+            let parent = match (index % 2, layer.get(index ^ 1)) {
+                (0, Some(right)) => node_hash(self.algorithm, &layer[index], right),
+                (1, Some(left)) => node_hash(self.algorithm, left, &layer[index]),
+                _ => layer[index].clone(),
+            };
+            let parents = layer.len().div_ceil(2);
 
-            self.root = new_node;
-        } else {
-            let center_hash = self.root.center().as_deref().unwrap().hash();
-            let right_hash = self.root.right().as_deref().unwrap().hash();
-            let new_hash = super::sha_all([hash, left_hash, center_hash, right_hash, self.root()]);
+            if self.layers.len() == level + 1 {
+                self.layers.push(vec![]);
+            }
+            let next = &mut self.layers[level + 1];
+            next.truncate(parents);
+            index /= 2;
+            match next.get_mut(index) {
+                Some(node) => *node = parent,
+                None => next.push(parent),
+            }
+            level += 1;
+        }
+        self.layers.truncate(level + 1);
 
-            let mut new_node = MerkleNode::build(new_hash, None, None, None);
+        self.root = match self.layers[level].first() {
+            Some(root) => root.clone(),
+            None => empty_root(self.algorithm),
+        };
+    }
+}
 
-            new_node.left = Some(Box::new(self.root.clone()));
+#[cfg(test)]
+mod tests {
+    use super::{
+        empty_root, leaf_hash, node_hash, verify_multi_proof, verify_proof, MerkleError, MerkleTree,
+    };
+    use crate::{Hash, HashAlgorithm};
 
-            self.root = new_node;
+    /// The root as defined recursively in RFC 6962, section 2.1
+    fn reference_root(algorithm: HashAlgorithm, leaves: &[Hash]) -> Hash {
+        match leaves.len() {
+            0 => empty_root(algorithm),
+            1 => leaf_hash(algorithm, &leaves[0]),
+            n => {
+                let k = n.next_power_of_two() / 2;
+                let left = reference_root(algorithm, &leaves[..k]);
+                let right = reference_root(algorithm, &leaves[k..]);
+                node_hash(algorithm, &left, &right)
+            }
         }
     }
 
-    pub fn pop(&self) -> bool {
-        todo!()
+    #[test]
+    fn root_test() {
+        let tree = MerkleTree::new();
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            tree.root().to_hex()
+        );
+        assert_eq!(tree, MerkleTree::new());
+
+        let mut tree = MerkleTree::new();
+        tree.push(&crate::hash(&"hi"));
+        assert_eq!(
+            leaf_hash(HashAlgorithm::Sha256, &crate::hash(&"hi")),
+            *tree.root()
+        );
+
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let leaves = (0..33i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+            let mut tree = MerkleTree::with_algorithm(algorithm);
+            for (i, leaf) in leaves.iter().enumerate() {
+                tree.push(leaf);
+                assert_eq!(&reference_root(algorithm, &leaves[..=i]), tree.root());
+                assert_eq!(
+                    tree,
+                    MerkleTree::from_leaves(algorithm, &leaves[..=i]),
+                    "{i}"
+                );
+            }
+            assert_eq!(algorithm, tree.root().algorithm());
+        }
     }
 
-    pub fn size(&self) -> usize {
-        self.size
+    #[test]
+    fn domain_separation_test() {
+        let algorithm = HashAlgorithm::Sha256;
+        let [a, b] = [crate::hash(&"a"), crate::hash(&"b")];
+        let tree = MerkleTree::from_leaves(algorithm, [&a, &b]);
+
+        // the root of two leaves is not the root of the single leaf made of their hashes
+        let ab = [
+            leaf_hash(algorithm, &a).to_vec(),
+            leaf_hash(algorithm, &b).to_vec(),
+        ]
+        .concat();
+        assert_ne!(
+            tree.root(),
+            MerkleTree::from_leaves(algorithm, [&ab[..]]).root()
+        );
+        assert_ne!(
+            tree.root(),
+            MerkleTree::from_leaves(algorithm, [&b, &a]).root()
+        );
+    }
+
+    #[test]
+    fn proof_test() {
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let leaves = (0..33i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+            for size in 1..=leaves.len() {
+                let tree = MerkleTree::from_leaves(algorithm, &leaves[..size]);
+                assert_eq!(None, tree.proof(size));
+                for (index, leaf) in leaves[..size].iter().enumerate() {
+                    let proof = tree.proof(index).unwrap();
+                    assert!(proof.path().len() <= 6);
+                    assert_eq!(Ok(()), verify_proof(tree.root(), leaf, &proof));
+
+                    let other = &leaves[(index + 1) % leaves.len()];
+                    assert_eq!(
+                        Err(MerkleError::RootMismatch),
+                        verify_proof(tree.root(), other, &proof)
+                    );
+                }
+            }
+        }
+
+        let algorithm = HashAlgorithm::Sha256;
+        let leaves = (0..5i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+        let tree = MerkleTree::from_leaves(algorithm, &leaves);
+        let proof = tree.proof(2).unwrap();
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let proof = serde_json::from_str::<super::MerkleProof>(&json).unwrap();
+        assert_eq!(Ok(()), proof.verify(tree.root(), &leaves[2]));
+
+        // proofs for another position or size do not fit their path
+        let mut moved = proof.clone();
+        moved.index = 4;
+        assert_eq!(
+            Err(MerkleError::MalformedProof),
+            moved.verify(tree.root(), &leaves[2])
+        );
+        let mut shrunk = proof.clone();
+        shrunk.size = 2;
+        assert_eq!(
+            Err(MerkleError::MalformedProof),
+            shrunk.verify(tree.root(), &leaves[2])
+        );
+        let mut truncated = proof;
+        truncated.path.pop();
+        assert_eq!(
+            Err(MerkleError::MalformedProof),
+            truncated.verify(tree.root(), &leaves[2])
+        );
+    }
+
+    #[test]
+    fn update_test() {
+        let algorithm = HashAlgorithm::Blake3;
+        let hashes = (0..40i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+        let (leaves, others) = hashes.split_at(20);
+
+        for size in 0..=leaves.len() {
+            let full = MerkleTree::from_leaves(algorithm, &leaves[..size]);
+            for len in 0..=size + 1 {
+                let mut tree = full.clone();
+                tree.truncate(len);
+                assert_eq!(
+                    MerkleTree::from_leaves(algorithm, &leaves[..len.min(size)]),
+                    tree
+                );
+            }
+
+            for index in 0..size {
+                let mut tree = full.clone();
+                assert!(tree.replace(index, &others[index]));
+                let mut expected = leaves[..size].to_vec();
+                expected[index] = others[index].clone();
+                assert_eq!(MerkleTree::from_leaves(algorithm, &expected), tree);
+
+                let mut tree = full.clone();
+                assert!(tree.remove(index));
+                let mut expected = leaves[..size].to_vec();
+                expected.remove(index);
+                assert_eq!(MerkleTree::from_leaves(algorithm, &expected), tree);
+
+                let mut tree = full.clone();
+                assert!(tree.swap_remove(index));
+                let mut expected = leaves[..size].to_vec();
+                expected.swap_remove(index);
+                assert_eq!(MerkleTree::from_leaves(algorithm, &expected), tree);
+            }
+
+            let mut tree = full.clone();
+            assert!(!tree.replace(size, &others[0]));
+            assert!(!tree.remove(size));
+            assert!(!tree.swap_remove(size));
+            assert_eq!(full, tree);
+        }
+
+        let mut tree = MerkleTree::from_leaves(algorithm, &leaves[..3]);
+        assert!(tree.pop());
+        assert_eq!(MerkleTree::from_leaves(algorithm, &leaves[..2]), tree);
+        assert!(tree.pop() && tree.pop());
+        assert!(!tree.pop());
+        assert_eq!(MerkleTree::with_algorithm(algorithm), tree);
+    }
+
+    #[test]
+    fn layers_test() {
+        let leaves = (0..11).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+        let tree = MerkleTree::from_leaves(HashAlgorithm::Sha256, &leaves);
+        let layers = tree.layers().to_vec();
+        assert_eq!(5, layers.len());
+        assert_eq!(
+            Ok(tree.clone()),
+            MerkleTree::from_layers(HashAlgorithm::Sha256, layers.clone())
+        );
+
+        let mut tampered = layers.clone();
+        tampered[2][1] = crate::hash(&"tampered");
+        assert_eq!(
+            Err(MerkleError::InvalidTree),
+            MerkleTree::from_layers(HashAlgorithm::Sha256, tampered)
+        );
+        assert_eq!(
+            Err(MerkleError::InvalidTree),
+            MerkleTree::from_layers(HashAlgorithm::Sha256, layers[..4].to_vec())
+        );
+        assert_eq!(
+            Err(MerkleError::InvalidTree),
+            MerkleTree::from_layers(HashAlgorithm::Sha256, vec![])
+        );
+    }
+
+    #[test]
+    fn multi_proof_test() {
+        let algorithm = HashAlgorithm::Sha256;
+        let leaves = (0..37i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+        for size in 1..=leaves.len() {
+            let tree = MerkleTree::from_leaves(algorithm, &leaves[..size]);
+            let sets = [
+                vec![0],
+                vec![size - 1],
+                (0..size).collect(),
+                (0..size).step_by(3).collect(),
+                (size / 2..size).collect(),
+            ];
+            for indices in sets {
+                let proof = tree.multi_proof(&indices).unwrap();
+                let proven = indices
+                    .iter()
+                    .map(|&i| leaves[i].clone())
+                    .collect::<Vec<_>>();
+                assert_eq!(Ok(()), verify_multi_proof(tree.root(), &proven, &proof));
+
+                // shared nodes are only sent once
+                let single = indices
+                    .iter()
+                    .map(|&i| tree.proof(i).unwrap().path().len())
+                    .sum::<usize>();
+                assert!(proof.hashes().len() <= single);
+            }
+            assert_eq!(None, tree.multi_proof(&[]));
+            assert_eq!(None, tree.multi_proof(&[0, size]));
+        }
+
+        let tree = MerkleTree::from_leaves(algorithm, &leaves);
+        let proof = tree.multi_proof(&[12, 3, 4, 3]).unwrap();
+        assert_eq!(&[3, 4, 12], proof.indices());
+        let proven = [3, 4, 12].map(|i| leaves[i].clone());
+        assert_eq!(Ok(()), proof.verify(tree.root(), &proven));
+
+        let json = serde_json::to_string(&proof).unwrap();
+        let proof = serde_json::from_str::<super::MerkleMultiProof>(&json).unwrap();
+        assert_eq!(Ok(()), proof.verify(tree.root(), &proven));
+
+        let swapped = [4, 3, 12].map(|i| leaves[i].clone());
+        assert_eq!(
+            Err(MerkleError::RootMismatch),
+            proof.verify(tree.root(), &swapped)
+        );
+        assert_eq!(
+            Err(MerkleError::MalformedProof),
+            proof.verify(tree.root(), &proven[..2])
+        );
+        let mut truncated = proof.clone();
+        truncated.hashes.pop();
+        assert_eq!(
+            Err(MerkleError::MalformedProof),
+            truncated.verify(tree.root(), &proven)
+        );
+        let mut unsorted = proof;
+        unsorted.indices = vec![4, 3, 12];
+        assert_eq!(
+            Err(MerkleError::MalformedProof),
+            unsorted.verify(tree.root(), &proven)
+        );
     }
 }
@@ -0,0 +1,567 @@
+//! Sparse Merkle trees for committing to key-value state.
+//!
+//! A sparse Merkle tree has a leaf for each of the `2^256` possible keys, almost all of them empty.
+//! The tree here is compacted: an empty subtree is the root of the empty tree, `H("")`, and a
+//! subtree holding a single key is the leaf of that key itself, so a tree with `n` keys is only
+//! about `log2(n)` levels deep.
+//!
+//! * a leaf is hashed as `H(0x00 || key || H(value))`,
+//! * an inner node is hashed as `H(0x01 || left || right)`, as in a [`MerkleTree`](super::MerkleTree),
+//! * the bits of a key, the most significant first, lead from the root to its leaf.
+//!
+//! Nodes are kept in a [`NodeStore`] under their hashes and never removed, so every root ever
+//! computed can still be opened and proven against. [`MemoryNodeStore`] keeps the nodes in memory,
+//! [`SqliteNodeStore`](crate::SqliteNodeStore) in a SQLite database.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::merkle::sparse::{self, MemoryNodeStore, SparseMerkleTree};
+//! use blockify::HashAlgorithm;
+//!
+//! let mut state = SparseMerkleTree::new(MemoryNodeStore::new(), HashAlgorithm::Sha256);
+//! let alice = blockify::hash(&"alice");
+//! let bob = blockify::hash(&"bob");
+//!
+//! state.insert(&alice, b"100").unwrap();
+//! assert_eq!(Some(b"100".to_vec()), state.get(&alice).unwrap());
+//!
+//! let proof = state.prove(&alice).unwrap();
+//! assert!(sparse::verify_membership(state.root(), &alice, b"100", &proof).is_ok());
+//!
+//! let proof = state.prove(&bob).unwrap();
+//! assert!(sparse::verify_non_membership(state.root(), &bob, &proof).is_ok());
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{empty_root, node_hash, LEAF_PREFIX};
+use crate::{
+    error::{DataBaseError, SerdeError},
+    Hash, HashAlgorithm,
+};
+
+/// The number of bits in a key.
+pub const KEY_BITS: usize = 256;
+
+/// Returns the hash of the leaf holding `value` under `key`.
+pub fn leaf_hash(algorithm: HashAlgorithm, key: &Hash, value: &[u8]) -> Hash {
+    hash_leaf(algorithm, key, &algorithm.digest(value))
+}
+
+fn hash_leaf(algorithm: HashAlgorithm, key: &[u8], value_hash: &[u8]) -> Hash {
+    algorithm.digest_all([&[LEAF_PREFIX][..], key, value_hash])
+}
+
+/// Returns the bit of `key` at `depth`, the most significant bit first.
+fn bit(key: &[u8], depth: usize) -> bool {
+    key[depth / 8] >> (7 - depth % 8) & 1 == 1
+}
+
+fn check_key(key: &Hash) -> Result<(), SparseMerkleError> {
+    match key.len() * 8 {
+        KEY_BITS => Ok(()),
+        _ => Err(SparseMerkleError::InvalidKey),
+    }
+}
+
+/// Verifies that `key` holds `value` in the tree with the root `root`, as shown by `proof`.
+pub fn verify_membership(
+    root: &Hash,
+    key: &Hash,
+    value: &[u8],
+    proof: &SparseProof,
+) -> Result<(), SparseMerkleError> {
+    proof.verify(root, key, Some(value))
+}
+
+/// Verifies that `key` holds nothing in the tree with the root `root`, as shown by `proof`.
+pub fn verify_non_membership(
+    root: &Hash,
+    key: &Hash,
+    proof: &SparseProof,
+) -> Result<(), SparseMerkleError> {
+    proof.verify(root, key, None)
+}
+
+/// The types of error that can occur when working with sparse Merkle trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SparseMerkleError {
+    /// The key is not 256 bits long.
+    InvalidKey,
+    /// A node of the tree is not in the store.
+    MissingNode,
+    /// A node of the tree is deeper than any key is long.
+    InvalidNode,
+    /// The store could not be read or written.
+    DataBaseError(DataBaseError),
+    /// A node could not be serialized or deserialized.
+    SerdeError(SerdeError),
+    /// The proof does not fit the key or value.
+    MalformedProof,
+    /// The proof leads to a different root.
+    RootMismatch,
+}
+
+crate::impl_display_error!(SparseMerkleError);
+
+/// A node of a sparse Merkle tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SparseNode {
+    /// The only key of its subtree, with its value.
+    Leaf { key: Hash, value: Vec<u8> },
+    /// A node with at least two keys below it.
+    Inner { left: Hash, right: Hash },
+}
+
+impl SparseNode {
+    /// Returns the hash of the node.
+    pub fn hash(&self, algorithm: HashAlgorithm) -> Hash {
+        match self {
+            SparseNode::Leaf { key, value } => leaf_hash(algorithm, key, value),
+            SparseNode::Inner { left, right } => node_hash(algorithm, left, right),
+        }
+    }
+}
+
+/// A store of the nodes of sparse Merkle trees, addressed by their hashes.
+pub trait NodeStore {
+    /// Returns the node with the hash `hash`, or `None` if it is not in the store.
+    fn get(&self, hash: &Hash) -> Result<Option<SparseNode>, SparseMerkleError>;
+
+    /// Stores `node` under its hash `hash`.
+    fn put(&mut self, hash: &Hash, node: &SparseNode) -> Result<(), SparseMerkleError>;
+}
+
+/// A [`NodeStore`] that keeps the nodes in memory.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryNodeStore {
+    nodes: BTreeMap<Box<[u8]>, SparseNode>,
+}
+
+impl MemoryNodeStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl NodeStore for MemoryNodeStore {
+    fn get(&self, hash: &Hash) -> Result<Option<SparseNode>, SparseMerkleError> {
+        Ok(self.nodes.get(hash.as_bytes()).cloned())
+    }
+
+    fn put(&mut self, hash: &Hash, node: &SparseNode) -> Result<(), SparseMerkleError> {
+        self.nodes.insert(hash.as_bytes().into(), node.clone());
+        Ok(())
+    }
+}
+
+/// A proof that a key holds a value, or nothing, in a sparse Merkle tree.
+///
+/// The proof holds the siblings on the path of the key from the root down to the first empty
+/// subtree or leaf, and that leaf. For a key without a value, the leaf is the one of another key
+/// sharing the path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SparseProof {
+    siblings: Vec<Hash>,
+    // the key of the leaf ending the path and the hash of its value
+    leaf: Option<(Hash, Hash)>,
+}
+
+impl SparseProof {
+    /// Returns the siblings on the path of the key, starting at the root.
+    pub fn siblings(&self) -> &[Hash] {
+        &self.siblings
+    }
+
+    /// Computes the root of the tree in which `key` holds `value`, or nothing if `value` is `None`,
+    /// according to this proof.
+    pub fn root(
+        &self,
+        algorithm: HashAlgorithm,
+        key: &Hash,
+        value: Option<&[u8]>,
+    ) -> Result<Hash, SparseMerkleError> {
+        check_key(key)?;
+        let depth = self.siblings.len();
+        if depth >= KEY_BITS {
+            return Err(SparseMerkleError::MalformedProof);
+        }
+
+        let mut hash = match (value, &self.leaf) {
+            (Some(value), Some((other, _))) if other[..] == key[..] => {
+                leaf_hash(algorithm, key, value)
+            }
+            (None, None) => empty_root(algorithm),
+            // another key can only end the path if it shares the path
+            (None, Some((other, value_hash)))
+                if other.len() == key.len()
+                    && other[..] != key[..]
+                    && (0..depth).all(|depth| bit(other, depth) == bit(key, depth)) =>
+            {
+                hash_leaf(algorithm, other, value_hash)
+            }
+            _ => return Err(SparseMerkleError::MalformedProof),
+        };
+
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            hash = match bit(key, depth) {
+                false => node_hash(algorithm, &hash, sibling),
+                true => node_hash(algorithm, sibling, &hash),
+            };
+        }
+        Ok(hash)
+    }
+
+    /// Verifies that `key` holds `value`, or nothing if `value` is `None`, in the tree with the
+    /// root `root`.
+    pub fn verify(
+        &self,
+        root: &Hash,
+        key: &Hash,
+        value: Option<&[u8]>,
+    ) -> Result<(), SparseMerkleError> {
+        match self.root(root.algorithm(), key, value)? == *root {
+            true => Ok(()),
+            false => Err(SparseMerkleError::RootMismatch),
+        }
+    }
+}
+
+/// A sparse Merkle tree mapping 256-bit keys to values.
+///
+/// Every change creates the nodes on the path of the key from the new root and leaves the old ones
+/// in the store.
+pub struct SparseMerkleTree<S> {
+    store: S,
+    root: Hash,
+}
+
+impl<S: NodeStore> SparseMerkleTree<S> {
+    /// Creates an empty tree whose nodes are hashed with `algorithm` and kept in `store`.
+    pub fn new(store: S, algorithm: HashAlgorithm) -> Self {
+        Self::open(store, empty_root(algorithm))
+    }
+
+    /// Opens the tree with the root `root`, whose nodes are in `store`.
+    pub fn open(store: S, root: Hash) -> Self {
+        Self { store, root }
+    }
+
+    /// Returns the root of the tree.
+    pub fn root(&self) -> &Hash {
+        &self.root
+    }
+
+    /// Returns the hash algorithm of the tree.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.root.algorithm()
+    }
+
+    /// Returns the store of the tree.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the store of the tree, consuming the tree.
+    pub fn into_store(self) -> S {
+        self.store
+    }
+
+    /// Returns the value of `key`, if any.
+    pub fn get(&self, key: &Hash) -> Result<Option<Vec<u8>>, SparseMerkleError> {
+        check_key(key)?;
+        let mut hash = self.root.clone();
+        for depth in 0..KEY_BITS {
+            hash = match self.node(&hash)? {
+                None => return Ok(None),
+                Some(SparseNode::Leaf { key: other, value }) => {
+                    return Ok((other[..] == key[..]).then_some(value))
+                }
+                Some(SparseNode::Inner { left, right }) => match bit(key, depth) {
+                    false => left,
+                    true => right,
+                },
+            };
+        }
+        Err(SparseMerkleError::InvalidNode)
+    }
+
+    /// Sets the value of `key` to `value` and returns its previous value, if any.
+    pub fn insert(
+        &mut self,
+        key: &Hash,
+        value: &[u8],
+    ) -> Result<Option<Vec<u8>>, SparseMerkleError> {
+        let previous = self.get(key)?;
+        let leaf = self.put(SparseNode::Leaf {
+            key: key.clone(),
+            value: value.to_vec(),
+        })?;
+        self.root = self.insert_at(self.root.clone(), 0, key, leaf)?;
+        Ok(previous)
+    }
+
+    /// Removes `key` from the tree and returns its value, if any.
+    pub fn remove(&mut self, key: &Hash) -> Result<Option<Vec<u8>>, SparseMerkleError> {
+        let previous = self.get(key)?;
+        if previous.is_some() {
+            self.root = self.remove_at(self.root.clone(), 0, key)?;
+        }
+        Ok(previous)
+    }
+
+    /// Returns the proof that `key` holds its value, or nothing if it has none.
+    pub fn prove(&self, key: &Hash) -> Result<SparseProof, SparseMerkleError> {
+        check_key(key)?;
+        let mut siblings = vec![];
+        let mut hash = self.root.clone();
+        for depth in 0..KEY_BITS {
+            hash = match self.node(&hash)? {
+                None => {
+                    return Ok(SparseProof {
+                        siblings,
+                        leaf: None,
+                    })
+                }
+                Some(SparseNode::Leaf { key, value }) => {
+                    let leaf = Some((key, self.algorithm().digest(value)));
+                    return Ok(SparseProof { siblings, leaf });
+                }
+                Some(SparseNode::Inner { left, right }) => {
+                    let (sibling, next) = match bit(key, depth) {
+                        false => (right, left),
+                        true => (left, right),
+                    };
+                    siblings.push(sibling);
+                    next
+                }
+            };
+        }
+        Err(SparseMerkleError::InvalidNode)
+    }
+
+    /// Returns the node with the hash `hash`, or `None` for the empty subtree.
+    fn node(&self, hash: &Hash) -> Result<Option<SparseNode>, SparseMerkleError> {
+        if *hash == empty_root(self.algorithm()) {
+            return Ok(None);
+        }
+        match self.store.get(hash)? {
+            Some(node) => Ok(Some(node)),
+            None => Err(SparseMerkleError::MissingNode),
+        }
+    }
+
+    fn put(&mut self, node: SparseNode) -> Result<Hash, SparseMerkleError> {
+        let hash = node.hash(self.algorithm());
+        self.store.put(&hash, &node)?;
+        Ok(hash)
+    }
+
+    fn is_leaf(&self, hash: &Hash) -> Result<bool, SparseMerkleError> {
+        Ok(matches!(self.node(hash)?, Some(SparseNode::Leaf { .. })))
+    }
+
+    /// Puts `leaf`, the leaf of `key`, into the subtree `hash` at `depth`.
+    fn insert_at(
+        &mut self,
+        hash: Hash,
+        depth: usize,
+        key: &Hash,
+        leaf: Hash,
+    ) -> Result<Hash, SparseMerkleError> {
+        if depth >= KEY_BITS {
+            return Err(SparseMerkleError::InvalidNode);
+        }
+
+        match self.node(&hash)? {
+            None => Ok(leaf),
+            Some(SparseNode::Leaf { key: other, .. }) if other[..] == key[..] => Ok(leaf),
+            Some(SparseNode::Leaf { key: other, .. }) => {
+                self.split(depth, (hash, &other), (leaf, key))
+            }
+            Some(SparseNode::Inner { left, right }) => {
+                let (left, right) = match bit(key, depth) {
+                    false => (self.insert_at(left, depth + 1, key, leaf)?, right),
+                    true => (left, self.insert_at(right, depth + 1, key, leaf)?),
+                };
+                self.put(SparseNode::Inner { left, right })
+            }
+        }
+    }
+
+    /// Builds the subtree at `depth` holding the two leaves `a` and `b` with their keys.
+    fn split(
+        &mut self,
+        depth: usize,
+        a: (Hash, &Hash),
+        b: (Hash, &Hash),
+    ) -> Result<Hash, SparseMerkleError> {
+        if depth >= KEY_BITS {
+            return Err(SparseMerkleError::InvalidNode);
+        }
+
+        let empty = empty_root(self.algorithm());
+        let (left, right) = match (bit(a.1, depth), bit(b.1, depth)) {
+            (false, true) => (a.0, b.0),
+            (true, false) => (b.0, a.0),
+            (false, false) => (self.split(depth + 1, a, b)?, empty),
+            (true, true) => (empty, self.split(depth + 1, a, b)?),
+        };
+        self.put(SparseNode::Inner { left, right })
+    }
+
+    /// Removes the leaf of `key`, which must be in the subtree `hash` at `depth`.
+    fn remove_at(
+        &mut self,
+        hash: Hash,
+        depth: usize,
+        key: &Hash,
+    ) -> Result<Hash, SparseMerkleError> {
+        if depth >= KEY_BITS {
+            return Err(SparseMerkleError::InvalidNode);
+        }
+
+        let empty = empty_root(self.algorithm());
+        let (left, right) = match self.node(&hash)? {
+            Some(SparseNode::Inner { left, right }) => (left, right),
+            _ => return Ok(empty),
+        };
+        let (child, sibling) = match bit(key, depth) {
+            false => (left, right),
+            true => (right, left),
+        };
+        let child = self.remove_at(child, depth + 1, key)?;
+
+        // a subtree left with a single key is the leaf of that key
+        if child == empty && self.is_leaf(&sibling)? {
+            return Ok(sibling);
+        }
+        if sibling == empty && self.is_leaf(&child)? {
+            return Ok(child);
+        }
+        let (left, right) = match bit(key, depth) {
+            false => (child, sibling),
+            true => (sibling, child),
+        };
+        self.put(SparseNode::Inner { left, right })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        verify_membership, verify_non_membership, MemoryNodeStore, SparseMerkleError,
+        SparseMerkleTree,
+    };
+    use crate::{merkle::empty_root, Hash, HashAlgorithm};
+
+    fn tree(
+        algorithm: HashAlgorithm,
+        pairs: &[(Hash, Vec<u8>)],
+    ) -> SparseMerkleTree<MemoryNodeStore> {
+        let mut tree = SparseMerkleTree::new(MemoryNodeStore::new(), algorithm);
+        for (key, value) in pairs {
+            tree.insert(key, value).unwrap();
+        }
+        tree
+    }
+
+    #[test]
+    fn update_test() {
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let pairs = (0..50i64)
+                .map(|i| (crate::hash(&i), i.to_be_bytes().to_vec()))
+                .collect::<Vec<_>>();
+            let full = tree(algorithm, &pairs);
+            for (key, value) in &pairs {
+                assert_eq!(Some(value.clone()), full.get(key).unwrap());
+            }
+            assert_eq!(None, full.get(&crate::hash(&"absent")).unwrap());
+
+            // the root does not depend on the order of insertion
+            let mut reversed = pairs.clone();
+            reversed.reverse();
+            assert_eq!(full.root(), tree(algorithm, &reversed).root());
+
+            // removing keys gives the root of the tree that never had them
+            let mut shrinking = tree(algorithm, &pairs);
+            for i in 0..pairs.len() {
+                let (key, value) = &pairs[i];
+                assert_eq!(Some(value.clone()), shrinking.remove(key).unwrap());
+                assert_eq!(None, shrinking.remove(key).unwrap());
+                assert_eq!(tree(algorithm, &pairs[i + 1..]).root(), shrinking.root());
+            }
+            assert_eq!(&empty_root(algorithm), shrinking.root());
+
+            // updating a value changes the root, restoring it restores the root
+            let mut updated = tree(algorithm, &pairs);
+            let (key, value) = &pairs[7];
+            assert_eq!(Some(value.clone()), updated.insert(key, b"new").unwrap());
+            assert_ne!(full.root(), updated.root());
+            updated.insert(key, value).unwrap();
+            assert_eq!(full.root(), updated.root());
+
+            // old roots stay readable
+            let old = SparseMerkleTree::open(updated.into_store(), shrinking.root().clone());
+            assert_eq!(None, old.get(key).unwrap());
+        }
+
+        let mut tree = SparseMerkleTree::new(MemoryNodeStore::new(), HashAlgorithm::Sha256);
+        let short = Hash::from(vec![0; 20]);
+        assert_eq!(Err(SparseMerkleError::InvalidKey), tree.insert(&short, b""));
+    }
+
+    #[test]
+    fn proof_test() {
+        let algorithm = HashAlgorithm::Sha256;
+        let pairs = (0..20i64)
+            .map(|i| (crate::hash(&i), i.to_be_bytes().to_vec()))
+            .collect::<Vec<_>>();
+        let tree = tree(algorithm, &pairs);
+        let root = tree.root();
+
+        for (key, value) in &pairs {
+            let proof = tree.prove(key).unwrap();
+            assert_eq!(Ok(()), verify_membership(root, key, value, &proof));
+            assert_eq!(
+                Err(SparseMerkleError::RootMismatch),
+                verify_membership(root, key, b"other", &proof)
+            );
+            assert_eq!(
+                Err(SparseMerkleError::MalformedProof),
+                verify_non_membership(root, key, &proof)
+            );
+        }
+
+        for i in 20..60i64 {
+            let key = crate::hash(&i);
+            let proof = tree.prove(&key).unwrap();
+            assert_eq!(Ok(()), verify_non_membership(root, &key, &proof));
+            assert_eq!(
+                Err(SparseMerkleError::MalformedProof),
+                verify_membership(root, &key, b"", &proof)
+            );
+
+            let json = serde_json::to_string(&proof).unwrap();
+            let proof = serde_json::from_str::<super::SparseProof>(&json).unwrap();
+            assert_eq!(Ok(()), proof.verify(root, &key, None));
+        }
+
+        // a proof for one key does not prove anything about another one
+        let proof = tree.prove(&pairs[0].0).unwrap();
+        assert!(verify_membership(root, &pairs[1].0, &pairs[1].1, &proof).is_err());
+        assert!(verify_non_membership(root, &pairs[1].0, &proof).is_err());
+
+        let empty = SparseMerkleTree::new(MemoryNodeStore::new(), algorithm);
+        let proof = empty.prove(&pairs[0].0).unwrap();
+        assert!(proof.siblings().is_empty());
+        assert_eq!(
+            Ok(()),
+            verify_non_membership(empty.root(), &pairs[0].0, &proof)
+        );
+    }
+}
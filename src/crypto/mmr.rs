@@ -0,0 +1,416 @@
+//! Merkle Mountain Ranges over the hashes of blocks.
+//!
+//! A Merkle Mountain Range (MMR) is an append-only list of perfect binary Merkle trees, the
+//! *peaks*, whose heights are the set bits of the number of leaves. Appending a leaf merges the
+//! peaks of equal height, so only `O(log n)` nodes are added and no existing node ever changes.
+//!
+//! Nodes are hashed as in [`merkle`](super::merkle): a leaf as `H(0x00 || leaf)` and an inner node
+//! as `H(0x01 || left || right)`. The root bags the peaks from right to left, i.e. for the peaks
+//! `p1, ..., pk` from the highest to the lowest it is `node(p1, node(p2, ... node(pk-1, pk)))`,
+//! and the root of the empty range is `H("")`.
+//!
+//! The nodes are kept in the order they are created, so a stored range only grows at its end.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::mmr::{self, MerkleMountainRange};
+//!
+//! let blocks = (0..11i64).map(|i| blockify::hash(&i)).collect::<Vec<_>>();
+//!
+//! let mut mmr = MerkleMountainRange::new();
+//! for block in &blocks {
+//!     mmr.push(block);
+//! }
+//! assert_eq!(3, mmr.peaks().len());
+//!
+//! let proof = mmr.proof(6).unwrap();
+//! assert!(mmr::verify_proof(&mmr.root(), &blocks[6], &proof).is_ok());
+//! assert!(mmr::verify_proof(&mmr.root(), &blocks[5], &proof).is_err());
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    merkle::{empty_root, leaf_hash, node_hash},
+    Hash, HashAlgorithm,
+};
+
+/// Returns the heights of the peaks of a range with `leaves` leaves, the highest first.
+fn peak_heights(leaves: u64) -> impl Iterator<Item = u32> {
+    (0..u64::BITS)
+        .rev()
+        .filter(move |height| leaves >> height & 1 == 1)
+}
+
+/// Returns the number of nodes in a perfect tree of height `height`.
+fn tree_size(height: u32) -> u64 {
+    (2 << height) - 1
+}
+
+/// Bags `peaks` from right to left into the root of the range.
+fn bag(algorithm: HashAlgorithm, peaks: &[Hash]) -> Hash {
+    match peaks.split_last() {
+        Some((last, rest)) => rest
+            .iter()
+            .rev()
+            .fold(last.clone(), |bag, peak| node_hash(algorithm, peak, &bag)),
+        None => empty_root(algorithm),
+    }
+}
+
+/// Verifies that `leaf` is included in the range with the root `root`, as shown by `proof`.
+///
+/// `leaf` is the unhashed leaf, e.g. the hash of a block, and the nodes are hashed with the
+/// algorithm of `root`. The proof is only for the leaf at [`MmrProof::index`], so callers expecting
+/// a certain position should also check it.
+pub fn verify_proof(root: &Hash, leaf: &Hash, proof: &MmrProof) -> Result<(), MmrError> {
+    match proof.root(root.algorithm(), leaf)? == *root {
+        true => Ok(()),
+        false => Err(MmrError::RootMismatch),
+    }
+}
+
+/// The types of error that can occur when working with Merkle Mountain Ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MmrError {
+    /// The number of nodes does not belong to any range.
+    InvalidNodeCount,
+    /// The path or peaks of the proof do not fit its index and size.
+    MalformedProof,
+    /// The proof leads to a different root.
+    RootMismatch,
+}
+
+crate::impl_display_error!(MmrError);
+
+/// A proof that a leaf is included in a Merkle Mountain Range.
+///
+/// The proof holds the siblings on the path from the leaf to its peak, starting at the leaf, and
+/// the other peaks of the range from the highest to the lowest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MmrProof {
+    index: u64,
+    size: u64,
+    path: Vec<Hash>,
+    peaks: Vec<Hash>,
+}
+
+impl MmrProof {
+    /// Returns the index of the proven leaf.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Returns the number of leaves in the range.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the sibling hashes on the path from the leaf to its peak.
+    pub fn path(&self) -> &[Hash] {
+        &self.path
+    }
+
+    /// Returns the peaks of the range other than the one of the leaf.
+    pub fn peaks(&self) -> &[Hash] {
+        &self.peaks
+    }
+
+    /// Computes the root of the range that `leaf` is included in according to this proof.
+    pub fn root(&self, algorithm: HashAlgorithm, leaf: &Hash) -> Result<Hash, MmrError> {
+        if self.index >= self.size {
+            return Err(MmrError::MalformedProof);
+        }
+
+        let mut others = self.peaks.iter();
+        let mut peaks = vec![];
+        let mut first = 0;
+        for height in peak_heights(self.size) {
+            let leaves = 1 << height;
+            if (first..first + leaves).contains(&self.index) {
+                if self.path.len() != height as usize {
+                    return Err(MmrError::MalformedProof);
+                }
+                let index = self.index - first;
+                let mut hash = leaf_hash(algorithm, leaf);
+                for (level, sibling) in self.path.iter().enumerate() {
+                    hash = match index >> level & 1 {
+                        0 => node_hash(algorithm, &hash, sibling),
+                        _ => node_hash(algorithm, sibling, &hash),
+                    };
+                }
+                peaks.push(hash);
+            } else {
+                let peak = others.next().ok_or(MmrError::MalformedProof)?;
+                peaks.push(peak.clone());
+            }
+            first += leaves;
+        }
+
+        match others.next() {
+            Some(_) => Err(MmrError::MalformedProof),
+            None => Ok(bag(algorithm, &peaks)),
+        }
+    }
+
+    /// Verifies that `leaf` is included in the range with the root `root`.
+    ///
+    /// This is the same as [`verify_proof`].
+    pub fn verify(&self, root: &Hash, leaf: &Hash) -> Result<(), MmrError> {
+        verify_proof(root, leaf, self)
+    }
+}
+
+/// A Merkle Mountain Range.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleMountainRange {
+    // all nodes in the order they were created
+    nodes: Vec<Hash>,
+    leaves: u64,
+    #[serde(default)]
+    algorithm: HashAlgorithm,
+}
+
+impl MerkleMountainRange {
+    /// Creates a new, empty range hashed with SHA-256.
+    pub fn new() -> Self {
+        Self::with_algorithm(HashAlgorithm::Sha256)
+    }
+
+    /// Creates a new, empty range whose nodes are hashed with `algorithm`.
+    pub fn with_algorithm(algorithm: HashAlgorithm) -> Self {
+        Self {
+            nodes: vec![],
+            leaves: 0,
+            algorithm,
+        }
+    }
+
+    /// Restores a range from its nodes, as returned by [`MerkleMountainRange::nodes`].
+    ///
+    /// Fails with `InvalidNodeCount` if no range has as many nodes as `nodes`.
+    pub fn from_nodes(algorithm: HashAlgorithm, nodes: Vec<Hash>) -> Result<Self, MmrError> {
+        let mut remaining = nodes.len() as u64;
+        let mut leaves = 0;
+        for height in (0..u64::BITS - 1).rev() {
+            if remaining >= tree_size(height) {
+                remaining -= tree_size(height);
+                leaves |= 1 << height;
+            }
+        }
+
+        match remaining {
+            0 => Ok(Self {
+                nodes,
+                leaves,
+                algorithm,
+            }),
+            _ => Err(MmrError::InvalidNodeCount),
+        }
+    }
+
+    /// Returns the hash algorithm of the range.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Returns the number of leaves in the range.
+    pub fn size(&self) -> u64 {
+        self.leaves
+    }
+
+    /// Returns all nodes of the range in the order they were created.
+    pub fn nodes(&self) -> &[Hash] {
+        &self.nodes
+    }
+
+    /// Appends `hash` as the last leaf of the range.
+    pub fn push(&mut self, hash: &Hash) {
+        let mut node = leaf_hash(self.algorithm, hash);
+        self.nodes.push(node.clone());
+        // every trailing one of the old number of leaves is a peak to merge with
+        for height in 0..self.leaves.trailing_ones() {
+            let left = &self.nodes[self.nodes.len() - 1 - tree_size(height) as usize];
+            node = node_hash(self.algorithm, left, &node);
+            self.nodes.push(node.clone());
+        }
+        self.leaves += 1;
+    }
+
+    /// Returns the peaks of the range from the highest to the lowest.
+    pub fn peaks(&self) -> Vec<Hash> {
+        let mut end = 0;
+        peak_heights(self.leaves)
+            .map(|height| {
+                end += tree_size(height);
+                self.nodes[end as usize - 1].clone()
+            })
+            .collect()
+    }
+
+    /// Returns the root of the range.
+    pub fn root(&self) -> Hash {
+        bag(self.algorithm, &self.peaks())
+    }
+
+    /// Returns the proof that the leaf at `index` is included in the range, or `None` if there is
+    /// no such leaf.
+    pub fn proof(&self, index: u64) -> Option<MmrProof> {
+        if index >= self.leaves {
+            return None;
+        }
+
+        let mut path = vec![];
+        let mut peaks = vec![];
+        let (mut first, mut end) = (0, 0);
+        for height in peak_heights(self.leaves) {
+            end += tree_size(height);
+            if (first..first + (1 << height)).contains(&index) {
+                path = self.path(end - 1, height, index - first);
+            } else {
+                peaks.push(self.nodes[end as usize - 1].clone());
+            }
+            first += 1 << height;
+        }
+
+        Some(MmrProof {
+            index,
+            size: self.leaves,
+            path,
+            peaks,
+        })
+    }
+
+    /// Returns the siblings on the path to the leaf at `index` of the perfect tree of height
+    /// `height` whose root is the node `top`, starting at the leaf.
+    fn path(&self, top: u64, height: u32, index: u64) -> Vec<Hash> {
+        let mut path = vec![];
+        let mut node = top;
+        for height in (0..height).rev() {
+            // the left child is followed by the right subtree, which has `2^height - 1` nodes
+            let (left, right) = (node - (1 << (height + 1)), node - 1);
+            let (sibling, next) = match index >> height & 1 {
+                0 => (right, left),
+                _ => (left, right),
+            };
+            path.push(self.nodes[sibling as usize].clone());
+            node = next;
+        }
+        path.reverse();
+        path
+    }
+}
+
+impl Default for MerkleMountainRange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_proof, MerkleMountainRange, MmrError};
+    use crate::{
+        merkle::{leaf_hash, node_hash, MerkleTree},
+        Hash, HashAlgorithm,
+    };
+
+    #[test]
+    fn root_test() {
+        let algorithm = HashAlgorithm::Sha256;
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            MerkleMountainRange::new().root().to_hex()
+        );
+
+        let leaves = (0..7i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+        let mut mmr = MerkleMountainRange::new();
+        for leaf in &leaves {
+            mmr.push(leaf);
+        }
+        assert_eq!(11, mmr.nodes().len());
+
+        // the peaks are the Merkle trees of 4, 2 and 1 leaves
+        let peaks = [&leaves[..4], &leaves[4..6], &leaves[6..]]
+            .map(|leaves| MerkleTree::from_leaves(algorithm, leaves).root().clone());
+        assert_eq!(peaks.to_vec(), mmr.peaks());
+        assert_eq!(leaf_hash(algorithm, &leaves[6]), peaks[2]);
+        let root = node_hash(
+            algorithm,
+            &peaks[0],
+            &node_hash(algorithm, &peaks[1], &peaks[2]),
+        );
+        assert_eq!(root, mmr.root());
+
+        let restored = MerkleMountainRange::from_nodes(algorithm, mmr.nodes().to_vec()).unwrap();
+        assert_eq!(mmr, restored);
+        for len in [2, 5, 6, 9] {
+            assert_eq!(
+                Err(MmrError::InvalidNodeCount),
+                MerkleMountainRange::from_nodes(algorithm, mmr.nodes()[..len].to_vec())
+            );
+        }
+    }
+
+    #[test]
+    fn proof_test() {
+        for algorithm in [HashAlgorithm::Sha256, HashAlgorithm::Blake3] {
+            let leaves = (0..40i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+            let mut mmr = MerkleMountainRange::with_algorithm(algorithm);
+            let mut roots: Vec<Hash> = vec![];
+            for leaf in &leaves {
+                mmr.push(leaf);
+                let root = mmr.root();
+                assert!(!roots.contains(&root));
+                roots.push(root);
+
+                for (index, leaf) in leaves[..mmr.size() as usize].iter().enumerate() {
+                    let proof = mmr.proof(index as u64).unwrap();
+                    assert_eq!(Ok(()), verify_proof(&mmr.root(), leaf, &proof));
+                }
+                assert_eq!(None, mmr.proof(mmr.size()));
+            }
+
+            // old proofs do not verify against newer roots
+            let proof = mmr.proof(3).unwrap();
+            assert_eq!(
+                Err(MmrError::RootMismatch),
+                verify_proof(&roots[20], &leaves[3], &proof)
+            );
+            assert_eq!(
+                Err(MmrError::RootMismatch),
+                verify_proof(&mmr.root(), &leaves[4], &proof)
+            );
+        }
+
+        let leaves = (0..11i64).map(|i| crate::hash(&i)).collect::<Vec<_>>();
+        let mut mmr = MerkleMountainRange::new();
+        for leaf in &leaves {
+            mmr.push(leaf);
+        }
+        let proof = mmr.proof(9).unwrap();
+        let json = serde_json::to_string(&proof).unwrap();
+        let proof = serde_json::from_str::<super::MmrProof>(&json).unwrap();
+        assert_eq!(Ok(()), proof.verify(&mmr.root(), &leaves[9]));
+
+        let mut moved = proof.clone();
+        moved.index = 2;
+        assert_eq!(
+            Err(MmrError::MalformedProof),
+            moved.verify(&mmr.root(), &leaves[9])
+        );
+        let mut grown = proof.clone();
+        grown.size = 12;
+        assert_eq!(
+            Err(MmrError::MalformedProof),
+            grown.verify(&mmr.root(), &leaves[9])
+        );
+        let mut pruned = proof;
+        pruned.peaks.pop();
+        assert_eq!(
+            Err(MmrError::MalformedProof),
+            pruned.verify(&mmr.root(), &leaves[9])
+        );
+    }
+}
@@ -4,14 +4,35 @@ use rand::{thread_rng, Rng};
 
 use sha2::{Digest, Sha256};
 
+use zeroize::Zeroize;
+
+pub mod address;
+pub mod batch;
+pub mod bls;
+pub mod canonical;
+pub mod digest;
+pub mod domain;
+pub mod encoding;
+pub mod encryption;
+pub mod hd;
+pub mod keystore;
 pub mod merkle;
+pub mod mmr;
+pub mod signer;
+
+pub use address::Address;
+pub use digest::HashAlgorithm;
+pub use domain::SigningDomain;
+pub use signer::Signer;
 
 /// An error that can occur while signing a piece of message
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SigningError {
     KeyRejected,
     Unspecified,
     SerdeError(SerdeError),
+    /// The signer could not be reached, such as a remote signer that has gone away.
+    Unavailable,
 }
 
 impl std::fmt::Display for SigningError {
@@ -35,13 +56,17 @@ impl From<ring::error::Unspecified> for SigningError {
 }
 
 /// An error that can occur while verifying a digital signature
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VerificationError {
     InvalidSignature,
     NoMatch,
     BadKey,
     Unspecified,
     SerdeError(SerdeError),
+    /// A multi-signed record does not carry enough valid signatures.
+    ThresholdNotMet,
+    /// The signer's key was rotated or revoked, or not yet in use, at the height of the record.
+    KeyNotValid,
 }
 
 impl Error for VerificationError {}
@@ -58,8 +83,48 @@ impl From<ring::error::Unspecified> for VerificationError {
     }
 }
 
+/// An error that can occur while generating or loading a cryptographic key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyError {
+    /// The key was parsed but is not acceptable for the requested algorithm.
+    KeyRejected,
+    /// The key material is not in a recognized encoding.
+    InvalidEncoding,
+    /// The requested key size is not supported.
+    UnsupportedSize,
+    /// The key belongs to a different algorithm than the one requested.
+    AlgorithmMismatch,
+    /// The operation or encoding is not available for the key's algorithm.
+    UnsupportedAlgorithm,
+    /// The cryptographic backend failed without giving a reason, for example when no randomness is available.
+    Unspecified,
+}
+
+impl Error for KeyError {}
+
+impl std::fmt::Display for KeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+impl From<ring::error::KeyRejected> for KeyError {
+    fn from(_: ring::error::KeyRejected) -> Self {
+        KeyError::KeyRejected
+    }
+}
+
+impl From<ring::error::Unspecified> for KeyError {
+    fn from(_: ring::error::Unspecified) -> Self {
+        KeyError::Unspecified
+    }
+}
+
 /// Hashes the provided data using the SHA-256 algorithm and returns the computed hash.
 ///
+/// This is the hash records are identified by, see [`Record::hash`]. Use [`hash_with`] for the
+/// algorithm of a chain.
+///
 /// # Arguments
 ///
 /// * `data` - The data to be hashed. It must implement the `serde::Serialize` trait.
@@ -68,14 +133,33 @@ impl From<ring::error::Unspecified> for VerificationError {
 ///
 /// The computed hash as a `Hash` type.
 pub fn hash<T: Sized + serde::Serialize>(data: &T) -> Hash {
-    // Serialize the input data into a binary format using the `bincode` crate.
-    let bytes = bincode::serialize(data).unwrap();
-    let buffer = hash_bytes(&bytes);
-    buffer.into()
+    hash_with(HashAlgorithm::Sha256, data)
+}
+
+/// Hashes the provided data using the given hash algorithm and returns the computed hash.
+///
+/// # Arguments
+///
+/// * `algorithm` - The hash algorithm to use.
+/// * `data` - The data to be hashed. It must implement the `serde::Serialize` trait.
+///
+/// # Returns
+///
+/// The computed hash as a `Hash` type, tagged with `algorithm`.
+///
+/// # Panics
+///
+/// Panics if `data` has no canonical encoding, see [`canonical`].
+pub fn hash_with<T: Sized + serde::Serialize>(algorithm: HashAlgorithm, data: &T) -> Hash {
+    // Serialize the input data into its canonical binary format.
+    let bytes = canonical::encode(data).unwrap();
+    algorithm.digest(bytes)
 }
 
 /// Hashes the given byte slice using the SHA-256 algorithm and returns the resulting hash as a byte vector.
 ///
+/// Use [`HashAlgorithm::digest`] for the algorithm of a chain.
+///
 /// # Arguments
 ///
 /// * `bytes` - The binary data to be hashed.
@@ -102,7 +186,10 @@ use crate::{
 };
 use serde::{Deserialize, Serialize};
 
-/// Hashes a block of records along with other parameters to compute the block's hash using the SHA-256 algorithm.
+/// Hashes a block of records along with other parameters to compute the block's hash.
+///
+/// The hash algorithm is the one of the block's Merkle tree, see [`LocalInstance::hash_algorithm`].
+/// The records, timestamp and position are hashed in their [`canonical`] encoding.
 ///
 /// # Arguments
 ///
@@ -120,17 +207,22 @@ pub fn hash_block<R: Record + Serialize>(
     timestamp: &Timestamp,
     position: &Position,
 ) -> Hash {
-    let records = bincode::serialize(block.get_records()).unwrap().into();
-    let timestamp = bincode::serialize(timestamp).unwrap().into();
-    let position = bincode::serialize(position).unwrap().into();
-    let buffer = sha_all([
-        prevhash,
-        &records,
-        &block.get_merkle_root(),
-        &timestamp,
-        &position,
-    ]);
-    buffer.into()
+    let records = canonical::encode(block.get_records()).unwrap().into();
+    let timestamp = canonical::encode(timestamp).unwrap().into();
+    let position = canonical::encode(position).unwrap().into();
+    // blocks without a state root keep the hashes they had before state roots existed
+    let state_root = block.state_root.as_ref();
+    block.hash_algorithm().digest_all(
+        [
+            prevhash,
+            &records,
+            block.get_merkle_root(),
+            &timestamp,
+            &position,
+        ]
+        .into_iter()
+        .chain(state_root),
+    )
 }
 
 /// Generates a random SHA-256 hash.
@@ -184,9 +276,7 @@ pub fn random_bytes_vec(len: usize) -> Vec<u8> {
 ///
 /// The computed hash as a `Hash` type.
 pub fn sha<H: AsRef<[u8]>>(value: &H) -> Hash {
-    let mut hasher = Sha256::new();
-    hasher.update(value);
-    hasher.finalize().to_vec().into()
+    HashAlgorithm::Sha256.digest(value)
 }
 
 /// Computes the combined `SHA-256` hash of the data in the iterator
@@ -204,11 +294,7 @@ pub fn sha<H: AsRef<[u8]>>(value: &H) -> Hash {
 ///
 /// The computed hash as a `Hash` type.
 pub fn sha_all<V: AsRef<[u8]>, T: IntoIterator<Item = V>>(values: T) -> Hash {
-    let mut hasher = Sha256::new();
-    for value in values {
-        hasher.update(value);
-    }
-    hasher.finalize().to_vec().into()
+    HashAlgorithm::Sha256.digest_all(values)
 }
 
 /// Verifies whether a given object's hash matches the provided hash value.
@@ -221,28 +307,29 @@ pub fn sha_all<V: AsRef<[u8]>, T: IntoIterator<Item = V>>(values: T) -> Hash {
 /// # Returns
 ///
 /// `true` if the object's hash matches the provided hash value, otherwise `false`.
+///
+/// The object is hashed with the algorithm recorded in `value`.
 pub fn verify_hash<T: Sized + serde::Serialize>(obj: &T, value: &Hash) -> bool {
-    value == &hash(obj)
+    value == &hash_with(value.algorithm(), obj)
 }
 
-/// Generates a new Ed25519 key pair using the `ed25519_dalek` crate and returns it as an `AuthKeyPair`.
+/// Generates a new Ed25519 key pair and returns it as an `AuthKeyPair`.
 ///
-/// The key pair consists of a private key and a corresponding public key, both serialized as byte vectors.
+/// The private key is stored as a PKCS#8 document and the public key as the raw 32-byte key.
 ///
 /// # Returns
 ///
 /// An `AuthKeyPair` containing the generated key pair and the `KeyPairAlgorithm` used.
-
 pub fn generate_ed25519_keypair() -> AuthKeyPair {
-    let mut rng = rand::thread_rng();
+    let rng = ring::rand::SystemRandom::new();
 
-    // Serialize the private and public keys as byte vectors
-    let keypair = ed25519_dalek::Keypair::generate(&mut rng);
+    // `KeyPairAlgorithm::sign` expects a PKCS#8 document, so the key is generated in that form
+    let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).expect("system randomness is unavailable");
+    let keypair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).expect("generated key is valid");
 
-    let public_key = keypair.public.as_ref().to_vec();
-    let private_key = keypair.secret.as_ref().to_vec();
+    let public_key = keypair.public_key().as_ref().to_vec();
+    let private_key = pkcs8.as_ref().to_vec();
 
-    // Returns the public and private keys as byte vectors
     AuthKeyPair::new(
         private_key.into_boxed_slice(),
         public_key.into_boxed_slice(),
@@ -250,11 +337,141 @@ pub fn generate_ed25519_keypair() -> AuthKeyPair {
     )
 }
 
+/// Generates a new BLS12-381 key pair and returns it as an `AuthKeyPair`.
+///
+/// The private key is stored as the 32-byte secret scalar and the public key as the compressed
+/// 48-byte curve point. Signatures of these keys can be aggregated, see [`bls`].
+///
+/// # Returns
+///
+/// An `AuthKeyPair` containing the generated key pair and the `KeyPairAlgorithm` used.
+pub fn generate_bls_keypair() -> AuthKeyPair {
+    let rng = ring::rand::SystemRandom::new();
+    let ikm: zeroize::Zeroizing<[u8; 32]> = zeroize::Zeroizing::new(
+        ring::rand::generate(&rng)
+            .expect("system randomness is unavailable")
+            .expose(),
+    );
+    bls::bls_keypair_from_ikm(&*ikm).expect("32 bytes of key material are enough")
+}
+
+/// Generates a new ECDSA key pair for the given curve and signature encoding and returns it as an `AuthKeyPair`.
+///
+/// The private key is stored as a PKCS#8 document and the public key as the uncompressed curve point.
+///
+/// # Arguments
+///
+/// * `algo` - The curve, digest and signature encoding to be used.
+///
+/// # Returns
+///
+/// An `AuthKeyPair` containing the generated key pair and the `KeyPairAlgorithm` used.
 pub fn generate_ecdsa_keypair(algo: EcdsaSigningAlgorithm) -> AuthKeyPair {
     let rng = ring::rand::SystemRandom::new();
-    let algo = algo.into();
-    let _res = EcdsaKeyPair::generate_pkcs8(algo, &rng).unwrap();
-    todo!()
+    let signing_algo = algo.into();
+
+    let pkcs8 =
+        EcdsaKeyPair::generate_pkcs8(signing_algo, &rng).expect("system randomness is unavailable");
+    let keypair =
+        EcdsaKeyPair::from_pkcs8(signing_algo, pkcs8.as_ref()).expect("generated key is valid");
+
+    let public_key = keypair.public_key().as_ref().to_vec();
+    let private_key = pkcs8.as_ref().to_vec();
+
+    AuthKeyPair::new(
+        private_key.into_boxed_slice(),
+        public_key.into_boxed_slice(),
+        KeyPairAlgorithm::ECDSA(algo),
+    )
+}
+
+/// Generates a new RSA key pair with a modulus of `bits` bits and returns it as an `AuthKeyPair`.
+///
+/// The private key is stored as a PKCS#1 `RSAPrivateKey` DER document and the public key as a
+/// PKCS#1 `RSAPublicKey` DER document, which are the forms expected for signing and verification.
+///
+/// # Arguments
+///
+/// * `algo` - The padding and digest to be used when signing with the key pair.
+/// * `bits` - The size of the modulus. Must be between 2048 and 8192.
+///
+/// # Errors
+///
+/// * `UnsupportedSize` - If `bits` is outside the supported range.
+pub fn generate_rsa_keypair(
+    algo: RsaSigningAlgorithm,
+    bits: usize,
+) -> Result<AuthKeyPair, KeyError> {
+    if !(RSA_MIN_BITS..=RSA_MAX_BITS).contains(&bits) {
+        return Err(KeyError::UnsupportedSize);
+    }
+    let mut rng = rsa::rand_core::OsRng;
+    let key = rsa::RsaPrivateKey::new(&mut rng, bits).map_err(|_| KeyError::Unspecified)?;
+    rsa_keypair_from_private_key(&key, algo)
+}
+
+/// Loads an RSA key pair from a DER-encoded private key.
+///
+/// Both PKCS#1 `RSAPrivateKey` and PKCS#8 `PrivateKeyInfo` documents are accepted. The public key
+/// is derived from the private key.
+///
+/// # Arguments
+///
+/// * `der` - The DER-encoded private key.
+/// * `algo` - The padding and digest to be used when signing with the key pair.
+pub fn rsa_keypair_from_der(
+    der: &[u8],
+    algo: RsaSigningAlgorithm,
+) -> Result<AuthKeyPair, KeyError> {
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs8::DecodePrivateKey;
+
+    let key = rsa::RsaPrivateKey::from_pkcs1_der(der)
+        .or_else(|_| rsa::RsaPrivateKey::from_pkcs8_der(der))
+        .map_err(|_| KeyError::InvalidEncoding)?;
+    rsa_keypair_from_private_key(&key, algo)
+}
+
+/// Loads an RSA key pair from a PEM-encoded private key.
+///
+/// Both `RSA PRIVATE KEY` (PKCS#1) and `PRIVATE KEY` (PKCS#8) documents are accepted. The public
+/// key is derived from the private key.
+///
+/// # Arguments
+///
+/// * `pem` - The PEM-encoded private key.
+/// * `algo` - The padding and digest to be used when signing with the key pair.
+pub fn rsa_keypair_from_pem(pem: &str, algo: RsaSigningAlgorithm) -> Result<AuthKeyPair, KeyError> {
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs8::DecodePrivateKey;
+
+    let key = rsa::RsaPrivateKey::from_pkcs1_pem(pem)
+        .or_else(|_| rsa::RsaPrivateKey::from_pkcs8_pem(pem))
+        .map_err(|_| KeyError::InvalidEncoding)?;
+    rsa_keypair_from_private_key(&key, algo)
+}
+
+const RSA_MIN_BITS: usize = 2048;
+const RSA_MAX_BITS: usize = 8192;
+
+fn rsa_keypair_from_private_key(
+    key: &rsa::RsaPrivateKey,
+    algo: RsaSigningAlgorithm,
+) -> Result<AuthKeyPair, KeyError> {
+    use rsa::pkcs1::EncodeRsaPrivateKey;
+
+    let der = key.to_pkcs1_der().map_err(|_| KeyError::InvalidEncoding)?;
+    // Parsing with `ring` validates the key and rejects moduli outside the verifiable range
+    let keypair = RsaKeyPair::from_der(der.as_bytes())?;
+
+    let public_key = keypair.public_key().as_ref().to_vec();
+    let private_key = der.as_bytes().to_vec();
+
+    Ok(AuthKeyPair::new(
+        private_key.into_boxed_slice(),
+        public_key.into_boxed_slice(),
+        KeyPairAlgorithm::RSA(algo),
+    ))
 }
 
 /// Verifies the Ed25519 digital signature for the given message using a public key.
@@ -328,7 +545,8 @@ pub fn verify_signature(
 
 /// Serialize the given value into bytes.
 ///
-/// Internally uses `bincode::serialize`
+/// Internally uses `bincode::serialize`, so the bytes are not a stable encoding: signatures and
+/// other bytes that must be reproduced elsewhere use [`canonical::encode`] instead.
 ///
 /// # Trait Bound
 /// - `serde::Serialize`
@@ -339,7 +557,7 @@ pub fn serialize<T: Serialize>(value: &T) -> Result<Vec<u8>, SerdeError> {
 /// A `PrivateKey` is the secret component of an AuthKeyPair
 /// TODO
 /// Must fill comments here
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(PartialEq, Eq, Clone)]
 pub struct PrivateKey {
     bytes: Box<[u8]>,
 }
@@ -356,6 +574,20 @@ impl PrivateKey {
     }
 }
 
+impl std::fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrivateKey")
+            .field("bytes", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        self.bytes.zeroize();
+    }
+}
+
 impl From<Vec<u8>> for PrivateKey {
     fn from(bytes: Vec<u8>) -> Self {
         PrivateKey {
@@ -365,7 +597,7 @@ impl From<Vec<u8>> for PrivateKey {
 }
 /// A `PublicKey` is a cryptographic key that can be used to verify digital signatures that are signed with the equivalent `AuthKeyPair`
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PublicKey {
     bytes: Box<[u8]>,
     algorithm: KeyPairAlgorithm,
@@ -384,12 +616,24 @@ impl PublicKey {
         self.algorithm
     }
 
+    /// Verifies `signature` over the raw bytes of `msg`, without any signing domain.
     pub fn verify(
         &self,
         msg: &[u8],
         signature: &DigitalSignature,
     ) -> Result<(), VerificationError> {
-        self.algorithm.verify(msg, signature, self.as_bytes())
+        self.algorithm.verify_raw(msg, signature, self.as_bytes())
+    }
+
+    /// Verifies `signature` over `msg` in `domain`, see [`KeyPairAlgorithm::verify`].
+    pub fn verify_in(
+        &self,
+        domain: &SigningDomain,
+        msg: &[u8],
+        signature: &DigitalSignature,
+    ) -> Result<(), VerificationError> {
+        self.algorithm
+            .verify(domain, msg, signature, self.as_bytes())
     }
 
     pub fn to_hex(&self) -> String {
@@ -417,22 +661,19 @@ impl std::fmt::Display for PublicKey {
 }
 
 impl From<AuthKeyPair> for PublicKey {
-    fn from(value: AuthKeyPair) -> Self {
-        let AuthKeyPair {
-            private_key: _,
-            public_key,
-            algorithm,
-        } = value;
+    fn from(mut value: AuthKeyPair) -> Self {
         Self {
-            bytes: public_key,
-            algorithm,
+            bytes: std::mem::take(&mut value.public_key),
+            algorithm: value.algorithm,
         }
     }
 }
 
 /// An `AuthKeyPair` is a cryptographic key pair that can be used for digital signing and verification.
 ///
-#[derive(Debug, Clone)]
+/// The private key is wiped from memory when the `AuthKeyPair` is dropped and is never shown by its
+/// `Debug` output. Use the [`keystore`] module to persist it.
+#[derive(Clone)]
 pub struct AuthKeyPair {
     private_key: Box<[u8]>,
     public_key: Box<[u8]>,
@@ -473,21 +714,56 @@ impl AuthKeyPair {
     }
 }
 
-/// A `Hash` is the result of hashing a piece of data.
+impl std::fmt::Debug for AuthKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthKeyPair")
+            .field("private_key", &"<redacted>")
+            .field("public_key", &hex::encode(&self.public_key))
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
 
+impl Drop for AuthKeyPair {
+    fn drop(&mut self) {
+        self.private_key.zeroize();
+    }
+}
+
+/// A `Hash` is the result of hashing a piece of data.
+///
+/// The serialized form holds the [`HashAlgorithm`] after the bytes. A missing algorithm is read as
+/// SHA-256 from self-describing formats such as JSON, which chains and blocks are stored in, but
+/// hashes serialized with bincode, see [`crate::serialize`], before the algorithm was recorded
+/// cannot be deserialized anymore.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Hash {
     bytes: Box<[u8]>,
+    // hashes stored before algorithms were recorded are SHA-256 hashes
+    #[serde(default)]
+    algorithm: HashAlgorithm,
 }
 
 impl Hash {
+    /// Creates a SHA-256 `Hash` from its bytes.
     pub fn new(bytes: Box<[u8]>) -> Hash {
-        Hash { bytes }
+        Self::with_algorithm(bytes, HashAlgorithm::Sha256)
     }
+
+    /// Creates a `Hash` from its bytes and the algorithm that produced them.
+    pub fn with_algorithm(bytes: Box<[u8]>, algorithm: HashAlgorithm) -> Hash {
+        Hash { bytes, algorithm }
+    }
+
     pub fn as_bytes(&self) -> &[u8] {
         &self.bytes
     }
 
+    /// Returns the algorithm that produced this hash.
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
     pub fn to_hex(&self) -> String {
         hex::encode(self.as_bytes())
     }
@@ -495,8 +771,7 @@ impl Hash {
 
 impl Default for Hash {
     fn default() -> Self {
-        let bytes = vec![0; 32];
-        Hash::new(bytes.into_boxed_slice())
+        HashAlgorithm::Sha256.zero()
     }
 }
 
@@ -577,8 +852,8 @@ impl AsRef<[u8]> for DigitalSignature {
 }
 
 use ring::signature::{
-    EcdsaKeyPair, Ed25519KeyPair, RsaEncoding, RsaKeyPair, RsaParameters, UnparsedPublicKey,
-    VerificationAlgorithm,
+    EcdsaKeyPair, Ed25519KeyPair, KeyPair, RsaEncoding, RsaKeyPair, RsaParameters,
+    UnparsedPublicKey, VerificationAlgorithm,
 };
 
 /// An enum representing the different algorithms that can be used to generate key pairs.
@@ -588,15 +863,19 @@ use ring::signature::{
 /// The following algorithms are supported:
 ///
 /// * `Ed25519`: An elliptic curve digital signature algorithm.
-/// * `Ecdsa256256Fixed`: An elliptic curve digital signature algorithm with a fixed curve.
-/// * `RsaPKCS1256`: A Rivest–Shamir–Adleman algorithm with a 256-bit modulus.
-
+/// * `Ecdsa`: An elliptic curve digital signature algorithm over the NIST P-256 or P-384 curves.
+/// * `Rsa`: A Rivest–Shamir–Adleman algorithm with PKCS#1.5 or PSS padding.
+/// * `Bls12_381`: A pairing based algorithm whose signatures can be aggregated, see [`bls`].
+#[allow(non_camel_case_types)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum KeyPairAlgorithm {
     ED25519,
-    ECDSA,
+    /// An ECDSA algorithm
+    ECDSA(EcdsaSigningAlgorithm),
     /// An RSA algorithm
     RSA(RsaSigningAlgorithm),
+    /// BLS signatures over the BLS12-381 curve with 48-byte public keys and 96-byte signatures
+    BLS12_381,
 }
 
 #[allow(non_camel_case_types)]
@@ -637,15 +916,26 @@ impl KeyPairAlgorithm {
         match self {
             KeyPairAlgorithm::ED25519 => sign_ed25519(msg, private_key),
             KeyPairAlgorithm::RSA(algo) => sign_rsa(msg, private_key, algo),
-            KeyPairAlgorithm::ECDSA => sign_ecdsa(
-                msg,
-                private_key,
-                &ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING,
-            ),
+            KeyPairAlgorithm::ECDSA(algo) => sign_ecdsa(msg, private_key, algo.into()),
+            KeyPairAlgorithm::BLS12_381 => bls::sign(msg, private_key),
         }
     }
 
+    /// Verifies `signature` by the public key `signer` over `msg` in `domain`.
+    ///
+    /// The signature must have been made over `domain.message(msg)`, so it does not verify in any
+    /// other domain.
     pub fn verify(
+        self,
+        domain: &SigningDomain,
+        msg: &[u8],
+        signature: &DigitalSignature,
+        signer: &[u8],
+    ) -> Result<(), VerificationError> {
+        self.verify_raw(&domain.message(msg), signature, signer)
+    }
+
+    fn verify_raw(
         self,
         msg: &[u8],
         signature: &DigitalSignature,
@@ -654,7 +944,8 @@ impl KeyPairAlgorithm {
         let algo: &dyn VerificationAlgorithm = match self {
             KeyPairAlgorithm::ED25519 => &ring::signature::ED25519,
             KeyPairAlgorithm::RSA(algo) => algo.into(),
-            KeyPairAlgorithm::ECDSA => &ring::signature::ECDSA_P256_SHA256_FIXED,
+            KeyPairAlgorithm::ECDSA(algo) => algo.into(),
+            KeyPairAlgorithm::BLS12_381 => return bls::verify(msg, signature, signer),
         };
 
         let key = UnparsedPublicKey::new(algo, signer);
@@ -691,14 +982,14 @@ fn sign_rsa(
     let padding = algo.into();
 
     let mut signature_vec = vec![0u8; private_key.public_modulus_len()];
-    private_key.sign(padding, &rng, &msg, &mut signature_vec)?;
+    private_key.sign(padding, &rng, msg, &mut signature_vec)?;
 
     Ok(signature_vec.into())
 }
 
-impl Into<&'static dyn RsaEncoding> for RsaSigningAlgorithm {
-    fn into(self) -> &'static dyn RsaEncoding {
-        match self {
+impl From<RsaSigningAlgorithm> for &'static dyn RsaEncoding {
+    fn from(value: RsaSigningAlgorithm) -> Self {
+        match value {
             RsaSigningAlgorithm::PKCS1_2048_8192_SHA256 => &ring::signature::RSA_PKCS1_SHA256,
             RsaSigningAlgorithm::PKCS1_2048_8192_SHA384 => &ring::signature::RSA_PKCS1_SHA384,
             RsaSigningAlgorithm::PKCS1_2048_8192_SHA512 => &ring::signature::RSA_PKCS1_SHA512,
@@ -709,17 +1000,56 @@ impl Into<&'static dyn RsaEncoding> for RsaSigningAlgorithm {
     }
 }
 
-pub enum EcdsaSigningAlgorithm {}
+#[allow(non_camel_case_types)]
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EcdsaSigningAlgorithm {
+    /// Signing of ECDSA signatures using the P-256 curve and SHA-256, with fixed-length signatures.
+    P256_SHA256_FIXED,
+
+    /// Signing of ECDSA signatures using the P-256 curve and SHA-256, with ASN.1 DER-encoded signatures.
+    P256_SHA256_ASN1,
+
+    /// Signing of ECDSA signatures using the P-384 curve and SHA-384, with fixed-length signatures.
+    P384_SHA384_FIXED,
+
+    /// Signing of ECDSA signatures using the P-384 curve and SHA-384, with ASN.1 DER-encoded signatures.
+    P384_SHA384_ASN1,
+}
+
+impl From<EcdsaSigningAlgorithm> for &'static ring::signature::EcdsaSigningAlgorithm {
+    fn from(value: EcdsaSigningAlgorithm) -> Self {
+        match value {
+            EcdsaSigningAlgorithm::P256_SHA256_FIXED => {
+                &ring::signature::ECDSA_P256_SHA256_FIXED_SIGNING
+            }
+            EcdsaSigningAlgorithm::P256_SHA256_ASN1 => {
+                &ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING
+            }
+            EcdsaSigningAlgorithm::P384_SHA384_FIXED => {
+                &ring::signature::ECDSA_P384_SHA384_FIXED_SIGNING
+            }
+            EcdsaSigningAlgorithm::P384_SHA384_ASN1 => {
+                &ring::signature::ECDSA_P384_SHA384_ASN1_SIGNING
+            }
+        }
+    }
+}
 
-impl Into<&'static ring::signature::EcdsaSigningAlgorithm> for EcdsaSigningAlgorithm {
-    fn into(self) -> &'static ring::signature::EcdsaSigningAlgorithm {
-        match self {}
+impl From<EcdsaSigningAlgorithm> for &'static dyn VerificationAlgorithm {
+    fn from(value: EcdsaSigningAlgorithm) -> Self {
+        match value {
+            EcdsaSigningAlgorithm::P256_SHA256_FIXED => &ring::signature::ECDSA_P256_SHA256_FIXED,
+            EcdsaSigningAlgorithm::P256_SHA256_ASN1 => &ring::signature::ECDSA_P256_SHA256_ASN1,
+            EcdsaSigningAlgorithm::P384_SHA384_FIXED => &ring::signature::ECDSA_P384_SHA384_FIXED,
+            EcdsaSigningAlgorithm::P384_SHA384_ASN1 => &ring::signature::ECDSA_P384_SHA384_ASN1,
+        }
     }
 }
 
-impl Into<&'static RsaParameters> for RsaSigningAlgorithm {
-    fn into(self) -> &'static RsaParameters {
-        match self {
+impl From<RsaSigningAlgorithm> for &'static RsaParameters {
+    fn from(value: RsaSigningAlgorithm) -> Self {
+        match value {
             RsaSigningAlgorithm::PKCS1_2048_8192_SHA256 => {
                 &ring::signature::RSA_PKCS1_2048_8192_SHA256
             }
@@ -736,9 +1066,9 @@ impl Into<&'static RsaParameters> for RsaSigningAlgorithm {
     }
 }
 
-impl Into<&'static dyn VerificationAlgorithm> for RsaSigningAlgorithm {
-    fn into(self) -> &'static dyn VerificationAlgorithm {
-        match self {
+impl From<RsaSigningAlgorithm> for &'static dyn VerificationAlgorithm {
+    fn from(value: RsaSigningAlgorithm) -> Self {
+        match value {
             RsaSigningAlgorithm::PKCS1_2048_8192_SHA256 => {
                 &ring::signature::RSA_PKCS1_2048_8192_SHA256
             }
@@ -764,7 +1094,7 @@ mod tests2 {
         let data = "Hello, World".to_owned();
         let keypair = crate::generate_ed25519_keypair();
         let record = data
-            .record(keypair.clone(), crate::data::Metadata::empty())
+            .record(keypair.clone(), "test", crate::data::Metadata::empty())
             .expect("Couldn't record string!");
         println!("{}", record.signature());
         println!("{}", keypair.algorithm);
@@ -779,15 +1109,15 @@ mod tests {
     #[test]
     fn hash_test() {
         #[derive(Serialize)]
-        struct DMS {
+        struct Dms {
             audio: Option<String>,
             moving_pictures: Option<String>,
             metadata: String,
         }
 
-        impl DMS {
-            fn new(audio: Option<String>, mp: Option<String>, d: String) -> DMS {
-                DMS {
+        impl Dms {
+            fn new(audio: Option<String>, mp: Option<String>, d: String) -> Dms {
+                Dms {
                     audio,
                     moving_pictures: mp,
                     metadata: d,
@@ -795,14 +1125,14 @@ mod tests {
             }
         }
 
-        impl Default for DMS {
+        impl Default for Dms {
             fn default() -> Self {
-                DMS::new(None, None, String::new())
+                Dms::new(None, None, String::new())
             }
         }
 
-        let dms = DMS::default();
-        let my_dms = DMS::new(None, Some("Harry Potter".into()), "".into());
+        let dms = Dms::default();
+        let my_dms = Dms::new(None, Some("Harry Potter".into()), "".into());
 
         let dms_hash = crate::hash(&dms);
         let my_dms_hash = crate::hash(&my_dms);
@@ -810,4 +1140,102 @@ mod tests {
         println!("{}", dms_hash.to_hex());
         println!("{}", my_dms_hash.to_hex());
     }
+
+    #[test]
+    fn ed25519_test() {
+        use crate::KeyPairAlgorithm;
+
+        let keypair = crate::generate_ed25519_keypair();
+        assert_eq!(KeyPairAlgorithm::ED25519, keypair.algorithm());
+
+        // the private key is a PKCS#8 document, which is what signing expects
+        assert!(ring::signature::Ed25519KeyPair::from_pkcs8(keypair.private_key_bytes()).is_ok());
+        assert_eq!(32, keypair.public_key_bytes().len());
+
+        let msg = b"Hello, World";
+        let signature = keypair.sign(msg).expect("couldn't sign with Ed25519 key");
+        let pubkey = keypair.into_public_key();
+
+        assert!(pubkey.verify(msg, &signature).is_ok());
+        assert!(pubkey.verify(b"Goodbye, World", &signature).is_err());
+    }
+
+    #[test]
+    fn ecdsa_test() {
+        use crate::{EcdsaSigningAlgorithm, KeyPairAlgorithm};
+
+        let algorithms = [
+            EcdsaSigningAlgorithm::P256_SHA256_FIXED,
+            EcdsaSigningAlgorithm::P256_SHA256_ASN1,
+            EcdsaSigningAlgorithm::P384_SHA384_FIXED,
+            EcdsaSigningAlgorithm::P384_SHA384_ASN1,
+        ];
+
+        for algo in algorithms {
+            let keypair = crate::generate_ecdsa_keypair(algo);
+            assert_eq!(KeyPairAlgorithm::ECDSA(algo), keypair.algorithm());
+
+            let msg = b"Hello, World";
+            let signature = keypair.sign(msg).expect("couldn't sign with ECDSA key");
+            let pubkey = keypair.into_public_key();
+
+            assert!(pubkey.verify(msg, &signature).is_ok());
+            assert!(pubkey.verify(b"Goodbye, World", &signature).is_err());
+        }
+    }
+
+    #[test]
+    fn rsa_test() {
+        use crate::{KeyPairAlgorithm, RsaSigningAlgorithm};
+        use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+
+        let keypair = crate::generate_rsa_keypair(RsaSigningAlgorithm::PSS_2048_8192_SHA256, 2048)
+            .expect("couldn't generate RSA key pair");
+
+        let msg = b"Hello, World";
+        let pubkey = keypair.clone().into_public_key();
+
+        // every variant can sign and verify with the same key material
+        let algorithms = [
+            RsaSigningAlgorithm::PKCS1_2048_8192_SHA256,
+            RsaSigningAlgorithm::PKCS1_2048_8192_SHA384,
+            RsaSigningAlgorithm::PKCS1_2048_8192_SHA512,
+            RsaSigningAlgorithm::PSS_2048_8192_SHA256,
+            RsaSigningAlgorithm::PSS_2048_8192_SHA384,
+            RsaSigningAlgorithm::PSS_2048_8192_SHA512,
+        ];
+        for algo in algorithms {
+            let keypair = crate::rsa_keypair_from_der(keypair.private_key_bytes(), algo)
+                .expect("couldn't load PKCS#1 DER");
+            assert_eq!(KeyPairAlgorithm::RSA(algo), keypair.algorithm());
+            assert_eq!(pubkey.as_bytes(), keypair.public_key_bytes());
+
+            let signature = keypair.sign(msg).expect("couldn't sign with RSA key");
+            let pubkey = keypair.into_public_key();
+            assert!(pubkey.verify(msg, &signature).is_ok());
+            assert!(pubkey.verify(b"Goodbye, World", &signature).is_err());
+        }
+
+        let algo = RsaSigningAlgorithm::PKCS1_2048_8192_SHA256;
+        let key = <rsa::RsaPrivateKey as rsa::pkcs1::DecodeRsaPrivateKey>::from_pkcs1_der(
+            keypair.private_key_bytes(),
+        )
+        .unwrap();
+
+        let pkcs8_der = key.to_pkcs8_der().unwrap();
+        let from_der = crate::rsa_keypair_from_der(pkcs8_der.as_bytes(), algo).unwrap();
+        assert_eq!(pubkey.as_bytes(), from_der.public_key_bytes());
+
+        let pkcs8_pem = key.to_pkcs8_pem(LineEnding::LF).unwrap();
+        let from_pem = crate::rsa_keypair_from_pem(&pkcs8_pem, algo).unwrap();
+        assert_eq!(pubkey.as_bytes(), from_pem.public_key_bytes());
+
+        let pkcs1_pem =
+            rsa::pkcs1::EncodeRsaPrivateKey::to_pkcs1_pem(&key, LineEnding::LF).unwrap();
+        let from_pem = crate::rsa_keypair_from_pem(&pkcs1_pem, algo).unwrap();
+        assert_eq!(pubkey.as_bytes(), from_pem.public_key_bytes());
+
+        assert!(crate::rsa_keypair_from_pem("not a key", algo).is_err());
+        assert!(crate::generate_rsa_keypair(algo, 1024).is_err());
+    }
 }
@@ -0,0 +1,435 @@
+//! Signing through the [`Signer`] trait, so that records can be signed without holding raw private keys.
+//!
+//! `AuthKeyPair` implements `Signer` for keys held in process memory. On Unix, the [`remote`] module
+//! provides a `Signer` that forwards signing requests over a local socket to a separate process,
+//! together with a small server for hosting keys in that process.
+
+use std::{rc::Rc, sync::Arc};
+
+use super::{AuthKeyPair, DigitalSignature, KeyPairAlgorithm, PublicKey, SigningError};
+
+/// A source of digital signatures for a single public key.
+///
+/// # Examples
+///
+/// ```
+/// use blockify::{record::Record, Signer};
+///
+/// fn sign_greeting<S: Signer>(signer: &S) -> blockify::DigitalSignature {
+///     String::from("hello").sign(signer, "greetings").unwrap()
+/// }
+///
+/// let keypair = blockify::generate_ed25519_keypair();
+/// let signature = sign_greeting(&keypair);
+///
+/// let pubkey = Signer::public_key(&keypair);
+/// assert!(String::from("hello").verify(&signature, &pubkey, "greetings").is_ok());
+/// ```
+pub trait Signer {
+    /// Returns the public key that verifies the signatures of this signer.
+    fn public_key(&self) -> PublicKey;
+
+    /// Returns the algorithm of the key used by this signer.
+    fn algorithm(&self) -> KeyPairAlgorithm {
+        self.public_key().algorithm()
+    }
+
+    /// Signs `msg` and returns the signature.
+    fn sign(&self, msg: &[u8]) -> Result<DigitalSignature, SigningError>;
+}
+
+impl Signer for AuthKeyPair {
+    fn public_key(&self) -> PublicKey {
+        PublicKey::new(self.public_key_bytes().into(), self.algorithm())
+    }
+
+    fn algorithm(&self) -> KeyPairAlgorithm {
+        AuthKeyPair::algorithm(self)
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<DigitalSignature, SigningError> {
+        AuthKeyPair::sign(self, msg)
+    }
+}
+
+macro_rules! impl_signer_for_pointer {
+    ($($pointer:ty),*) => {
+        $(
+            impl<S: Signer + ?Sized> Signer for $pointer {
+                fn public_key(&self) -> PublicKey {
+                    (**self).public_key()
+                }
+
+                fn algorithm(&self) -> KeyPairAlgorithm {
+                    (**self).algorithm()
+                }
+
+                fn sign(&self, msg: &[u8]) -> Result<DigitalSignature, SigningError> {
+                    (**self).sign(msg)
+                }
+            }
+        )*
+    };
+}
+
+impl_signer_for_pointer!(&S, Box<S>, Rc<S>, Arc<S>);
+
+#[cfg(unix)]
+pub mod remote {
+    //! A `Signer` whose key lives in another process, reached over a Unix domain socket.
+    //!
+    //! Messages are bincode-encoded and framed by a 4 byte big-endian length. A client first asks
+    //! for the public key, then sends any number of signing requests on the same connection.
+    //!
+    //! # Trust model
+    //!
+    //! The server signs every message a connected client sends, without knowing what it signs. It
+    //! therefore relies on the operating system to keep other users away: the socket file is only
+    //! accessible to the user of the server, and each client is checked with its peer credentials
+    //! to run as an allowed user, the user of the server unless [`SignerServer::with_allowed_uids`]
+    //! says otherwise. Any process of an allowed user can have messages signed, so a server should
+    //! only run under a user that does nothing else, and its socket should be placed in a directory
+    //! only that user can enter, as the permissions are set just after the socket is created.
+    //!
+    //! # Examples
+    //!
+    //! ```no_run
+    //! use blockify::{record::Record, signer::remote::{RemoteSigner, SignerServer}};
+    //!
+    //! // in the process that holds the key
+    //! let server = SignerServer::bind("/tmp/blockify.sock", blockify::generate_ed25519_keypair()).unwrap();
+    //! std::thread::spawn(move || server.serve());
+    //!
+    //! // in the process that signs records
+    //! let signer = RemoteSigner::connect("/tmp/blockify.sock").unwrap();
+    //! let signature = String::from("hello").sign(&signer, "greetings").unwrap();
+    //! ```
+
+    use std::{
+        io::{Read, Write},
+        os::unix::{
+            fs::PermissionsExt,
+            io::AsRawFd,
+            net::{UnixListener, UnixStream},
+        },
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+    use super::Signer;
+    use crate::{DigitalSignature, KeyPairAlgorithm, PublicKey, SigningError};
+
+    /// The largest message accepted from either side of a connection
+    const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+    /// The permissions of the socket file, which only its owner can connect to
+    const SOCKET_MODE: u32 = 0o600;
+
+    #[derive(Serialize, Deserialize)]
+    enum Request {
+        PublicKey,
+        Sign(Vec<u8>),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum Response {
+        PublicKey(PublicKey),
+        Signature(DigitalSignature),
+        Failed,
+    }
+
+    /// An error that can occur while talking to a remote signer
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum RemoteSignerError {
+        /// Reading from or writing to the socket failed.
+        Io(std::io::ErrorKind),
+        /// The peer sent a message that does not follow the protocol.
+        Protocol,
+        /// The socket file is accessible to other users than its owner.
+        Permissions,
+        /// The peer credentials of a client cannot be read on this platform.
+        Unsupported,
+    }
+
+    crate::impl_display_error!(RemoteSignerError);
+
+    impl From<std::io::Error> for RemoteSignerError {
+        fn from(value: std::io::Error) -> Self {
+            RemoteSignerError::Io(value.kind())
+        }
+    }
+
+    /// A `Signer` that asks a [`SignerServer`] in another process to sign on its behalf.
+    pub struct RemoteSigner {
+        stream: Mutex<UnixStream>,
+        public_key: PublicKey,
+    }
+
+    impl RemoteSigner {
+        /// Connects to the signer server listening at `path` and fetches its public key.
+        pub fn connect<P: AsRef<Path>>(path: P) -> Result<Self, RemoteSignerError> {
+            let mut stream = UnixStream::connect(path)?;
+            let public_key = match call(&mut stream, &Request::PublicKey)? {
+                Response::PublicKey(key) => key,
+                _ => return Err(RemoteSignerError::Protocol),
+            };
+            Ok(Self {
+                stream: Mutex::new(stream),
+                public_key,
+            })
+        }
+    }
+
+    impl Signer for RemoteSigner {
+        fn public_key(&self) -> PublicKey {
+            self.public_key.clone()
+        }
+
+        fn algorithm(&self) -> KeyPairAlgorithm {
+            self.public_key.algorithm()
+        }
+
+        fn sign(&self, msg: &[u8]) -> Result<DigitalSignature, SigningError> {
+            let mut stream = self.stream.lock().map_err(|_| SigningError::Unavailable)?;
+            let signature = match call(&mut stream, &Request::Sign(msg.to_vec())) {
+                Ok(Response::Signature(signature)) => signature,
+                Ok(Response::Failed) => return Err(SigningError::Unspecified),
+                _ => return Err(SigningError::Unavailable),
+            };
+            // a signature that does not verify would only be noticed much later, so check it here
+            self.public_key
+                .verify(msg, &signature)
+                .map_err(|_| SigningError::Unspecified)?;
+            Ok(signature)
+        }
+    }
+
+    impl std::fmt::Debug for RemoteSigner {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("RemoteSigner")
+                .field("public_key", &self.public_key)
+                .finish()
+        }
+    }
+
+    /// A server that holds a `Signer` and signs messages for [`RemoteSigner`] clients.
+    ///
+    /// It signs anything an allowed client asks for, see the [trust model](self#trust-model). Each
+    /// connection is served on its own thread.
+    pub struct SignerServer<S> {
+        listener: UnixListener,
+        signer: Arc<S>,
+        allowed_uids: Vec<u32>,
+    }
+
+    impl<S: Signer + Send + Sync + 'static> SignerServer<S> {
+        /// Listens for clients on a new socket at `path`, which only the current user can connect
+        /// to.
+        ///
+        /// Fails with `Permissions` if the permissions of the socket file cannot be restricted.
+        pub fn bind<P: AsRef<Path>>(path: P, signer: S) -> Result<Self, RemoteSignerError> {
+            let path = path.as_ref();
+            let listener = UnixListener::bind(path)?;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(SOCKET_MODE))?;
+            if std::fs::metadata(path)?.permissions().mode() & 0o777 != SOCKET_MODE {
+                return Err(RemoteSignerError::Permissions);
+            }
+            Ok(Self {
+                listener,
+                signer: Arc::new(signer),
+                // SAFETY: `geteuid` has no preconditions and cannot fail
+                allowed_uids: vec![unsafe { libc::geteuid() }],
+            })
+        }
+
+        /// Serves only clients that run as one of the users `uids`, instead of the current user.
+        pub fn with_allowed_uids<I: IntoIterator<Item = u32>>(mut self, uids: I) -> Self {
+            self.allowed_uids = uids.into_iter().collect();
+            self
+        }
+
+        /// Accepts and serves clients until accepting a connection fails.
+        ///
+        /// Clients that do not run as an allowed user are disconnected without an answer.
+        pub fn serve(self) -> Result<(), RemoteSignerError> {
+            for stream in self.listener.incoming() {
+                let stream = stream?;
+                match peer_uid(&stream) {
+                    Ok(uid) if self.allowed_uids.contains(&uid) => {}
+                    _ => continue,
+                }
+                let signer = Arc::clone(&self.signer);
+                std::thread::spawn(move || serve_client(stream, &*signer));
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the user the process at the other end of `stream` runs as
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn peer_uid(stream: &UnixStream) -> Result<u32, RemoteSignerError> {
+        let mut cred = libc::ucred {
+            pid: 0,
+            uid: 0,
+            gid: 0,
+        };
+        let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+        // SAFETY: `cred` and `len` are valid for writes and `len` holds the size of `cred`
+        let result = unsafe {
+            libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_PEERCRED,
+                &mut cred as *mut libc::ucred as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        match result {
+            0 => Ok(cred.uid),
+            _ => Err(std::io::Error::last_os_error().into()),
+        }
+    }
+
+    /// Returns the user the process at the other end of `stream` runs as
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))]
+    fn peer_uid(stream: &UnixStream) -> Result<u32, RemoteSignerError> {
+        let (mut uid, mut gid) = (0, 0);
+        // SAFETY: `uid` and `gid` are valid for writes
+        match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+            0 => Ok(uid),
+            _ => Err(std::io::Error::last_os_error().into()),
+        }
+    }
+
+    /// Returns the user the process at the other end of `stream` runs as
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    )))]
+    fn peer_uid(_stream: &UnixStream) -> Result<u32, RemoteSignerError> {
+        Err(RemoteSignerError::Unsupported)
+    }
+
+    fn serve_client<S: Signer>(
+        mut stream: UnixStream,
+        signer: &S,
+    ) -> Result<(), RemoteSignerError> {
+        // the loop ends when the client disconnects and the next read fails
+        loop {
+            let response = match read_frame(&mut stream)? {
+                Request::PublicKey => Response::PublicKey(signer.public_key()),
+                Request::Sign(msg) => match signer.sign(&msg) {
+                    Ok(signature) => Response::Signature(signature),
+                    Err(_) => Response::Failed,
+                },
+            };
+            write_frame(&mut stream, &response)?;
+        }
+    }
+
+    fn call(stream: &mut UnixStream, request: &Request) -> Result<Response, RemoteSignerError> {
+        write_frame(stream, request)?;
+        read_frame(stream)
+    }
+
+    fn write_frame<T: Serialize>(
+        stream: &mut UnixStream,
+        value: &T,
+    ) -> Result<(), RemoteSignerError> {
+        let bytes = bincode::serialize(value).map_err(|_| RemoteSignerError::Protocol)?;
+        let len = u32::try_from(bytes.len())
+            .ok()
+            .filter(|len| *len <= MAX_FRAME_LEN)
+            .ok_or(RemoteSignerError::Protocol)?;
+        stream.write_all(&len.to_be_bytes())?;
+        stream.write_all(&bytes)?;
+        Ok(())
+    }
+
+    fn read_frame<T: DeserializeOwned>(stream: &mut UnixStream) -> Result<T, RemoteSignerError> {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len);
+        if len > MAX_FRAME_LEN {
+            return Err(RemoteSignerError::Protocol);
+        }
+        let mut bytes = vec![0; len as usize];
+        stream.read_exact(&mut bytes)?;
+        bincode::deserialize(&bytes).map_err(|_| RemoteSignerError::Protocol)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::os::unix::fs::PermissionsExt;
+
+        use super::{RemoteSigner, SignerServer};
+        use crate::{data::Metadata, record::Record, Signer};
+
+        #[test]
+        fn remote_signer_test() {
+            let dir = "target2/tests/signer/";
+            let path = format!("{dir}signer.sock");
+            std::fs::create_dir_all(dir).unwrap();
+            let _ = std::fs::remove_file(&path);
+
+            let keypair = crate::generate_ed25519_keypair();
+            let pubkey = keypair.clone().into_public_key();
+            let server = SignerServer::bind(&path, keypair).expect("couldn't bind socket");
+            std::thread::spawn(move || server.serve());
+
+            // only the owner can connect to the socket
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+
+            let signer = RemoteSigner::connect(&path).expect("couldn't connect to signer");
+            assert_eq!(pubkey, signer.public_key());
+
+            let record = String::from("signed elsewhere");
+            let signature = record
+                .sign(&signer, "test")
+                .expect("couldn't sign remotely");
+            assert!(record.verify(&signature, &pubkey, "test").is_ok());
+
+            let signed = record
+                .record(&signer, "test", Metadata::empty())
+                .expect("couldn't record remotely");
+            assert_eq!(&pubkey, signed.signer());
+            assert!(signed.verify().is_ok());
+
+            let _ = std::fs::remove_file(&path);
+        }
+
+        #[test]
+        fn peer_test() {
+            let dir = "target2/tests/signer/";
+            let path = format!("{dir}peer.sock");
+            std::fs::create_dir_all(dir).unwrap();
+            let _ = std::fs::remove_file(&path);
+
+            // a client whose user is not allowed is disconnected before it is answered
+            let keypair = crate::generate_ed25519_keypair();
+            let server = SignerServer::bind(&path, keypair)
+                .expect("couldn't bind socket")
+                .with_allowed_uids(vec![]);
+            std::thread::spawn(move || server.serve());
+            assert!(RemoteSigner::connect(&path).is_err());
+
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
@@ -70,6 +70,7 @@ impl ToTimestamp for u64 {
 }
 
 #[test]
+#[allow(clippy::assertions_on_constants)]
 fn test_timestamp_for_u64() {
     let val = 33u64;
     let _timestamp = val.to_timestamp();
@@ -113,6 +114,7 @@ pub enum Month {
     December,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<u8> for Month {
     fn into(self) -> u8 {
         match self {
@@ -153,6 +155,7 @@ impl From<u8> for Month {
 }
 
 impl Timestamp {
+    #[allow(clippy::let_and_return)]
     pub fn date_time<Z: TimeZone>(self, tz: &Z) -> DateTime<Z> {
         let utc = NaiveDateTime::from_timestamp_opt(self.secs as _, 0).unwrap();
         let res = tz.from_utc_datetime(&utc);
@@ -209,11 +212,31 @@ impl BufID {
         Self { value }
     }
 
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.value)
+    }
+
+    #[deprecated(note = "use `to_hex` or the `Display` implementation instead")]
+    #[allow(clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
-        hex::encode(&self.value)
+        self.to_hex()
+    }
+}
+
+impl std::fmt::Display for BufID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_hex())
     }
 }
 
+#[test]
+#[allow(deprecated)]
+fn test_buf_id_to_string() {
+    let id = BufID::new([0xab; 16]);
+    assert_eq!(id.to_hex(), id.to_string());
+    assert_eq!(id.to_hex(), format!("{id}"));
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Nonce {
     pub nonce: u64,
@@ -85,6 +85,7 @@ impl<const N: usize> Serialize for Units<N> {
 }
 
 impl<'d, const N: usize> Deserialize<'d> for Units<N> {
+    #[allow(clippy::manual_memcpy)]
     fn deserialize<D: serde::Deserializer<'d>>(dz: D) -> Result<Self, D::Error> {
         let vec = <Vec<MicQuan>>::deserialize(dz)?;
         let mut real = [MicQuan::default(); N];
@@ -1,7 +1,6 @@
-
 use super::impl_display_error;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataBaseError {
     NoSuchTable,
     NoSuchKey,
@@ -10,7 +9,7 @@ pub enum DataBaseError {
     ConnectionCannotEstablish,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SerdeError {
     SerializationError,
     DeserializationError,
@@ -19,11 +18,10 @@ pub enum SerdeError {
 impl_display_error!(SerdeError);
 impl_display_error!(DataBaseError);
 
-
 mod tests {
 
     #[test]
     fn test_diplay() {
         println!("{}", crate::error::SerdeError::SerializationError)
     }
-}
\ No newline at end of file
+}
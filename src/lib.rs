@@ -7,7 +7,7 @@
 //!
 //!
 //! # USEFUL FEATURES
-//! 
+//!
 //! - Generating cryptographic key pairs
 //! - Signing data with cryptographic keys
 //! - Serializing data into bytes
@@ -19,13 +19,13 @@
 //!
 //! # USAGE
 //!
-//! 
-//! 
+//!
+//!
 //! - **Example**
 //!
 //! ```
 //! use blockify::{
-//! block::{LocalInstance, UnchainedInstance, Block},
+//! block::{LocalInstance, UnchainedInstance, ChainedInstance},
 //! data::Metadata,
 //! record::{Record, SignedRecord}, SqliteChain, chain::Chain
 //! };
@@ -46,18 +46,18 @@
 //!                 groom_name,
 //!             }
 //!     }
-//! 
+//!
 //!     pub fn generate() -> Self {
 //!         Self {
 //!             bride_name: "Julian".to_owned(),
 //!             groom_name: "Jolie".to_owned(),
 //!         }
 //!     }
-//! 
+//!
 //!     pub fn generate_records(amount: usize) -> Vec<SignedRecord<Self>> {
 //!         let mut res = Vec::with_capacity(amount);
 //!         (0..amount).for_each(|_| {
-//!             match Self::generate().record(blockify::generate_ed25519_key_pair(), Default::default()){
+//!             match Self::generate().record(blockify::generate_ed25519_keypair(), "marriages", Default::default()){
 //!                 Ok(v) => res.push(v),
 //!                 Err(_) => unreachable!("Error occurs")
 //!             }
@@ -68,38 +68,38 @@
 //! }
 //! ```
 //!
-//! 
-//! 
+//!
+//!
 //! - **Creating `Records` and `SignedRecords`**
 //!
-//! ```
+//! ```ignore
 //! let contract = MarriageContract::new("John", "Julie");
-//! let keypair = blockify::generate_ed25519_key_pair();
-//! let signature = contract.sign(&keypair).unwrap();
+//! let keypair = blockify::generate_ed25519_keypair();
+//! let signature = contract.sign(&keypair, "marriages").unwrap();
 //! let hash = contract.hash();
-//! let record = contract.record(keypair, Metadata::empty()).unwrap();
-//! 
+//! let record = contract.record(keypair, "marriages", Metadata::empty()).unwrap();
+//!
 //! assert_eq!(&hash, record.hash());
 //! assert_eq!(&signature, record.signature());
 //! assert!(record.verify().is_ok());
 //! ```
 //!
 //!
-//! 
+//!
 //! - **Assembling a `Block`**
-//! ```
+//! ```ignore
 //! let mut pool = LocalInstance::new(Metadata::empty(), 0);
 //! let all_records = MarriageContract::generate_records(10);
 //! all_records.clone().into_iter().for_each(|record| pool.append(record).unwrap());
 //! ```
 //!
-//! 
-//! 
+//!
+//!
 //! - **`SqliteBlock` and `SqliteChain`**
-//! ```
+//! ```ignore
 //! let chain_url = "target2/tests/marriagecontractchain/";
 //! std::fs::create_dir_all(chain_url).expect("could initialize directories");
-//! 
+//!
 //! let mut chain = SqliteChain::new(chain_url).unwrap();
 //! let position = chain.append(&pool).expect("Error appending to SqliteChain");
 //! let block = position.block(&chain).expect("Error getting block by position");
@@ -107,9 +107,7 @@
 //! assert_eq!(&all_records, &*block.records().expect("Error retrieving records from block"));
 //! ```
 //!
-//! 
-
-
+//!
 
 pub mod data;
 pub mod error;
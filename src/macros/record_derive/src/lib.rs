@@ -4,6 +4,15 @@
 //!
 //! Types deriving `Record` must implement `Serialize + Deserialize`
 //!
+//! The `record` attribute controls what is signed and how the type is tagged:
+//!
+//! - `#[record(type_id = "...")]` sets `Record::TYPE_ID`, which defaults to the name of the type and
+//!   is required on types with type or const parameters
+//! - `#[record(version = N)]` sets `Record::VERSION`, which defaults to `1`
+//! - `#[record(hash_with = path)]` hashes the record with `path(&self, algorithm)`
+//! - `#[record(validate = path)]` checks the business rules of the record with `path(&self)`
+//! - `#[record(skip)]` keeps a field of a struct with named fields out of the signed and hashed payload,
+//!   which then holds the other fields under their serde names
 //!
 //! # Usage
 //! ```
@@ -17,37 +26,373 @@
 //! }
 //!
 //! #[derive(Serialize, Deserialize, Record)]
+//! #[record(type_id = "Detail")]
 //! pub struct Detail<T> {
 //!   val: T
 //! }
+//!
+//! #[derive(Serialize, Deserialize, Record)]
+//! #[record(type_id = "registry/Transfer", version = 3)]
+//! pub struct Transfer {
+//!   from: String,
+//!   to: String,
+//!   #[record(skip)]
+//!   note: String,
+//! }
 //! ```
 
 extern crate proc_macro;
 
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput};
+use syn::{
+    ext::IdentExt, parse_macro_input, punctuated::Punctuated, spanned::Spanned, Attribute, Data,
+    DeriveInput, Error, Expr, ExprLit, Fields, Lit, LitInt, LitStr, Meta, MetaNameValue, Path,
+    Token,
+};
 
-#[proc_macro_derive(Record)]
+#[proc_macro_derive(Record, attributes(record))]
 pub fn record_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     // Parse the input code as a Rust syntax tree
     let input = parse_macro_input!(input as DeriveInput);
 
-    impl_record(&input)
+    impl_record(&input).unwrap_or_else(|err| err.into_compile_error().into())
+}
+
+/// The options given by `#[record(...)]` on the type
+#[derive(Default)]
+struct RecordOptions {
+    type_id: Option<LitStr>,
+    version: Option<LitInt>,
+    hash_with: Option<Path>,
+    validate: Option<Path>,
+}
+
+impl RecordOptions {
+    fn parse(input: &DeriveInput) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attr in input
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("record"))
+        {
+            attr.parse_nested_meta(|meta| {
+                let duplicate = || meta.error("duplicate record attribute");
+                if meta.path.is_ident("type_id") {
+                    let type_id = meta.value()?.parse::<LitStr>()?;
+                    if type_id.value().is_empty() {
+                        return Err(Error::new(type_id.span(), "`type_id` must not be empty"));
+                    }
+                    match options.type_id {
+                        Some(_) => return Err(duplicate()),
+                        None => options.type_id = Some(type_id),
+                    }
+                } else if meta.path.is_ident("version") {
+                    let version = meta.value()?.parse::<LitInt>()?;
+                    if version.base10_parse::<u32>()? == 0 {
+                        return Err(Error::new(version.span(), "`version` must be at least 1"));
+                    }
+                    match options.version {
+                        Some(_) => return Err(duplicate()),
+                        None => options.version = Some(version),
+                    }
+                } else if meta.path.is_ident("hash_with") {
+                    let hash_with = meta.value()?.parse::<Path>()?;
+                    match options.hash_with {
+                        Some(_) => return Err(duplicate()),
+                        None => options.hash_with = Some(hash_with),
+                    }
+                } else if meta.path.is_ident("validate") {
+                    let validate = meta.value()?.parse::<Path>()?;
+                    match options.validate {
+                        Some(_) => return Err(duplicate()),
+                        None => options.validate = Some(validate),
+                    }
+                } else if meta.path.is_ident("skip") {
+                    return Err(meta.error("`skip` can only be used on fields"));
+                } else {
+                    return Err(meta.error(
+                        "unknown record attribute, expected `type_id`, `version`, `hash_with` or `validate`",
+                    ));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(options)
+    }
+}
+
+/// Returns whether `field` has `#[record(skip)]`
+fn is_skipped(field: &syn::Field) -> syn::Result<bool> {
+    let mut skip = false;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("record"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                match skip {
+                    true => return Err(meta.error("duplicate record attribute")),
+                    false => skip = true,
+                }
+                Ok(())
+            } else if ["type_id", "version", "hash_with", "validate"]
+                .iter()
+                .any(|name| meta.path.is_ident(name))
+            {
+                Err(meta.error("this attribute can only be used on the type"))
+            } else {
+                Err(meta.error("unknown record attribute, expected `skip`"))
+            }
+        })?;
+    }
+    Ok(skip)
+}
+
+/// The `serde` attributes of a type or a field that change the payload of a record with skipped
+/// fields
+#[derive(Default)]
+struct SerdeOptions {
+    /// `rename` on a field, or `rename_all` on the type
+    rename: Option<LitStr>,
+    /// `skip` or `skip_serializing` on a field
+    skip: bool,
+}
+
+impl SerdeOptions {
+    /// Parses the `serde` attributes in `attrs`, where `rename` is the name of the renaming attribute
+    /// and `unsupported` lists the attributes that change the serialized form otherwise
+    fn parse(attrs: &[Attribute], rename: &str, unsupported: &[&str]) -> syn::Result<Self> {
+        let mut options = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+            let metas = attr.parse_args_with(Punctuated::<Meta, Token![,]>::parse_terminated)?;
+            for meta in metas {
+                let path = meta.path();
+                if path.is_ident(rename) {
+                    if let Some(name) = serialized_name(&meta)? {
+                        options.rename = Some(name);
+                    }
+                } else if path.is_ident("skip") || path.is_ident("skip_serializing") {
+                    options.skip = true;
+                } else if let Some(name) = unsupported.iter().find(|name| path.is_ident(name)) {
+                    return Err(Error::new(
+                        path.span(),
+                        format!("`#[serde({name})]` cannot be combined with `#[record(skip)]`"),
+                    ));
+                }
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Returns the name given to serialization by `rename = "..."` or `rename(serialize = "...")`
+fn serialized_name(meta: &Meta) -> syn::Result<Option<LitStr>> {
+    let name_value = |value: &Expr| match value {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(name),
+            ..
+        }) => Ok(name.clone()),
+        _ => Err(Error::new(value.span(), "expected a string literal")),
+    };
+    match meta {
+        Meta::NameValue(meta) => name_value(&meta.value).map(Some),
+        Meta::List(list) => {
+            let metas =
+                list.parse_args_with(Punctuated::<MetaNameValue, Token![,]>::parse_terminated)?;
+            metas
+                .iter()
+                .find(|meta| meta.path.is_ident("serialize"))
+                .map(|meta| name_value(&meta.value))
+                .transpose()
+        }
+        Meta::Path(path) => Err(Error::new(path.span(), "expected a name")),
+    }
 }
 
-fn impl_record(input: &DeriveInput) -> proc_macro::TokenStream {
+/// Applies the `rename_all` rule `rule` to the field name `name`, as serde does
+fn rename_field(rule: &LitStr, name: &str) -> syn::Result<String> {
+    let pascal = || {
+        name.split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars
+                    .next()
+                    .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<String>()
+    };
+    Ok(match rule.value().as_str() {
+        "lowercase" | "snake_case" => name.to_owned(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => name.to_ascii_uppercase(),
+        "PascalCase" => pascal(),
+        "camelCase" => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_lowercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        }
+        "kebab-case" => name.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => name.to_ascii_uppercase().replace('_', "-"),
+        _ => return Err(Error::new(rule.span(), "unknown serde rename rule")),
+    })
+}
+
+/// Returns the fields that are signed with the names they are signed under, or `None` if no field
+/// is skipped
+fn signed_fields(input: &DeriveInput) -> syn::Result<Option<Vec<(&syn::Field, String)>>> {
+    let fields = match &input.data {
+        Data::Struct(data) => data.fields.iter().collect::<Vec<_>>(),
+        Data::Enum(data) => data.variants.iter().flat_map(|v| &v.fields).collect(),
+        Data::Union(data) => {
+            return Err(Error::new(
+                data.union_token.span(),
+                "Record cannot be derived for unions",
+            ))
+        }
+    };
+
+    let mut signed = vec![];
+    let mut skipped = None;
+    for field in fields {
+        match is_skipped(field)? {
+            true => skipped = Some(field),
+            false => signed.push(field),
+        }
+    }
+
+    let skipped = match skipped {
+        Some(field) => field,
+        None => return Ok(None),
+    };
+    if !matches!(&input.data, Data::Struct(data) if matches!(data.fields, Fields::Named(_))) {
+        return Err(Error::new(
+            skipped.span(),
+            "`skip` can only be used on fields of structs with named fields",
+        ));
+    }
+
+    // the signed fields keep the names serde gives them, and fields serde skips are not signed
+    let unsupported = ["into", "tag", "transparent"];
+    let rename_all = SerdeOptions::parse(&input.attrs, "rename_all", &unsupported)?.rename;
+    let mut named = vec![];
+    for field in signed {
+        let unsupported = [
+            "flatten",
+            "getter",
+            "serialize_with",
+            "skip_serializing_if",
+            "with",
+        ];
+        let serde = SerdeOptions::parse(&field.attrs, "rename", &unsupported)?;
+        if serde.skip {
+            continue;
+        }
+        let ident = field.ident.as_ref().expect("the fields are named").unraw();
+        let name = match (serde.rename, &rename_all) {
+            (Some(name), _) => name.value(),
+            (None, Some(rule)) => rename_field(rule, &ident.to_string())?,
+            (None, None) => ident.to_string(),
+        };
+        named.push((field, name));
+    }
+    Ok(Some(named))
+}
+
+fn impl_record(input: &DeriveInput) -> syn::Result<proc_macro::TokenStream> {
     let name = &input.ident;
     let generics = &input.generics;
-    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    let options = RecordOptions::parse(input)?;
+    let signed = signed_fields(input)?;
+
+    // the instances of a generic type would share the domain of their signatures
+    let type_id = match options.type_id {
+        Some(type_id) => type_id,
+        None if generics.type_params().next().is_some()
+            || generics.const_params().next().is_some() =>
+        {
+            return Err(Error::new(
+                generics.span(),
+                "generic types need an explicit `#[record(type_id = \"...\")]`",
+            ))
+        }
+        None => LitStr::new(&name.to_string(), name.span()),
+    };
+    let version = match options.version {
+        Some(version) => quote!(#version),
+        None => quote!(1),
+    };
+
+    // the payload is either the whole record or only its signed fields
+    let mut bounded = generics.clone();
+    let (payload, signed_fields) = match signed {
+        None => (quote!(self), quote!()),
+        Some(fields) => {
+            let type_name = name.to_string();
+            let len = fields.len();
+            let idents = fields.iter().map(|(field, _)| &field.ident);
+            let names = fields.iter().map(|(_, name)| name);
+            let predicates = &mut bounded.make_where_clause().predicates;
+            for (field, _) in &fields {
+                let ty = &field.ty;
+                predicates.push(syn::parse_quote!(#ty: blockify::record::__private::Serialize));
+            }
+            let where_clause = &bounded.where_clause;
+
+            let payload = quote!(&blockify::record::__private::Signed(self));
+            let signed_fields = quote! {
+                impl #impl_generics blockify::record::__private::SignedFields for #name #ty_generics #where_clause {
+                    const NAME: &'static str = #type_name;
+                    const LEN: usize = #len;
+
+                    fn serialize_fields<S: blockify::record::__private::SerializeStruct>(
+                        &self,
+                        state: &mut S,
+                    ) -> Result<(), S::Error> {
+                        #(state.serialize_field(#names, &self.#idents)?;)*
+                        Ok(())
+                    }
+                }
+            };
+            (payload, signed_fields)
+        }
+    };
+
+    let where_clause = &bounded.where_clause;
+    let hash = match options.hash_with {
+        Some(hash_with) => quote!(#hash_with(self, algorithm)),
+        None => quote!(blockify::hash_with(algorithm, #payload)),
+    };
+
+    let validate = options.validate.map(|validate| {
+        quote! {
+            fn validate(&self) -> Result<(), blockify::record::ValidationError> {
+                #validate(self)
+            }
+        }
+    });
 
     let gen = quote! {
+        #signed_fields
+
         impl #impl_generics Record for #name #ty_generics #where_clause {
-            fn sign(
+            const TYPE_ID: &'static str = #type_id;
+
+            const VERSION: u32 = #version;
+
+            fn sign<S: blockify::Signer + ?Sized>(
                 &self,
-                key: &blockify::AuthKeyPair,
+                signer: &S,
+                chain_id: &str,
             ) -> Result<blockify::DigitalSignature, blockify::SigningError> {
-                let msg = blockify::serialize(self).map_err(|e| blockify::SigningError::SerdeError(e))?;
-                let signature = blockify::sign_msg(&msg, key)?;
+                let msg = self
+                    .signed_message(chain_id)
+                    .map_err(|e| blockify::SigningError::SerdeError(e))?;
+                let signature = blockify::Signer::sign(signer, &msg)?;
                 Ok(signature)
             }
 
@@ -55,33 +400,47 @@ fn impl_record(input: &DeriveInput) -> proc_macro::TokenStream {
                 &self,
                 signature: &blockify::DigitalSignature,
                 key: &blockify::PublicKey,
+                chain_id: &str,
             ) -> Result<(), blockify::VerificationError> {
                 let msg =
-                    blockify::serialize(self).map_err(|e| blockify::VerificationError::SerdeError(e))?;
-                key.verify(&msg, signature)
+                    self.signing_bytes().map_err(|e| blockify::VerificationError::SerdeError(e))?;
+                let domain = blockify::SigningDomain::record::<Self>(chain_id);
+                key.verify_in(&domain, &msg, signature)
             }
 
-            fn record(
+            fn record<S: blockify::Signer>(
                 self,
-                keypair: blockify::AuthKeyPair,
+                signer: S,
+                chain_id: &str,
                 metadata: blockify::data::Metadata,
             ) -> Result<blockify::record::SignedRecord<Self>, blockify::SigningError> {
-                let signature = self.sign(&keypair)?;
+                let signature = self.sign(&signer, chain_id)?;
                 let hash = self.hash();
                 Ok(blockify::record::SignedRecord::new(
                     self,
                     signature,
-                    keypair.into_public_key(),
+                    blockify::Signer::public_key(&signer),
                     hash,
+                    chain_id,
                     metadata,
                 ))
             }
 
             fn hash(&self) -> blockify::Hash {
-                blockify::hash(self)
+                self.hash_with(blockify::HashAlgorithm::Sha256)
+            }
+
+            fn hash_with(&self, algorithm: blockify::HashAlgorithm) -> blockify::Hash {
+                #hash
             }
+
+            fn signing_bytes(&self) -> Result<Vec<u8>, blockify::error::SerdeError> {
+                blockify::canonical::encode(#payload)
+            }
+
+            #validate
         }
     };
 
-    gen.into()
+    Ok(gen.into())
 }
@@ -1,2 +1,3 @@
+#[allow(clippy::module_inception)]
 mod node;
-pub use node::*;
\ No newline at end of file
+pub use node::*;
@@ -3,7 +3,7 @@ use crate::{
     chain::{Chain, ChainError},
     data::Metadata,
     record::{Record, SignedRecord},
-    AuthKeyPair, DigitalSignature, PublicKey, SigningError,
+    DigitalSignature, PublicKey, Signer, SigningError,
 };
 
 pub enum NodeError {
@@ -37,6 +37,7 @@ pub trait Node<R: Record>: Sized {
     fn chain(&self) -> Result<Self::ChainType, NodeError>;
     fn broadcast(&self, block: Self::ChainedInstanceType) -> Result<Feedback, NodeError>;
     fn mem_pool(&self) -> Result<Option<Self::MemPoolType>, NodeError>;
+    #[allow(clippy::redundant_closure)]
     fn push(&mut self, block: Self::UnchainedInstanceType) -> Result<PositionInstance, NodeError> {
         self.chain()?
             .append(&block)
@@ -55,20 +56,27 @@ pub enum Feedback {}
 
 pub trait Peer<R: Record> {
     fn public_key(&self) -> &PublicKey;
-    fn sign(record: &R, keypair: &AuthKeyPair) -> Result<DigitalSignature, SigningError> {
-        record.sign(keypair)
+    fn sign<S: Signer + ?Sized>(
+        record: &R,
+        signer: &S,
+        chain_id: &str,
+    ) -> Result<DigitalSignature, SigningError> {
+        record.sign(signer, chain_id)
     }
 
-    fn record(
+    fn record<S: Signer>(
         record: R,
-        keypair: AuthKeyPair,
+        signer: S,
+        chain_id: &str,
         metadata: Metadata,
     ) -> Result<SignedRecord<R>, SigningError> {
-        record.record(keypair, metadata)
+        record.record(signer, chain_id, metadata)
     }
 
-    fn verify(&self, signature: &DigitalSignature, record: R) -> bool {
-        record.verify(signature, self.public_key()).is_ok()
+    fn verify(&self, signature: &DigitalSignature, record: R, chain_id: &str) -> bool {
+        record
+            .verify(signature, self.public_key(), chain_id)
+            .is_ok()
     }
 }
 
@@ -5,8 +5,9 @@ use crate::{
     crypto::*,
     data::{Metadata, Nonce, Position, Timestamp},
     error::{DataBaseError, SerdeError},
-    merkle::MerkleTree,
-    record::Records,
+    merkle::{MerkleMultiProof, MerkleProof, MerkleTree},
+    record::{RecordError, Records},
+    upcast::UpcastError,
 };
 
 use super::{
@@ -20,7 +21,7 @@ use super::{
 /// This `Block` trait provides methods for accessing these properties.
 pub trait ChainedInstance<R: Record> {
     /// Returns a reference to the records in this block.
-    fn records(&self) -> Result<Records<R>, BlockError>;
+    fn records(&self) -> Result<Records<'_, R>, BlockError>;
 
     /// Returns the previous hash of this block.
     fn prev_hash(&self) -> Result<Hash, BlockError>;
@@ -39,6 +40,75 @@ pub trait ChainedInstance<R: Record> {
 
     /// Returns the nonce of this block.
     fn nonce(&self) -> Result<Nonce, BlockError>;
+
+    /// Returns the state root the hash of this block commits to, if any.
+    fn state_root(&self) -> Result<Option<Hash>, BlockError> {
+        Ok(None)
+    }
+
+    /// Returns the Merkle tree of the records of this block.
+    ///
+    /// The tree is built from the records, unless the block keeps it around.
+    fn merkle_tree(&self) -> Result<MerkleTree, BlockError> {
+        let algorithm = self.merkle_root()?.algorithm();
+        let records = self.records()?;
+        Ok(MerkleTree::from_leaves(
+            algorithm,
+            leaves(algorithm, &records),
+        ))
+    }
+
+    /// Recomputes the Merkle root from the records of this block.
+    ///
+    /// The result equals [`ChainedInstance::merkle_root`] unless the block has been tampered with.
+    fn compute_merkle_root(&self) -> Result<Hash, BlockError> {
+        let algorithm = self.merkle_root()?.algorithm();
+        let records = self.records()?;
+        let tree = MerkleTree::from_leaves(algorithm, leaves(algorithm, &records));
+        Ok(tree.root().clone())
+    }
+
+    /// Returns the proof that the record at `index` is included in the Merkle root of this block.
+    ///
+    /// The proof can be checked with [`merkle::verify_proof`] against the Merkle root and the hash
+    /// of the record with the algorithm of the root, see [`SignedRecord::hash_with`],
+    /// without any of the other records.
+    fn merkle_proof(&self, index: usize) -> Result<MerkleProof, BlockError> {
+        self.merkle_tree()?
+            .proof(index)
+            .ok_or(BlockError::AbsentValue)
+    }
+
+    /// Returns the proof that the records at `indices` are included in the Merkle root of this
+    /// block, sharing the nodes their paths have in common.
+    fn merkle_multi_proof(&self, indices: &[usize]) -> Result<MerkleMultiProof, BlockError> {
+        self.merkle_tree()?
+            .multi_proof(indices)
+            .ok_or(BlockError::AbsentValue)
+    }
+
+    /// Returns the proof that the record with the hash `record` is included in the Merkle root of
+    /// this block.
+    fn record_proof(&self, record: &Hash) -> Result<MerkleProof, BlockError> {
+        let index = self
+            .records()?
+            .iter()
+            .position(|item| item.hash() == record)
+            .ok_or(BlockError::AbsentValue)?;
+        self.merkle_proof(index)
+    }
+}
+
+/// Returns the Merkle leaves of `records` in a block hashed with `algorithm`, which are the hashes
+/// of the records with that algorithm.
+pub(crate) fn leaves<R: Record>(
+    algorithm: HashAlgorithm,
+    records: &[SignedRecord<R>],
+) -> Vec<Hash> {
+    records
+        .iter()
+        .map(|record| record.hash_with(algorithm))
+        .collect()
 }
 
 /// An error that can occur when working with blocks.
@@ -53,6 +123,12 @@ pub enum BlockError {
     /// The block is not valid.
     NotValid(BlockData),
 
+    /// The requested value is not in the block.
+    AbsentValue,
+
+    /// A record is rejected by [`SignedRecord::check`].
+    InvalidRecord(RecordError),
+
     /// An unspecified error occurred.
     Unspecified,
 }
@@ -77,6 +153,9 @@ pub enum BlockData {
 
     /// The position of the block in the blockchain.
     Position,
+
+    /// The records of the block.
+    Records,
 }
 
 impl std::error::Error for BlockError {}
@@ -93,7 +172,22 @@ impl From<ChainError> for BlockError {
             ChainError::SerdeError(v) => BlockError::SerdeError(v),
             ChainError::DataBaseError(u) => BlockError::DataBaseError(u),
             ChainError::Unspecified => BlockError::Unspecified,
-            ChainError::AbsentValue => unimplemented!(),
+            ChainError::AbsentValue => BlockError::AbsentValue,
+            ChainError::HashAlgorithmMismatch => BlockError::NotValid(BlockData::Hash),
+            ChainError::InvalidRecord(v) => BlockError::InvalidRecord(v),
+            ChainError::NotValid(v) => BlockError::NotValid(v),
+        }
+    }
+}
+
+impl From<UpcastError> for BlockError {
+    fn from(value: UpcastError) -> Self {
+        match value {
+            UpcastError::SerdeError(v) => BlockError::SerdeError(v),
+            UpcastError::UnknownVersion(_) => {
+                BlockError::SerdeError(SerdeError::DeserializationError)
+            }
+            UpcastError::VerificationError(_) => BlockError::NotValid(BlockData::Records),
         }
     }
 }
@@ -134,26 +228,81 @@ pub struct LocalInstance<R> {
     pub merkle: merkle::MerkleTree,
     pub metadata: Metadata,
     pub nonce: Nonce,
+    /// The root of the state after this block, committed to by the block hash if set.
+    #[serde(default)]
+    pub state_root: Option<Hash>,
+    /// The identifier of the chain the records must be signed for, checked when they are added if set.
+    #[serde(default)]
+    pub chain_id: Option<String>,
 }
 
 impl<R> LocalInstance<R> {
     pub fn new(metadata: Metadata, nonce: u64) -> Self {
+        Self::with_hash_algorithm(metadata, nonce, HashAlgorithm::Sha256)
+    }
+
+    /// Creates an empty instance whose Merkle tree and block hash use `algorithm`.
+    ///
+    /// The algorithm must match the one of the chain the instance is appended to.
+    pub fn with_hash_algorithm(metadata: Metadata, nonce: u64, algorithm: HashAlgorithm) -> Self {
         Self {
             records: vec![],
-            merkle: MerkleTree::new(),
+            merkle: MerkleTree::with_algorithm(algorithm),
             metadata,
             nonce: nonce.into(),
+            state_root: None,
+            chain_id: None,
         }
     }
+
+    /// Returns the hash algorithm of this instance.
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        self.merkle.algorithm()
+    }
+
+    /// Sets the state root the block hash commits to, e.g. the root of a
+    /// [`SparseMerkleTree`](crate::merkle::sparse::SparseMerkleTree) after applying the records.
+    pub fn set_state_root(&mut self, root: Hash) {
+        self.state_root = Some(root);
+    }
+
+    /// Sets the identifier of the chain the records of this instance must be signed for.
+    ///
+    /// Records added from then on are checked with [`SignedRecord::check_for`], so that records
+    /// signed for another chain are rejected before the instance reaches the chain.
+    pub fn set_chain_id(&mut self, chain_id: &str) {
+        self.chain_id = Some(chain_id.to_owned());
+    }
+
+    /// Returns the identifier of the chain the records of this instance must be signed for, if set.
+    pub fn chain_id(&self) -> Option<&str> {
+        self.chain_id.as_deref()
+    }
 }
 
-impl<R> LocalInstance<R> {
-    pub fn push(&mut self, item: SignedRecord<R>) {
-        let hash = item.hash();
-        self.merkle.push(hash);
+impl<R: Record> LocalInstance<R> {
+    /// Adds `item`, which is rejected with `InvalidRecord` unless it passes [`SignedRecord::check`],
+    /// or [`SignedRecord::check_for`] the chain of this instance if it is set.
+    ///
+    /// This is the same as [`UnchainedInstance::append`].
+    pub fn push(&mut self, item: SignedRecord<R>) -> Result<(), BlockError> {
+        self.check(&item)?;
+        let item = item.with_hash_algorithm(self.hash_algorithm());
+        self.merkle.push(item.hash());
         self.records.push(item);
+        Ok(())
     }
 
+    fn check(&self, item: &SignedRecord<R>) -> Result<(), BlockError> {
+        match &self.chain_id {
+            Some(chain_id) => item.check_for(chain_id),
+            None => item.check(),
+        }
+        .map_err(BlockError::InvalidRecord)
+    }
+}
+
+impl<R> LocalInstance<R> {
     pub fn get_records(&self) -> &Vec<SignedRecord<R>> {
         &self.records
     }
@@ -164,23 +313,61 @@ impl<R> LocalInstance<R> {
 }
 
 pub trait UnchainedInstance<R> {
+    /// Appends `item`, which is rejected with `InvalidRecord` unless it passes [`SignedRecord::check`].
     fn append(&mut self, item: SignedRecord<R>) -> Result<(), BlockError>;
     fn nonce(&self) -> Result<Nonce, BlockError>;
-    fn records(&self) -> Result<Records<R>, BlockError>;
+    fn records(&self) -> Result<Records<'_, R>, BlockError>;
     fn merkle_root(&self) -> Result<Hash, BlockError>;
+
+    /// Removes the record at `index` and shifts the records after it to the left.
+    ///
+    /// The default returns `Unspecified`, for blocks that cannot give up their records.
+    fn remove(&mut self, index: usize) -> Result<SignedRecord<R>, BlockError> {
+        let _ = index;
+        Err(BlockError::Unspecified)
+    }
+
+    /// Removes the record at `index` and moves the last record into its place, which changes the
+    /// order of the records but is cheaper than [`UnchainedInstance::remove`].
+    ///
+    /// The default returns `Unspecified`, like [`UnchainedInstance::remove`].
+    fn swap_remove(&mut self, index: usize) -> Result<SignedRecord<R>, BlockError> {
+        let _ = index;
+        Err(BlockError::Unspecified)
+    }
+
+    /// Replaces the record at `index` with `item` and returns the old record.
+    ///
+    /// `item` is checked like in [`UnchainedInstance::append`]. The default returns `Unspecified`,
+    /// like [`UnchainedInstance::remove`].
+    fn replace(
+        &mut self,
+        index: usize,
+        item: SignedRecord<R>,
+    ) -> Result<SignedRecord<R>, BlockError> {
+        let _ = (index, item);
+        Err(BlockError::Unspecified)
+    }
+
+    /// Keeps the first `len` records and removes the rest.
+    ///
+    /// The default returns `Unspecified`, like [`UnchainedInstance::remove`].
+    fn truncate(&mut self, len: usize) -> Result<(), BlockError> {
+        let _ = len;
+        Err(BlockError::Unspecified)
+    }
 }
 
-impl<R: Clone> UnchainedInstance<R> for LocalInstance<R> {
+impl<R: Record + Clone> UnchainedInstance<R> for LocalInstance<R> {
     fn append(&mut self, item: SignedRecord<R>) -> Result<(), BlockError> {
-        self.push(item);
-        Ok(())
+        self.push(item)
     }
 
     fn nonce(&self) -> Result<Nonce, BlockError> {
         Ok(self.nonce)
     }
 
-    fn records(&self) -> Result<Records<R>, BlockError> {
+    fn records(&self) -> Result<Records<'_, R>, BlockError> {
         let records = &self.records;
         Ok(records.into())
     }
@@ -188,4 +375,37 @@ impl<R: Clone> UnchainedInstance<R> for LocalInstance<R> {
     fn merkle_root(&self) -> Result<Hash, BlockError> {
         Ok(self.merkle.root().clone())
     }
+
+    fn remove(&mut self, index: usize) -> Result<SignedRecord<R>, BlockError> {
+        if !self.merkle.remove(index) {
+            return Err(BlockError::AbsentValue);
+        }
+        Ok(self.records.remove(index))
+    }
+
+    fn swap_remove(&mut self, index: usize) -> Result<SignedRecord<R>, BlockError> {
+        if !self.merkle.swap_remove(index) {
+            return Err(BlockError::AbsentValue);
+        }
+        Ok(self.records.swap_remove(index))
+    }
+
+    fn replace(
+        &mut self,
+        index: usize,
+        item: SignedRecord<R>,
+    ) -> Result<SignedRecord<R>, BlockError> {
+        self.check(&item)?;
+        let item = item.with_hash_algorithm(self.hash_algorithm());
+        if !self.merkle.replace(index, item.hash()) {
+            return Err(BlockError::AbsentValue);
+        }
+        Ok(std::mem::replace(&mut self.records[index], item))
+    }
+
+    fn truncate(&mut self, len: usize) -> Result<(), BlockError> {
+        self.merkle.truncate(len);
+        self.records.truncate(len);
+        Ok(())
+    }
 }
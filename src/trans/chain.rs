@@ -2,10 +2,13 @@ use crate::{
     block::UnchainedInstance,
     data::Position,
     error::{DataBaseError, SerdeError},
+    mmr::MmrProof,
+    record::RecordError,
+    Hash, HashAlgorithm,
 };
 
 use super::{
-    block::{ChainedInstance, BlockError, PositionInstance},
+    block::{BlockData, BlockError, ChainedInstance, PositionInstance},
     record::Record,
 };
 
@@ -16,6 +19,13 @@ pub enum ChainError {
     DataBaseError(DataBaseError),
     AbsentValue,
     Unspecified,
+    /// The block uses a different hash algorithm than the chain.
+    HashAlgorithmMismatch,
+    /// A record of the block is rejected by
+    /// [`SignedRecord::check`](crate::record::SignedRecord::check).
+    InvalidRecord(RecordError),
+    /// The data of a stored block is not valid, such as a record whose signature no longer verifies.
+    NotValid(BlockData),
 }
 
 impl From<BlockError> for ChainError {
@@ -24,7 +34,9 @@ impl From<BlockError> for ChainError {
             BlockError::SerdeError(v) => ChainError::SerdeError(v),
             BlockError::DataBaseError(u) => ChainError::DataBaseError(u),
             BlockError::Unspecified => ChainError::Unspecified,
-            BlockError::NotValid(_) => unimplemented!(),
+            BlockError::AbsentValue => ChainError::AbsentValue,
+            BlockError::InvalidRecord(v) => ChainError::InvalidRecord(v),
+            BlockError::NotValid(v) => ChainError::NotValid(v),
         }
     }
 }
@@ -32,6 +44,7 @@ impl From<BlockError> for ChainError {
 /// A chain is a collection of blocks.
 ///
 /// The `Chain` trait provides methods for adding blocks to the chain, getting blocks from the chain, and validating the chain.
+#[allow(clippy::len_without_is_empty)]
 pub trait Chain<R: Record>: Sized {
     type UnchainedInstanceType: UnchainedInstance<R>;
     /// The type of block that is stored in this chain.
@@ -39,6 +52,10 @@ pub trait Chain<R: Record>: Sized {
 
     /// Appends an `UnchainedInstance` block to the chain.
     ///
+    /// Every record of the block must pass
+    /// [`SignedRecord::check_for`](crate::record::SignedRecord::check_for) the chain if it has an
+    /// identifier, see [`Chain::chain_id`], even if it was added to the block without being checked.
+    ///
     /// # Arguments
     ///
     /// * `block` - The `UnchainedInstance` to append to the block.
@@ -66,12 +83,45 @@ pub trait Chain<R: Record>: Sized {
 
     fn len(&self) -> Result<u64, ChainError>;
 
+    /// Returns the identifier of the chain, which every record appended to it must be signed for.
+    ///
+    /// A chain without an identifier accepts records signed for any chain, each checked with
+    /// [`SignedRecord::check`](crate::record::SignedRecord::check). The default has none.
+    fn chain_id(&self) -> Result<Option<String>, ChainError> {
+        Ok(None)
+    }
+
+    /// Returns the hash algorithm used for the blocks of this chain.
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::Sha256
+    }
+
+    /// Returns the root of the Merkle Mountain Range over the hashes of all blocks in the chain.
+    ///
+    /// The default returns `Unspecified`, for chains that do not keep the range.
+    fn mmr_root(&self) -> Result<Hash, ChainError> {
+        Err(ChainError::Unspecified)
+    }
+
+    /// Returns the proof that the block at `pos` is included in the Merkle Mountain Range of the
+    /// chain.
+    ///
+    /// The proof is for the leaf at index `pos - 1` and can be checked with
+    /// [`mmr::verify_proof`](crate::mmr::verify_proof) against [`Chain::mmr_root`] and the hash of
+    /// the block, without walking back the previous hashes from the head of the chain.
+    ///
+    /// The default returns `Unspecified`, like [`Chain::mmr_root`].
+    fn block_proof(&self, pos: Position) -> Result<MmrProof, ChainError> {
+        let _ = pos;
+        Err(ChainError::Unspecified)
+    }
+
     fn last_block(&self) -> Result<Option<Self::ChainedInstanceType>, ChainError> {
         let last = match self.len()? {
             0 => return Ok(None),
             v => v.into(),
         };
 
-        self.block_at(last).map(|value| Some(value))
+        self.block_at(last).map(Some)
     }
 }
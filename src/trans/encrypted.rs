@@ -0,0 +1,248 @@
+//! Records whose content only chosen recipients can read.
+//!
+//! An [`EncryptedRecord`] holds a record sealed in an [`Envelope`] for a set of recipients, together
+//! with a salted commitment to the plaintext. It is itself a `Record`, so it is signed, hashed and
+//! stored in a `LocalInstance` or `SqliteChain` like any other record, while the chain never sees the
+//! plaintext. A recipient decrypts it with their [`EncryptionKeyPair`].
+//!
+//! Decryption checks the plaintext against the commitment, so every recipient is guaranteed to read
+//! the same record, which the AEAD cipher alone does not guarantee against a dishonest sender.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{
+//!     data::Metadata,
+//!     encrypted::EncryptedRecord,
+//!     encryption::EncryptionKeyPair,
+//!     record::Record,
+//! };
+//!
+//! let doctor = EncryptionKeyPair::generate();
+//! let patient = EncryptionKeyPair::generate();
+//! let keypair = blockify::generate_ed25519_keypair();
+//!
+//! let diagnosis = String::from("all good");
+//! let encrypted = EncryptedRecord::encrypt(&diagnosis, &[doctor.public_key(), patient.public_key()]).unwrap();
+//! let signed = encrypted.record(keypair, "clinic", Metadata::empty()).unwrap();
+//! assert!(signed.verify().is_ok());
+//!
+//! assert_eq!(diagnosis, signed.decrypt(&patient).unwrap());
+//! assert!(signed.decrypt(&EncryptionKeyPair::generate()).is_err());
+//! ```
+
+use std::marker::PhantomData;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    canonical,
+    data::Metadata,
+    encryption::{EncryptionError, EncryptionKeyPair, EncryptionPublicKey, Envelope},
+    error::SerdeError,
+    record::{Record, SignedRecord},
+    DigitalSignature, Hash, HashAlgorithm, PublicKey, Signer, SigningDomain, SigningError,
+    VerificationError,
+};
+
+/// The version of the encrypted record format written by this crate
+pub const ENCRYPTED_RECORD_VERSION: u32 = 1;
+
+const SALT_LEN: usize = 32;
+
+/// A record of type `R` encrypted for a set of recipients.
+///
+/// # Type Parameters
+///
+/// - `R`: The type of transaction that is encrypted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(bound = "")]
+pub struct EncryptedRecord<R> {
+    version: u32,
+    commitment: Hash,
+    envelope: Envelope,
+    #[serde(skip)]
+    _record: PhantomData<R>,
+}
+
+impl<R> EncryptedRecord<R> {
+    /// Returns the commitment to the salted plaintext.
+    pub fn commitment(&self) -> &Hash {
+        &self.commitment
+    }
+
+    /// Returns the envelope holding the encrypted record.
+    pub fn envelope(&self) -> &Envelope {
+        &self.envelope
+    }
+
+    /// Returns the public keys of the recipients.
+    pub fn recipients(&self) -> impl Iterator<Item = &EncryptionPublicKey> {
+        self.envelope.recipients()
+    }
+
+    /// Returns `true` if the record was encrypted for `recipient`.
+    pub fn is_recipient(&self, recipient: &EncryptionPublicKey) -> bool {
+        self.envelope.is_recipient(recipient)
+    }
+}
+
+impl<R: Record + Serialize> EncryptedRecord<R> {
+    /// Encrypts `record` so that it can be decrypted by any of `recipients`.
+    pub fn encrypt(
+        record: &R,
+        recipients: &[EncryptionPublicKey],
+    ) -> Result<Self, EncryptionError> {
+        let salt = crate::random_bytes::<SALT_LEN>();
+        let commitment = crate::hash(&(salt, record));
+        let plaintext = Zeroizing::new(
+            bincode::serialize(&(salt, record)).map_err(|_| SerdeError::SerializationError)?,
+        );
+        let aad = associated_data::<R>(ENCRYPTED_RECORD_VERSION, &commitment)?;
+        Ok(Self {
+            version: ENCRYPTED_RECORD_VERSION,
+            envelope: Envelope::seal(recipients, &plaintext, &aad)?,
+            commitment,
+            _record: PhantomData,
+        })
+    }
+
+    /// Decrypts the record with the key pair of one of its recipients.
+    ///
+    /// # Errors
+    ///
+    /// * `NotRecipient` - If the record was not encrypted for `keypair`.
+    /// * `DecryptionFailed` - If the record has been altered or does not match its commitment.
+    /// * `UnsupportedVersion` - If the record was encrypted in an unknown format version.
+    pub fn decrypt(&self, keypair: &EncryptionKeyPair) -> Result<R, EncryptionError>
+    where
+        R: DeserializeOwned,
+    {
+        if self.version != ENCRYPTED_RECORD_VERSION {
+            return Err(EncryptionError::UnsupportedVersion(self.version));
+        }
+        let aad = associated_data::<R>(self.version, &self.commitment)?;
+        let plaintext = self.envelope.open(keypair, &aad)?;
+        let (salt, record): ([u8; SALT_LEN], R) =
+            bincode::deserialize(&plaintext).map_err(|_| SerdeError::DeserializationError)?;
+        if crate::hash(&(salt, &record)) != self.commitment {
+            return Err(EncryptionError::DecryptionFailed);
+        }
+        Ok(record)
+    }
+}
+
+/// Binds the ciphertext to the format version, the record type and the commitment
+fn associated_data<R: Record>(version: u32, commitment: &Hash) -> Result<Vec<u8>, SerdeError> {
+    canonical::encode(&(version, R::TYPE_ID, commitment))
+}
+
+impl<R: Record + Serialize> Record for EncryptedRecord<R> {
+    const TYPE_ID: &'static str = "EncryptedRecord";
+
+    fn sign<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        chain_id: &str,
+    ) -> Result<DigitalSignature, SigningError> {
+        let msg = self
+            .signed_message(chain_id)
+            .map_err(SigningError::SerdeError)?;
+        Signer::sign(signer, &msg)
+    }
+
+    fn verify(
+        &self,
+        signature: &DigitalSignature,
+        pubkey: &PublicKey,
+        chain_id: &str,
+    ) -> Result<(), VerificationError> {
+        let msg = self
+            .signing_bytes()
+            .map_err(VerificationError::SerdeError)?;
+        pubkey.verify_in(&SigningDomain::record::<Self>(chain_id), &msg, signature)
+    }
+
+    fn record<S: Signer>(
+        self,
+        signer: S,
+        chain_id: &str,
+        metadata: Metadata,
+    ) -> Result<SignedRecord<Self>, SigningError> {
+        let signature = Record::sign(&self, &signer, chain_id)?;
+        let hash = Record::hash(&self);
+        Ok(SignedRecord::new(
+            self,
+            signature,
+            signer.public_key(),
+            hash,
+            chain_id,
+            metadata,
+        ))
+    }
+
+    fn hash(&self) -> Hash {
+        crate::hash(self)
+    }
+
+    fn hash_with(&self, algorithm: HashAlgorithm) -> Hash {
+        crate::hash_with(algorithm, self)
+    }
+
+    fn signing_bytes(&self) -> Result<Vec<u8>, SerdeError> {
+        canonical::encode(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EncryptedRecord;
+    use crate::{
+        block::{LocalInstance, UnchainedInstance},
+        data::Metadata,
+        encryption::{EncryptionError, EncryptionKeyPair},
+        record::Record,
+    };
+
+    #[test]
+    fn encrypted_record_test() {
+        let hr = EncryptionKeyPair::generate();
+        let employee = EncryptionKeyPair::generate();
+        let outsider = EncryptionKeyPair::generate();
+        let salary = 120_000i64;
+
+        let encrypted =
+            EncryptedRecord::encrypt(&salary, &[hr.public_key(), employee.public_key()]).unwrap();
+        assert!(encrypted.is_recipient(&employee.public_key()));
+        assert!(!encrypted.is_recipient(&outsider.public_key()));
+        assert_eq!(salary, encrypted.decrypt(&hr).unwrap());
+        assert_eq!(
+            Err(EncryptionError::NotRecipient),
+            encrypted.decrypt(&outsider)
+        );
+
+        // the same plaintext encrypts to a different commitment every time
+        let again = EncryptedRecord::encrypt(&salary, &[hr.public_key()]).unwrap();
+        assert_ne!(encrypted.commitment(), again.commitment());
+
+        let mut tampered = encrypted.clone();
+        tampered.commitment = again.commitment().clone();
+        assert_eq!(
+            Err(EncryptionError::DecryptionFailed),
+            tampered.decrypt(&hr)
+        );
+
+        let keypair = crate::generate_ed25519_keypair();
+        let signed = encrypted
+            .record(&keypair, "payroll", Metadata::empty())
+            .unwrap();
+        assert!(signed.verify().is_ok());
+
+        let mut block = LocalInstance::new(Metadata::empty(), 0);
+        block.append(signed.clone()).unwrap();
+        let stored = &block.records().unwrap()[0];
+        assert_eq!(&signed, stored);
+        assert_eq!(salary, stored.decrypt(&employee).unwrap());
+    }
+}
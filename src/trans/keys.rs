@@ -0,0 +1,533 @@
+//! Key rotation and revocation.
+//!
+//! Signers announce changes to their keys with [`KeyEvent`] records:
+//!
+//! * [`KeyEvent::SetRecovery`] registers a recovery key that can later rotate or revoke the key, and
+//!   that only it can replace.
+//! * [`KeyEvent::Rotate`] replaces a key with a new one.
+//! * [`KeyEvent::Revoke`] retires a key for good.
+//!
+//! An event must be signed by the key it is about, or by its recovery key. A [`KeyHistory`] replays
+//! the events of a chain and tells whether a key was valid at a given block height, so that a
+//! `SignedRecord` from block `N` can be checked with [`KeyHistory::verify_at`]. A history belongs to
+//! one chain: events and records signed for another chain are rejected.
+//!
+//! Key events usually sit on the chain of the records they govern. The record type of such a chain
+//! implements [`KeyEventRecord`] to tell which of its records carry an event, typically with an enum
+//! that has a variant for `KeyEvent`, and [`KeyHistory::from_chain`] replays those events while
+//! skipping every other record.
+//!
+//! A rotation or revocation takes effect in the block that contains it: records in that block and
+//! later ones are no longer valid with the old key, while the new key of a rotation is valid from
+//! that block on.
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{
+//!     data::Metadata,
+//!     keys::{KeyEvent, KeyHistory},
+//!     record::Record,
+//!     Signer,
+//! };
+//!
+//! let old = blockify::generate_ed25519_keypair();
+//! let new = blockify::generate_ed25519_keypair();
+//! let payment = 100i64.record(&old, "bank", Metadata::empty()).unwrap();
+//!
+//! let mut history = KeyHistory::new("bank");
+//! let rotation = KeyEvent::Rotate { old: old.public_key(), new: new.public_key() };
+//! history.apply(5, &rotation.record(&old, "bank", Metadata::empty()).unwrap()).unwrap();
+//!
+//! assert!(history.verify_at(&payment, 4).is_ok());
+//! assert!(history.verify_at(&payment, 5).is_err());
+//! assert_eq!(Some(&new.public_key()), history.current(&old.public_key()));
+//! ```
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    block::{BlockError, ChainedInstance},
+    chain::{Chain, ChainError},
+    record::{Record, SignedRecord},
+    PublicKey, VerificationError,
+};
+
+/// A change to the keys of a signer, recorded on a chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// Makes `recovery` the recovery key of `key`, replacing any previous one.
+    ///
+    /// Once a key has a recovery key, only the recovery key may replace it.
+    SetRecovery { key: PublicKey, recovery: PublicKey },
+    /// Replaces `old` with `new`, which keeps the recovery key of `old`.
+    Rotate { old: PublicKey, new: PublicKey },
+    /// Revokes `key`.
+    Revoke { key: PublicKey },
+}
+
+impl KeyEvent {
+    /// Returns the key this event changes.
+    pub fn key(&self) -> &PublicKey {
+        match self {
+            KeyEvent::SetRecovery { key, .. } => key,
+            KeyEvent::Rotate { old, .. } => old,
+            KeyEvent::Revoke { key } => key,
+        }
+    }
+}
+
+crate::record::impl_record_for!(KeyEvent, "KeyEvent");
+
+/// A record type whose records may carry a [`KeyEvent`], so that key changes can be recorded on the
+/// chain of the records they govern.
+///
+/// # Examples
+///
+/// ```
+/// use blockify::{keys::{KeyEvent, KeyEventRecord}, record::Record};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// enum Ledger {
+///     Payment(i64),
+///     Key(KeyEvent),
+/// }
+///
+/// impl KeyEventRecord for Ledger {
+///     fn key_event(&self) -> Option<&KeyEvent> {
+///         match self {
+///             Ledger::Key(event) => Some(event),
+///             Ledger::Payment(_) => None,
+///         }
+///     }
+/// }
+/// ```
+pub trait KeyEventRecord: Record {
+    /// Returns the key event this record carries, if any.
+    fn key_event(&self) -> Option<&KeyEvent>;
+}
+
+impl KeyEventRecord for KeyEvent {
+    fn key_event(&self) -> Option<&KeyEvent> {
+        Some(self)
+    }
+}
+
+/// The reasons a `KeyEvent` is not applied to a `KeyHistory`
+#[derive(Debug, Clone)]
+pub enum KeyHistoryError {
+    /// The record does not carry a `KeyEvent`, see [`KeyEventRecord::key_event`].
+    NotKeyEvent,
+    /// The signature of the event does not verify, or it was signed for another chain.
+    InvalidSignature(VerificationError),
+    /// The event is signed neither by the key it changes nor by its recovery key.
+    Unauthorized,
+    /// The key the event changes is no longer, or not yet, valid at the height of the event.
+    KeyNotValid,
+    /// A recovery key is the key itself, or the new key of a rotation already has a history.
+    KeyInUse,
+    /// The event comes from a lower height than an event applied before it.
+    OutOfOrder,
+    /// The chain could not be read.
+    ChainError(ChainError),
+    /// A block of the chain could not be read.
+    BlockError(BlockError),
+}
+
+crate::impl_display_error!(KeyHistoryError);
+
+/// The status of a key at a block height, see [`KeyHistory::status_at`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyStatus {
+    /// The key may sign records.
+    Valid,
+    /// The key was rotated in at the height `since`, after the height asked for.
+    NotYetValid { since: u64 },
+    /// The key was replaced by `new` at the height `height`.
+    Rotated { height: u64, new: PublicKey },
+    /// The key was revoked at the height `height`.
+    Revoked { height: u64 },
+}
+
+#[derive(Debug, Clone, Default)]
+struct KeyState {
+    since: u64,
+    recovery: Option<PublicKey>,
+    retired: Option<(u64, Option<PublicKey>)>,
+}
+
+/// The history of the keys of all signers on one chain, replayed from the `KeyEvent`s carried by
+/// records of type `R`.
+///
+/// Keys that no event mentions are valid at every height.
+#[derive(Debug, Clone)]
+pub struct KeyHistory<R = KeyEvent> {
+    chain_id: String,
+    keys: HashMap<PublicKey, KeyState>,
+    events: Vec<(u64, SignedRecord<R>)>,
+    rejected: Vec<(u64, SignedRecord<R>, KeyHistoryError)>,
+}
+
+impl<R: KeyEventRecord + Clone> KeyHistory<R> {
+    /// Creates a history of the chain `chain_id` in which every key is valid.
+    pub fn new(chain_id: &str) -> Self {
+        Self {
+            chain_id: chain_id.to_owned(),
+            keys: HashMap::new(),
+            events: vec![],
+            rejected: vec![],
+        }
+    }
+
+    /// Returns the identifier of the chain the events and records must be signed for.
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    /// Replays the `KeyEvent`s of all blocks of `chain`, using block positions as heights.
+    ///
+    /// Records without an event are skipped. Events that are not valid, such as revocations signed
+    /// by an unrelated key, are not applied but kept with the reason, see [`KeyHistory::rejected`].
+    ///
+    /// Fails with `ChainError(AbsentValue)` if the chain has no identifier, see [`Chain::chain_id`].
+    pub fn from_chain<C: Chain<R>>(chain: &C) -> Result<Self, KeyHistoryError> {
+        let chain_id = chain
+            .chain_id()
+            .map_err(KeyHistoryError::ChainError)?
+            .ok_or(KeyHistoryError::ChainError(ChainError::AbsentValue))?;
+        let mut history = Self::new(&chain_id);
+        for height in 1..=chain.len().map_err(KeyHistoryError::ChainError)? {
+            let block = chain
+                .block_at(height.into())
+                .map_err(KeyHistoryError::ChainError)?;
+            let records = block.records().map_err(KeyHistoryError::BlockError)?;
+            for record in records.iter() {
+                if record.record().key_event().is_none() {
+                    continue;
+                }
+                if let Err(err) = history.apply(height, record) {
+                    history.rejected.push((height, record.clone(), err));
+                }
+            }
+        }
+        Ok(history)
+    }
+
+    /// Applies the `KeyEvent` in `record`, found in the block at height `height`.
+    ///
+    /// Events must be applied in the order they appear on the chain.
+    ///
+    /// # Errors
+    ///
+    /// * `NotKeyEvent` - If `record` does not carry a `KeyEvent`.
+    /// * `InvalidSignature` - If the signature of `record` does not verify for the chain of the history.
+    /// * `Unauthorized` - If `record` is signed neither by the key it changes nor its recovery key,
+    ///   or it replaces a recovery key and is not signed by that recovery key.
+    /// * `KeyNotValid` - If the key it changes, or the signing recovery key, is not valid at `height`.
+    /// * `KeyInUse` - If a recovery key is the key itself, or the new key of a rotation already has a
+    ///   history.
+    /// * `OutOfOrder` - If an event from a greater height was applied before.
+    pub fn apply(&mut self, height: u64, record: &SignedRecord<R>) -> Result<(), KeyHistoryError> {
+        let event = record
+            .record()
+            .key_event()
+            .ok_or(KeyHistoryError::NotKeyEvent)?;
+        if matches!(self.events.last(), Some((last, _)) if *last > height) {
+            return Err(KeyHistoryError::OutOfOrder);
+        }
+        if record.chain_id() != self.chain_id {
+            return Err(KeyHistoryError::InvalidSignature(
+                VerificationError::NoMatch,
+            ));
+        }
+        record
+            .verify_for(&self.chain_id)
+            .map_err(KeyHistoryError::InvalidSignature)?;
+
+        let key = event.key();
+        let signer = record.signer();
+        if !self.is_valid_at(key, height) {
+            return Err(KeyHistoryError::KeyNotValid);
+        }
+        if signer != key {
+            match self.keys.get(key).and_then(|state| state.recovery.as_ref()) {
+                Some(recovery) if recovery == signer => {}
+                _ => return Err(KeyHistoryError::Unauthorized),
+            }
+            if !self.is_valid_at(signer, height) {
+                return Err(KeyHistoryError::KeyNotValid);
+            }
+        }
+
+        match event {
+            KeyEvent::SetRecovery { key, recovery } => {
+                if recovery == key {
+                    return Err(KeyHistoryError::KeyInUse);
+                }
+                // a stolen key must not be able to lock out its recovery key
+                if self.recovery(key).is_some_and(|current| current != signer) {
+                    return Err(KeyHistoryError::Unauthorized);
+                }
+                self.keys.entry(key.clone()).or_default().recovery = Some(recovery.clone());
+            }
+            KeyEvent::Rotate { old, new } => {
+                if new == old || self.keys.contains_key(new) {
+                    return Err(KeyHistoryError::KeyInUse);
+                }
+                let state = self.keys.entry(old.clone()).or_default();
+                state.retired = Some((height, Some(new.clone())));
+                let recovery = state.recovery.clone();
+                self.keys.insert(
+                    new.clone(),
+                    KeyState {
+                        since: height,
+                        recovery,
+                        retired: None,
+                    },
+                );
+            }
+            KeyEvent::Revoke { key } => {
+                self.keys.entry(key.clone()).or_default().retired = Some((height, None));
+            }
+        }
+
+        self.events.push((height, record.clone()));
+        Ok(())
+    }
+
+    /// Returns the events [`KeyHistory::from_chain`] found on the chain but did not apply, with the
+    /// heights they were found at and the reasons they were rejected.
+    pub fn rejected(&self) -> &[(u64, SignedRecord<R>, KeyHistoryError)] {
+        &self.rejected
+    }
+
+    /// Returns the status of `key` at the height `height`.
+    pub fn status_at(&self, key: &PublicKey, height: u64) -> KeyStatus {
+        let state = match self.keys.get(key) {
+            Some(state) => state,
+            None => return KeyStatus::Valid,
+        };
+        match &state.retired {
+            Some((retired, new)) if *retired <= height => match new {
+                Some(new) => KeyStatus::Rotated {
+                    height: *retired,
+                    new: new.clone(),
+                },
+                None => KeyStatus::Revoked { height: *retired },
+            },
+            _ if state.since > height => KeyStatus::NotYetValid { since: state.since },
+            _ => KeyStatus::Valid,
+        }
+    }
+
+    /// Returns `true` if `key` may sign records at the height `height`.
+    pub fn is_valid_at(&self, key: &PublicKey, height: u64) -> bool {
+        self.status_at(key, height) == KeyStatus::Valid
+    }
+
+    /// Returns the recovery key of `key`, if it has one.
+    pub fn recovery(&self, key: &PublicKey) -> Option<&PublicKey> {
+        self.keys.get(key)?.recovery.as_ref()
+    }
+
+    /// Follows the rotations of `key` to the key in use today, or `None` if it ends in a revocation.
+    pub fn current<'a>(&'a self, mut key: &'a PublicKey) -> Option<&'a PublicKey> {
+        while let Some((_, retired)) = self.keys.get(key).and_then(|state| state.retired.as_ref()) {
+            key = retired.as_ref()?;
+        }
+        Some(key)
+    }
+
+    /// Returns the applied events that rotated in or changed `key`, with the heights they were found at.
+    pub fn history<'a>(
+        &'a self,
+        key: &'a PublicKey,
+    ) -> impl Iterator<Item = (u64, &'a SignedRecord<R>)> + 'a {
+        self.events
+            .iter()
+            .filter(move |(_, record)| match record.record().key_event() {
+                Some(KeyEvent::Rotate { new, .. }) if new == key => true,
+                Some(event) => event.key() == key,
+                None => false,
+            })
+            .map(|(height, record)| (*height, record))
+    }
+
+    /// Verifies `record` like [`SignedRecord::verify_for`] the chain of the history, and that its
+    /// signer's key was valid at the height `height` of the block holding it.
+    pub fn verify_at<T: Record>(
+        &self,
+        record: &SignedRecord<T>,
+        height: u64,
+    ) -> Result<(), VerificationError> {
+        if !self.is_valid_at(record.signer(), height) {
+            return Err(VerificationError::KeyNotValid);
+        }
+        if record.chain_id() != self.chain_id {
+            return Err(VerificationError::NoMatch);
+        }
+        record.verify_for(&self.chain_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyEvent, KeyHistory, KeyHistoryError, KeyStatus};
+    use crate::{data::Metadata, record::Record, Signer, VerificationError};
+
+    #[test]
+    fn rotation_test() {
+        let [old, new, newer] = [(); 3].map(|_| crate::generate_ed25519_keypair());
+        let mut history = KeyHistory::new("test");
+
+        let rotate = |from: &crate::AuthKeyPair, to: &crate::AuthKeyPair| {
+            KeyEvent::Rotate {
+                old: from.public_key(),
+                new: to.public_key(),
+            }
+            .record(from, "test", Metadata::empty())
+            .unwrap()
+        };
+        history.apply(3, &rotate(&old, &new)).unwrap();
+        history.apply(7, &rotate(&new, &newer)).unwrap();
+
+        assert!(history.is_valid_at(&old.public_key(), 2));
+        assert_eq!(
+            KeyStatus::Rotated {
+                height: 3,
+                new: new.public_key()
+            },
+            history.status_at(&old.public_key(), 3)
+        );
+        assert_eq!(
+            KeyStatus::NotYetValid { since: 3 },
+            history.status_at(&new.public_key(), 2)
+        );
+        assert!(history.is_valid_at(&new.public_key(), 6));
+        assert!(!history.is_valid_at(&new.public_key(), 7));
+        assert_eq!(
+            Some(&newer.public_key()),
+            history.current(&old.public_key())
+        );
+        assert_eq!(2, history.history(&new.public_key()).count());
+
+        // a retired key can no longer rotate
+        assert!(matches!(
+            history.apply(8, &rotate(&old, &crate::generate_ed25519_keypair())),
+            Err(KeyHistoryError::KeyNotValid)
+        ));
+        assert!(matches!(
+            history.apply(2, &rotate(&newer, &old)),
+            Err(KeyHistoryError::OutOfOrder)
+        ));
+
+        // events and records signed for another chain are not accepted
+        let replayed = KeyEvent::Revoke {
+            key: newer.public_key(),
+        }
+        .record(&newer, "other", Metadata::empty())
+        .unwrap();
+        assert!(matches!(
+            history.apply(9, &replayed),
+            Err(KeyHistoryError::InvalidSignature(_))
+        ));
+        assert!(history.is_valid_at(&newer.public_key(), 9));
+        let elsewhere = 10i64.record(&new, "other", Metadata::empty()).unwrap();
+        assert!(history.verify_at(&elsewhere, 5).is_err());
+
+        let record = 10i64.record(&new, "test", Metadata::empty()).unwrap();
+        assert!(history.verify_at(&record, 5).is_ok());
+        assert_eq!(
+            Err(VerificationError::KeyNotValid),
+            history.verify_at(&record, 2)
+        );
+        assert_eq!(
+            Err(VerificationError::KeyNotValid),
+            history.verify_at(&record, 9)
+        );
+    }
+
+    #[test]
+    fn revocation_test() {
+        let [key, recovery, thief] = [(); 3].map(|_| crate::generate_ed25519_keypair());
+        let mut history = KeyHistory::new("test");
+
+        let revoke = KeyEvent::Revoke {
+            key: key.public_key(),
+        };
+        // only the key itself or its recovery key may revoke it
+        assert!(matches!(
+            history.apply(
+                1,
+                &revoke
+                    .clone()
+                    .record(&thief, "test", Metadata::empty())
+                    .unwrap()
+            ),
+            Err(KeyHistoryError::Unauthorized)
+        ));
+
+        let set_recovery = KeyEvent::SetRecovery {
+            key: key.public_key(),
+            recovery: recovery.public_key(),
+        };
+        history
+            .apply(
+                1,
+                &set_recovery
+                    .record(&key, "test", Metadata::empty())
+                    .unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            Some(&recovery.public_key()),
+            history.recovery(&key.public_key())
+        );
+
+        // whoever stole the key cannot replace its recovery key with their own
+        let takeover = KeyEvent::SetRecovery {
+            key: key.public_key(),
+            recovery: thief.public_key(),
+        };
+        assert!(matches!(
+            history.apply(
+                2,
+                &takeover.record(&key, "test", Metadata::empty()).unwrap()
+            ),
+            Err(KeyHistoryError::Unauthorized)
+        ));
+        assert_eq!(
+            Some(&recovery.public_key()),
+            history.recovery(&key.public_key())
+        );
+
+        history
+            .apply(
+                4,
+                &revoke.record(&recovery, "test", Metadata::empty()).unwrap(),
+            )
+            .unwrap();
+        assert_eq!(
+            KeyStatus::Revoked { height: 4 },
+            history.status_at(&key.public_key(), 10)
+        );
+        assert!(history.is_valid_at(&key.public_key(), 3));
+        assert_eq!(None, history.current(&key.public_key()));
+
+        // a revoked key cannot be brought back by rotating it
+        let rotate = KeyEvent::Rotate {
+            old: key.public_key(),
+            new: thief.public_key(),
+        };
+        assert!(matches!(
+            history.apply(
+                5,
+                &rotate.record(&recovery, "test", Metadata::empty()).unwrap()
+            ),
+            Err(KeyHistoryError::KeyNotValid)
+        ));
+    }
+}
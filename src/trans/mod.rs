@@ -2,8 +2,15 @@ pub mod block;
 
 pub mod chain;
 
+pub mod encrypted;
+
+pub mod keys;
+
+pub mod multisig;
+
 pub mod record;
 
+pub mod upcast;
 
 mod sqlite;
 
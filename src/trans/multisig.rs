@@ -0,0 +1,475 @@
+//! Records that must be signed by several parties before they are valid.
+//!
+//! A [`MultiSignedRecord`] carries a [`SignerPolicy`], such as 2-of-3 of a listed key set, and
+//! collects [`PartialSignature`]s over time. It verifies only once enough distinct signers from the
+//! policy have signed. Every partial signature covers the record, the policy and the chain, so it cannot
+//! be reused for the same record under a different policy or on another chain.
+//!
+//! Once complete, the record is submitted by one party, whose signature wraps it into a
+//! `SignedRecord<MultiSignedRecord<R>>`. That can be stored in a `LocalInstance` or `SqliteChain` like
+//! any other record, and its `verify` checks the threshold as well as the submitter's signature.
+//! Batch and aggregate verification check the threshold too, see [`Record::verify_conditions`].
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{data::Metadata, multisig::{MultiSignedRecord, SignerPolicy}, Signer};
+//!
+//! let keys = (0..3).map(|_| blockify::generate_ed25519_keypair()).collect::<Vec<_>>();
+//! let policy = SignerPolicy::new(2, keys.iter().map(|key| key.public_key()).collect()).unwrap();
+//!
+//! let payment = String::from("pay 100 to carol");
+//! let mut payment = MultiSignedRecord::new(payment, policy, "ledger", Metadata::empty());
+//! payment.sign_with(&keys[0]).unwrap();
+//! assert!(payment.verify().is_err());
+//!
+//! payment.sign_with(&keys[2]).unwrap();
+//! assert!(payment.verify().is_ok());
+//!
+//! let signed = payment.submit(&keys[2]).unwrap();
+//! assert!(signed.verify().is_ok());
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::Metadata,
+    error::SerdeError,
+    record::{Record, SignedRecord},
+    DigitalSignature, Hash, HashAlgorithm, PublicKey, Signer, SigningDomain, SigningError,
+    VerificationError,
+};
+
+/// An error that can occur while building or verifying a multi-signed record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiSigError {
+    /// The threshold is zero or larger than the number of signers, or a signer is listed twice.
+    InvalidPolicy,
+    /// The key is not one of the signers of the policy.
+    NotInPolicy,
+    /// The key has already signed the record.
+    DuplicateSigner,
+    /// The signature does not verify for the record and policy.
+    InvalidSignature,
+    /// Fewer valid signatures than required by the policy are present.
+    ThresholdNotMet {
+        required: usize,
+        valid: usize,
+    },
+    SigningError(SigningError),
+    SerdeError(SerdeError),
+}
+
+crate::impl_display_error!(MultiSigError);
+
+impl From<SigningError> for MultiSigError {
+    fn from(value: SigningError) -> Self {
+        MultiSigError::SigningError(value)
+    }
+}
+
+impl From<SerdeError> for MultiSigError {
+    fn from(value: SerdeError) -> Self {
+        MultiSigError::SerdeError(value)
+    }
+}
+
+/// The keys that may sign a record and how many of them must.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SignerPolicy {
+    threshold: usize,
+    signers: Vec<PublicKey>,
+}
+
+impl SignerPolicy {
+    /// Creates a policy requiring `threshold` signatures out of `signers`.
+    ///
+    /// The keys may use any `KeyPairAlgorithm`, including a mix of them.
+    pub fn new(threshold: usize, signers: Vec<PublicKey>) -> Result<Self, MultiSigError> {
+        let distinct = signers
+            .iter()
+            .enumerate()
+            .all(|(i, key)| !signers[..i].contains(key));
+        if threshold == 0 || threshold > signers.len() || !distinct {
+            return Err(MultiSigError::InvalidPolicy);
+        }
+        Ok(Self { threshold, signers })
+    }
+
+    /// Returns the number of signatures required.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Returns the keys that may sign.
+    pub fn signers(&self) -> &[PublicKey] {
+        &self.signers
+    }
+
+    /// Returns `true` if `key` is one of the signers of this policy.
+    pub fn contains(&self, key: &PublicKey) -> bool {
+        self.signers.contains(key)
+    }
+}
+
+/// A signature by one of the signers of a policy.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PartialSignature {
+    signer: PublicKey,
+    signature: DigitalSignature,
+}
+
+impl PartialSignature {
+    pub fn new(signer: PublicKey, signature: DigitalSignature) -> Self {
+        Self { signer, signature }
+    }
+
+    pub fn signer(&self) -> &PublicKey {
+        &self.signer
+    }
+
+    pub fn signature(&self) -> &DigitalSignature {
+        &self.signature
+    }
+}
+
+/// A record that becomes valid once it is signed by enough of the signers of its policy.
+///
+/// # Type Parameters
+///
+/// - `R`: The type of transaction that is signed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MultiSignedRecord<R> {
+    record: R,
+    policy: SignerPolicy,
+    signatures: Vec<PartialSignature>,
+    hash: Hash,
+    chain_id: String,
+    metadata: Metadata,
+}
+
+impl<R> MultiSignedRecord<R> {
+    /// Returns a reference to the `Record` inside this instance
+    pub fn record(&self) -> &R {
+        &self.record
+    }
+
+    /// Returns the policy this record must satisfy
+    pub fn policy(&self) -> &SignerPolicy {
+        &self.policy
+    }
+
+    /// Returns the partial signatures collected so far
+    pub fn signatures(&self) -> &[PartialSignature] {
+        &self.signatures
+    }
+
+    /// Returns a reference to the hash of the `Record` inside this instance
+    pub fn hash(&self) -> &Hash {
+        &self.hash
+    }
+
+    /// Returns the identifier of the chain this record is signed for
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
+    /// Returns a reference to the `Metadata` associated with this instance
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl<R: Record> MultiSignedRecord<R> {
+    /// Creates an unsigned instance of `record` governed by `policy`, to be signed for the chain `chain_id`.
+    pub fn new(record: R, policy: SignerPolicy, chain_id: &str, metadata: Metadata) -> Self {
+        let hash = record.hash();
+        Self {
+            record,
+            policy,
+            signatures: vec![],
+            hash,
+            chain_id: chain_id.to_owned(),
+            metadata,
+        }
+    }
+
+    /// Returns the domain of the partial signatures.
+    pub fn domain(&self) -> SigningDomain {
+        SigningDomain::new(&self.chain_id, R::TYPE_ID, SigningDomain::MULTISIG)
+    }
+
+    /// Returns the bytes that each signer of the policy signs.
+    pub fn message(&self) -> Result<Vec<u8>, SerdeError> {
+        let payload = crate::canonical::encode(&(self.record.signing_bytes()?, &self.policy))?;
+        Ok(self.domain().message(&payload))
+    }
+
+    /// Creates a partial signature with `signer` without adding it, so that it can be sent elsewhere.
+    pub fn sign_partial<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+    ) -> Result<PartialSignature, MultiSigError> {
+        let signature = signer.sign(&self.message()?)?;
+        Ok(PartialSignature::new(signer.public_key(), signature))
+    }
+
+    /// Signs this record with `signer` and adds the signature.
+    ///
+    /// This is a partial signature of the policy, unlike [`Record::sign`] which signs as a submitter.
+    pub fn sign_with<S: Signer + ?Sized>(&mut self, signer: &S) -> Result<(), MultiSigError> {
+        let partial = self.sign_partial(signer)?;
+        self.add_signature(partial)
+    }
+
+    /// Adds a partial signature after checking it.
+    ///
+    /// # Errors
+    ///
+    /// * `NotInPolicy` - If the signer is not part of the policy.
+    /// * `DuplicateSigner` - If the signer has already signed.
+    /// * `InvalidSignature` - If the signature does not verify.
+    pub fn add_signature(&mut self, partial: PartialSignature) -> Result<(), MultiSigError> {
+        if !self.policy.contains(&partial.signer) {
+            return Err(MultiSigError::NotInPolicy);
+        }
+        if self.signatures.iter().any(|s| s.signer == partial.signer) {
+            return Err(MultiSigError::DuplicateSigner);
+        }
+        partial
+            .signer
+            .verify(&self.message()?, &partial.signature)
+            .map_err(|_| MultiSigError::InvalidSignature)?;
+        self.signatures.push(partial);
+        Ok(())
+    }
+
+    /// Returns the number of distinct signers of the policy with a valid signature.
+    pub fn valid_signatures(&self) -> Result<usize, SerdeError> {
+        let msg = self.message()?;
+        let valid = self
+            .signatures
+            .iter()
+            .enumerate()
+            .filter(|(i, partial)| {
+                self.policy.contains(&partial.signer)
+                    && !self.signatures[..*i]
+                        .iter()
+                        .any(|s| s.signer == partial.signer)
+                    && partial.signer.verify(&msg, &partial.signature).is_ok()
+            })
+            .count();
+        Ok(valid)
+    }
+
+    /// Returns `true` if the threshold of the policy is met.
+    pub fn is_complete(&self) -> bool {
+        self.verify().is_ok()
+    }
+
+    /// Verifies that enough distinct signers of the policy have validly signed this record.
+    pub fn verify(&self) -> Result<(), MultiSigError> {
+        let valid = self.valid_signatures()?;
+        let required = self.policy.threshold;
+        if valid < required {
+            return Err(MultiSigError::ThresholdNotMet { required, valid });
+        }
+        Ok(())
+    }
+}
+
+impl<R: Record + Serialize> MultiSignedRecord<R> {
+    /// Wraps this complete record into a `SignedRecord` signed by `submitter`, ready to be stored.
+    ///
+    /// Fails with `ThresholdNotMet` if the record is not complete yet.
+    pub fn submit<S: Signer>(self, submitter: S) -> Result<SignedRecord<Self>, MultiSigError> {
+        self.verify()?;
+        let metadata = self.metadata.clone();
+        let chain_id = self.chain_id.clone();
+        Ok(self.record(submitter, &chain_id, metadata)?)
+    }
+}
+
+impl<R: Record + Serialize> Record for MultiSignedRecord<R> {
+    const TYPE_ID: &'static str = "MultiSignedRecord";
+
+    fn sign<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        chain_id: &str,
+    ) -> Result<DigitalSignature, SigningError> {
+        let msg = self
+            .signed_message(chain_id)
+            .map_err(SigningError::SerdeError)?;
+        Signer::sign(signer, &msg)
+    }
+
+    /// Verifies the signature of the submitter, after checking the threshold of the policy.
+    ///
+    /// Fails with `NoMatch` if `chain_id` is not the chain the partial signatures were made for.
+    fn verify(
+        &self,
+        signature: &DigitalSignature,
+        pubkey: &PublicKey,
+        chain_id: &str,
+    ) -> Result<(), VerificationError> {
+        self.verify_conditions(chain_id)?;
+        let msg = self
+            .signing_bytes()
+            .map_err(VerificationError::SerdeError)?;
+        pubkey.verify_in(&SigningDomain::record::<Self>(chain_id), &msg, signature)
+    }
+
+    /// Checks that `chain_id` is the chain the partial signatures were made for and that the
+    /// threshold of the policy is met.
+    fn verify_conditions(&self, chain_id: &str) -> Result<(), VerificationError> {
+        if chain_id != self.chain_id {
+            return Err(VerificationError::NoMatch);
+        }
+        match MultiSignedRecord::verify(self) {
+            Ok(()) => Ok(()),
+            Err(MultiSigError::SerdeError(e)) => Err(VerificationError::SerdeError(e)),
+            Err(_) => Err(VerificationError::ThresholdNotMet),
+        }
+    }
+
+    fn record<S: Signer>(
+        self,
+        signer: S,
+        chain_id: &str,
+        metadata: Metadata,
+    ) -> Result<SignedRecord<Self>, SigningError> {
+        let signature = Record::sign(&self, &signer, chain_id)?;
+        let hash = Record::hash(&self);
+        Ok(SignedRecord::new(
+            self,
+            signature,
+            signer.public_key(),
+            hash,
+            chain_id,
+            metadata,
+        ))
+    }
+
+    fn hash(&self) -> Hash {
+        crate::hash(self)
+    }
+
+    fn hash_with(&self, algorithm: HashAlgorithm) -> Hash {
+        crate::hash_with(algorithm, self)
+    }
+
+    fn signing_bytes(&self) -> Result<Vec<u8>, SerdeError> {
+        crate::canonical::encode(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MultiSigError, MultiSignedRecord, PartialSignature, SignerPolicy};
+    use crate::{
+        block::{LocalInstance, UnchainedInstance},
+        data::Metadata,
+        generate_bls_keypair, generate_ecdsa_keypair, generate_ed25519_keypair,
+        record::{self, Record},
+        EcdsaSigningAlgorithm, Signer, VerificationError,
+    };
+
+    #[test]
+    fn threshold_test() {
+        let keys = [
+            generate_ed25519_keypair(),
+            generate_ecdsa_keypair(EcdsaSigningAlgorithm::P256_SHA256_FIXED),
+            generate_ecdsa_keypair(EcdsaSigningAlgorithm::P384_SHA384_ASN1),
+        ];
+        let signers = keys.iter().map(|key| key.public_key()).collect::<Vec<_>>();
+        assert_eq!(
+            Err(MultiSigError::InvalidPolicy),
+            SignerPolicy::new(4, signers.clone())
+        );
+        assert_eq!(
+            Err(MultiSigError::InvalidPolicy),
+            SignerPolicy::new(1, vec![signers[0].clone(), signers[0].clone()])
+        );
+        let policy = SignerPolicy::new(2, signers).unwrap();
+
+        let mut record = MultiSignedRecord::new(true, policy.clone(), "test", Metadata::empty());
+        assert_eq!(
+            Err(MultiSigError::ThresholdNotMet {
+                required: 2,
+                valid: 0
+            }),
+            record.verify()
+        );
+
+        // partial signatures can be produced elsewhere and added later
+        let partial = record.sign_partial(&keys[1]).unwrap();
+        record.add_signature(partial.clone()).unwrap();
+        assert_eq!(
+            Err(MultiSigError::DuplicateSigner),
+            record.add_signature(partial)
+        );
+        assert!(!record.is_complete());
+
+        let outsider = generate_ed25519_keypair();
+        assert_eq!(Err(MultiSigError::NotInPolicy), record.sign_with(&outsider));
+        let forged = PartialSignature::new(
+            keys[0].public_key(),
+            keys[0].sign(b"something else").unwrap(),
+        );
+        assert_eq!(
+            Err(MultiSigError::InvalidSignature),
+            record.add_signature(forged)
+        );
+
+        // the same signatures do not satisfy the record under another policy
+        let other = SignerPolicy::new(1, vec![keys[1].public_key()]).unwrap();
+        let mut moved = MultiSignedRecord::new(true, other, "test", Metadata::empty());
+        let reused = record.signatures()[0].clone();
+        assert_eq!(
+            Err(MultiSigError::InvalidSignature),
+            moved.add_signature(reused.clone())
+        );
+
+        // nor on another chain
+        let mut replayed = MultiSignedRecord::new(true, policy.clone(), "other", Metadata::empty());
+        assert_eq!(
+            Err(MultiSigError::InvalidSignature),
+            replayed.add_signature(reused)
+        );
+
+        record.sign_with(&keys[2]).unwrap();
+        assert!(record.verify().is_ok());
+
+        let submitted = record.clone().submit(&outsider).unwrap();
+        assert!(submitted.verify().is_ok());
+
+        let incomplete = MultiSignedRecord::new(false, policy, "test", Metadata::empty());
+        assert!(incomplete.clone().submit(&keys[0]).is_err());
+        let wrapped = incomplete
+            .clone()
+            .record(&keys[0], "test", Metadata::empty())
+            .unwrap();
+        assert!(wrapped.verify().is_err());
+
+        // batch and aggregate verification check the threshold as well as the submitter's signature
+        let batch = [submitted.clone(), wrapped];
+        assert_eq!(&[1], record::verify_batch(&batch).unwrap_err().failed());
+
+        let bls = generate_bls_keypair();
+        let batch = [
+            record.clone().submit(&bls).unwrap(),
+            incomplete.record(&bls, "test", Metadata::empty()).unwrap(),
+        ];
+        let aggregate = record::aggregate_signatures(&batch).unwrap();
+        assert_eq!(
+            Err(VerificationError::ThresholdNotMet),
+            record::verify_aggregate(&batch, &aggregate)
+        );
+        assert!(record::verify_aggregate(&batch[..1], batch[0].signature()).is_ok());
+
+        let mut block = LocalInstance::new(Metadata::empty(), 0);
+        block.append(submitted.clone()).unwrap();
+        assert_eq!(&[submitted], &*block.records().unwrap());
+    }
+}
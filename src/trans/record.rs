@@ -1,8 +1,13 @@
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    data::Metadata, AuthKeyPair, DigitalSignature, Hash, KeyPairAlgorithm, PublicKey, SigningError,
-    VerificationError,
+    address::{Address, AddressError},
+    batch::SignedMessage,
+    data::Metadata,
+    error::SerdeError,
+    DigitalSignature, Hash, HashAlgorithm, KeyPairAlgorithm, PublicKey, Signer, SigningDomain,
+    SigningError, VerificationError,
 };
 
 pub use record_derive::Record;
@@ -24,36 +29,150 @@ pub use record_derive::Record;
 /// }
 ///
 /// // Generate an `ed25519` key pair
-/// let keypair = blockify::generate_ed25519_key_pair();
+/// let keypair = blockify::generate_ed25519_keypair();
 ///
 /// // Create a `Vote` instance
 /// let my_record = Vote { session: 0, choice: 2 };
 ///
-/// // Sign `my_record` and obtain a `DigitalSignature`
-/// let signature = my_record.sign(&keypair).unwrap();
+/// // Sign `my_record` for the chain "elections" and obtain a `DigitalSignature`
+/// let signature = my_record.sign(&keypair, "elections").unwrap();
 ///
 /// // Verify the signature with the trait method `verify`
-/// assert!(my_record.verify(&signature, &keypair.into_public_key()).is_ok())
+/// let pubkey = keypair.into_public_key();
+/// assert!(my_record.verify(&signature, &pubkey, "elections").is_ok());
+///
+/// // The signature is not valid on any other chain
+/// assert!(my_record.verify(&signature, &pubkey, "other").is_err());
+/// ```
+///
+/// # Derive attributes
+///
+/// The derive macro signs and hashes the whole serde form of the type, which the `record` attribute
+/// changes:
+///
+/// - `#[record(type_id = "...")]` sets [`Record::TYPE_ID`] instead of the name of the type. Types
+///   with type or const parameters need it, since their instances would share the name.
+/// - `#[record(version = N)]` sets [`Record::VERSION`].
+/// - `#[record(hash_with = path)]` implements [`Record::hash_with`] with the function `path`, taking
+///   `&Self` and a `HashAlgorithm` and returning a `Hash`.
+/// - `#[record(validate = path)]` implements [`Record::validate`] with the function `path`, taking
+///   `&Self` and returning `Result<(), ValidationError>`.
+/// - `#[record(skip)]` on a field of a struct with named fields keeps it out of the signed and hashed
+///   payload. The remaining fields are then signed under the names serde gives them, and fields
+///   serde skips are not signed. Other serde attributes that change how the type or a remaining
+///   field is serialized, such as `flatten` or `serialize_with`, are rejected.
+///
+/// ```
+/// use blockify::record::Record;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// #[record(type_id = "elections/Vote", version = 2)]
+/// struct Vote {
+///     session: i32,
+///     choice: i32,
+///     #[record(skip)]
+///     received_at: u64,
+/// }
+///
+/// assert_eq!("elections/Vote", Vote::TYPE_ID);
+/// assert_eq!(2, Vote::VERSION);
+///
+/// let keypair = blockify::generate_ed25519_keypair();
+/// let mut vote = Vote { session: 0, choice: 2, received_at: 17 };
+/// let signature = vote.sign(&keypair, "elections").unwrap();
+///
+/// // the skipped field can change without invalidating the signature
+/// vote.received_at = 18;
+/// assert!(vote.verify(&signature, &keypair.into_public_key(), "elections").is_ok());
+/// ```
+///
+/// Misused attributes are compile errors:
+///
+/// ```compile_fail
+/// # use blockify::record::Record;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// #[record(version = "2")]
+/// struct Vote {
+///     choice: i32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use blockify::record::Record;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// #[record(skip)]
+/// struct Vote {
+///     choice: i32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use blockify::record::Record;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// struct Vote(i32, #[record(skip)] u64);
+/// ```
+///
+/// ```compile_fail
+/// # use blockify::record::Record;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// struct Ballot<T> {
+///     choice: T,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use blockify::record::Record;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// struct Vote {
+///     #[serde(serialize_with = "serde::Serialize::serialize")]
+///     choice: i32,
+///     #[record(skip)]
+///     received_at: u64,
+/// }
 /// ```
 pub trait Record: Sized {
-    /// Signs the record with the given key and returns the signature, if the signing succeeds
+    /// The identifier of this record type, which is part of the domain of its signatures.
+    ///
+    /// It must differ between the record types of a chain and must not change once records are signed.
+    /// The derive macro uses the name of the type, and requires it to be given for generic types.
+    const TYPE_ID: &'static str;
+
+    /// The schema version of this record type.
+    ///
+    /// It is not part of the signed message and should be bumped whenever the fields of the type
+    /// change. The derive macro uses `1` unless `#[record(version = N)]` is given.
+    const VERSION: u32 = 1;
+
+    /// Signs the record for the chain `chain_id` with the given signer and returns the signature, if the signing succeeds
     ///
     /// # Arguments
     ///
-    /// * `AuthKeyPair` - The private key to use for signing.
+    /// * `Signer` - The signer to use, such as an `AuthKeyPair` or a remote signer.
+    /// * `chain_id` - The identifier of the chain the signature is valid on.
     ///
     /// # Returns
     ///
     /// * `Ok(DigitalSignature)`
     /// * `Err(SigningError)`
-    fn sign(&self, keypair: &AuthKeyPair) -> Result<DigitalSignature, SigningError>;
+    fn sign<S: Signer + ?Sized>(
+        &self,
+        signer: &S,
+        chain_id: &str,
+    ) -> Result<DigitalSignature, SigningError>;
 
-    /// Attempts to verify the `DigitalSignature` for `self` with the given `PublicKey`
+    /// Attempts to verify the `DigitalSignature` for `self` on the chain `chain_id` with the given `PublicKey`
     ///
     /// # Arguments
     ///
     /// * `DigitalSignature`
     /// * `PublicKey`
+    /// * `chain_id`
     ///
     /// # Returns
     ///
@@ -63,10 +182,12 @@ pub trait Record: Sized {
         &self,
         signature: &DigitalSignature,
         pubkey: &PublicKey,
+        chain_id: &str,
     ) -> Result<(), VerificationError>;
 
-    /// Attempts to convert the given record into a `SignedRecord` instance by singing it with an `AuthKeyPair`.
+    /// Attempts to convert the given record into a `SignedRecord` instance for the chain `chain_id` by singing it with a `Signer`.
     ///
+    /// The signer may be an `AuthKeyPair`, a reference to one or any other `Signer`.
     /// This function accepts a `MetaData` type which may be empty (i.e `MetaData::empty()`).
     ///
     /// # Returns
@@ -74,27 +195,115 @@ pub trait Record: Sized {
     /// - `Ok(SignedRecord<T>)`
     /// - `Err(SigningError)`
     ///
-    fn record(
+    fn record<S: Signer>(
         self,
-        keypair: AuthKeyPair,
+        signer: S,
+        chain_id: &str,
         metadata: Metadata,
     ) -> Result<SignedRecord<Self>, SigningError>;
-    /// Computes and returns the hash of the record.
+    /// Computes and returns the SHA-256 hash of the record.
     ///
-    /// Implementations of this function `must not` fail.
+    /// Implementations of this function `must not` fail, and must return the same hash as
+    /// [`Record::hash_with`] with [`HashAlgorithm::Sha256`].
     fn hash(&self) -> Hash;
+
+    /// Computes and returns the hash of the record with `algorithm`, which is how a chain using that
+    /// algorithm identifies the record and the Merkle leaf of the record in its blocks.
+    ///
+    /// The default implementation hashes [`Record::signing_bytes`]. Implementations of this function
+    /// `must not` fail.
+    fn hash_with(&self, algorithm: HashAlgorithm) -> Hash {
+        algorithm.digest(
+            self.signing_bytes()
+                .expect("the record has no canonical encoding"),
+        )
+    }
+
+    /// Returns the bytes of the record that are signed by `sign` and checked by `verify`, before
+    /// the signing domain is added.
+    ///
+    /// Batch verification checks signatures against [`Record::signed_message`] directly, so `sign`
+    /// and `verify` must agree with this function.
+    fn signing_bytes(&self) -> Result<Vec<u8>, SerdeError>;
+
+    /// Returns the message that is signed for this record on the chain `chain_id`.
+    fn signed_message(&self, chain_id: &str) -> Result<Vec<u8>, SerdeError> {
+        Ok(SigningDomain::record::<Self>(chain_id).message(&self.signing_bytes()?))
+    }
+
+    /// Checks the conditions of the record on the chain `chain_id` that the signature of its submitter
+    /// does not cover, such as the threshold of a [`MultiSignedRecord`](crate::multisig::MultiSignedRecord).
+    ///
+    /// `verify` must run this check. Batch and aggregate verification only check signatures against
+    /// [`Record::signed_message`], so they run it for every record as well. The default accepts
+    /// every record.
+    fn verify_conditions(&self, chain_id: &str) -> Result<(), VerificationError> {
+        let _ = chain_id;
+        Ok(())
+    }
+
+    /// Checks the business rules of the record, such as the range of a field.
+    ///
+    /// Records that break them are rejected when they are appended to a block or a chain, see
+    /// [`SignedRecord::check`]. The default accepts every record.
+    fn validate(&self) -> Result<(), ValidationError> {
+        Ok(())
+    }
+}
+
+/// A business rule that a record breaks, returned by [`Record::validate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationError {
+    reason: &'static str,
+}
+
+impl ValidationError {
+    /// Creates an error for a record that breaks the rule described by `reason`.
+    pub const fn new(reason: &'static str) -> Self {
+        Self { reason }
+    }
+
+    /// Returns the description of the broken rule.
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+}
+
+crate::impl_display_error!(ValidationError);
+
+/// The reason a `SignedRecord` is rejected, see [`SignedRecord::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordError {
+    /// The signature does not verify.
+    VerificationError(VerificationError),
+
+    /// The hash is not the hash of the record.
+    HashMismatch,
+
+    /// The record breaks a business rule.
+    ValidationError(ValidationError),
+
+    /// The record was upcast from an older version and has to be signed again in its current version.
+    Upcast,
 }
 
+crate::impl_display_error!(RecordError);
+
 // This macro is not exported in favor of the derive macro Record which is also in this module.
 macro_rules! impl_record_for {
-    ($type:ty) => {
+    ($type:ty, $type_id:literal) => {
         impl Record for $type {
-            fn sign(
+            const TYPE_ID: &'static str = $type_id;
+
+            fn sign<S: crate::Signer + ?Sized>(
                 &self,
-                key: &crate::AuthKeyPair,
+                signer: &S,
+                chain_id: &str,
             ) -> Result<crate::DigitalSignature, crate::SigningError> {
-                let msg = crate::serialize(self).map_err(|e| SigningError::SerdeError(e))?;
-                let signature = crate::sign_msg(&msg, key)?;
+                let msg = self
+                    .signed_message(chain_id)
+                    .map_err(|e| crate::SigningError::SerdeError(e))?;
+                let signature = crate::Signer::sign(signer, &msg)?;
                 Ok(signature)
             }
 
@@ -102,24 +311,29 @@ macro_rules! impl_record_for {
                 &self,
                 signature: &crate::DigitalSignature,
                 key: &crate::PublicKey,
+                chain_id: &str,
             ) -> Result<(), crate::VerificationError> {
-                let msg =
-                    crate::serialize(self).map_err(|e| crate::VerificationError::SerdeError(e))?;
-                key.verify(&msg, signature)
+                let msg = self
+                    .signing_bytes()
+                    .map_err(|e| crate::VerificationError::SerdeError(e))?;
+                let domain = crate::SigningDomain::record::<Self>(chain_id);
+                key.verify_in(&domain, &msg, signature)
             }
 
-            fn record(
+            fn record<S: crate::Signer>(
                 self,
-                keypair: crate::AuthKeyPair,
+                signer: S,
+                chain_id: &str,
                 metadata: crate::data::Metadata,
             ) -> Result<crate::record::SignedRecord<Self>, crate::SigningError> {
-                let signature = self.sign(&keypair)?;
+                let signature = self.sign(&signer, chain_id)?;
                 let hash = self.hash();
                 Ok(crate::record::SignedRecord::new(
                     self,
                     signature,
-                    keypair.into_public_key(),
+                    crate::Signer::public_key(&signer),
                     hash,
+                    chain_id,
                     metadata,
                 ))
             }
@@ -127,14 +341,53 @@ macro_rules! impl_record_for {
             fn hash(&self) -> crate::Hash {
                 crate::hash(self)
             }
+
+            fn hash_with(&self, algorithm: crate::digest::HashAlgorithm) -> crate::Hash {
+                crate::hash_with(algorithm, self)
+            }
+
+            fn signing_bytes(&self) -> Result<Vec<u8>, crate::error::SerdeError> {
+                crate::canonical::encode(self)
+            }
         }
     };
 }
 
-impl_record_for!(String);
-impl_record_for!(bool);
-impl_record_for!(i64);
-impl_record_for!(Box<[u8]>);
+pub(crate) use impl_record_for;
+
+/// Support for the `Record` derive macro, which is not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    pub use serde::{ser::SerializeStruct, Serialize};
+
+    /// A type whose fields are signed one by one, because some of them are skipped.
+    pub trait SignedFields {
+        /// The name of the type.
+        const NAME: &'static str;
+
+        /// The number of signed fields.
+        const LEN: usize;
+
+        /// Serializes the signed fields into `state`.
+        fn serialize_fields<S: SerializeStruct>(&self, state: &mut S) -> Result<(), S::Error>;
+    }
+
+    /// Serializes the signed fields of a record as a struct.
+    pub struct Signed<'a, R>(pub &'a R);
+
+    impl<R: SignedFields> Serialize for Signed<'_, R> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct(R::NAME, R::LEN)?;
+            self.0.serialize_fields(&mut state)?;
+            state.end()
+        }
+    }
+}
+
+impl_record_for!(String, "String");
+impl_record_for!(bool, "bool");
+impl_record_for!(i64, "i64");
+impl_record_for!(Box<[u8]>, "Box<[u8]>");
 
 /// A `SignedRecord` represents a piece of blockchain transaction that is signed and hashed.
 ///
@@ -143,6 +396,7 @@ impl_record_for!(Box<[u8]>);
 /// - the `public key` of the signer of the record
 /// - the `algorithm` of the keypair used by the signer
 /// - the `hash` of the record
+/// - the identifier of the chain the signature is valid on
 /// - any associated `metadata`
 ///  
 ///
@@ -160,49 +414,47 @@ impl_record_for!(Box<[u8]>);
 /// use blockify::{data::Metadata, record::Record};
 /// use serde::{Deserialize, Serialize};
 ///
-/// fn main() {
-///    #[derive(Clone, Serialize, Deserialize, Record)]
-///    struct Vote {
-///        session: i32,
-///        choice: i32,
-///    }
+/// #[derive(Clone, Serialize, Deserialize, Record)]
+/// struct Vote {
+///     session: i32,
+///     choice: i32,
+/// }
 ///
-///    // Generate a new keypair
-///    let keypair = blockify::generate_ed25519_key_pair();
+/// // Generate a new keypair
+/// let keypair = blockify::generate_ed25519_keypair();
 ///
-///    // Clone the public key
-///    let pub_key = keypair.clone().into_public_key();
+/// // Clone the public key
+/// let pub_key = keypair.clone().into_public_key();
 ///
-///    // Create a new `Vote` instance
-///    let my_record = Vote {
-///        session: 0,
-///        choice: 2,
-///    };
+/// // Create a new `Vote` instance
+/// let my_record = Vote {
+///     session: 0,
+///     choice: 2,
+/// };
 ///
-///    // calculate the hash of my_record
-///    let my_record_hash = blockify::hash(&my_record);
+/// // calculate the hash of my_record
+/// let my_record_hash = blockify::hash(&my_record);
 ///
-///    // sign my_record with the AuthKeyPair instance and obtain a digital signature
-///    let signature = my_record.sign(&keypair).unwrap();
+/// // sign my_record with the AuthKeyPair instance and obtain a digital signature
+/// let signature = my_record.sign(&keypair, "elections").unwrap();
 ///
-///    // verify the authencity of the digital signature
-///    assert!(my_record.verify(&signature, &pub_key).is_ok());
+/// // verify the authencity of the digital signature
+/// assert!(my_record.verify(&signature, &pub_key, "elections").is_ok());
 ///
-///    // record the my_vote (convert it into a SignedRecord instance)
-///    let signed_record = my_record.record(keypair, Metadata::empty()).unwrap();
+/// // record the my_vote (convert it into a SignedRecord instance)
+/// let signed_record = my_record.record(keypair, "elections", Metadata::empty()).unwrap();
 ///
-///    // Compare the signature of `my_record` with that inside the `SignedRecord` instance
-///    assert_eq!(&signature, signed_record.signature());
+/// // Compare the signature of `my_record` with that inside the `SignedRecord` instance
+/// assert_eq!(&signature, signed_record.signature());
 ///
-///    // Compare the public key used to sign my_record with that inside the `SignedRecord` instance.
-///    assert_eq!(&pub_key, signed_record.signer());
+/// // Compare the public key used to sign my_record with that inside the `SignedRecord` instance.
+/// assert_eq!(&pub_key, signed_record.signer());
 ///
-///    // Compare the hash of my_record with that inside the `SignedRecord` instance.
-///    assert_eq!(&my_record_hash, signed_record.hash());
+/// // Compare the hash of my_record with that inside the `SignedRecord` instance.
+/// assert_eq!(&my_record_hash, signed_record.hash());
 ///
-///    // Verify the validity of the signature within the `SignedRecord` instance.
-///    assert!(signed_record.verify().is_ok());
-///}
+/// // Verify the validity of the signature within the `SignedRecord` instance.
+/// assert!(signed_record.verify().is_ok());
 /// ```
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct SignedRecord<R> {
@@ -210,7 +462,50 @@ pub struct SignedRecord<R> {
     signature: DigitalSignature,
     hash: Hash,
     record: R,
+    chain_id: String,
     metadata: Metadata,
+    // only set by `upcast` while reading a block, never taken from serialized data
+    #[serde(skip)]
+    original: Option<OriginalRecord>,
+}
+
+/// The record that was signed for a `SignedRecord` whose record has since been upcast to a newer
+/// version, see [`crate::upcast`].
+///
+/// It is kept in memory only: serializing an upcast `SignedRecord` drops it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalRecord {
+    version: u32,
+    type_id: String,
+    signing_bytes: Vec<u8>,
+    // the hashes of the original record with every algorithm, in the order of `HashAlgorithm::ALL`
+    hashes: Vec<Hash>,
+}
+
+impl OriginalRecord {
+    /// Returns the schema version of the original record.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Returns the type identifier of the original record, see [`Record::TYPE_ID`].
+    pub fn type_id(&self) -> &str {
+        &self.type_id
+    }
+
+    /// Returns the bytes of the original record that were signed, see [`Record::signing_bytes`].
+    pub fn signing_bytes(&self) -> &[u8] {
+        &self.signing_bytes
+    }
+
+    /// Returns the hash of the original record with `algorithm`, see [`Record::hash_with`].
+    pub fn hash_with(&self, algorithm: HashAlgorithm) -> &Hash {
+        let index = HashAlgorithm::ALL
+            .iter()
+            .position(|candidate| *candidate == algorithm)
+            .expect("every algorithm is listed in HashAlgorithm::ALL");
+        &self.hashes[index]
+    }
 }
 
 impl<R> SignedRecord<R> {
@@ -220,6 +515,7 @@ impl<R> SignedRecord<R> {
         signature: DigitalSignature,
         signer: PublicKey,
         hash: Hash,
+        chain_id: &str,
         metadata: Metadata,
     ) -> Self {
         Self {
@@ -227,7 +523,9 @@ impl<R> SignedRecord<R> {
             signature,
             hash,
             signer,
+            chain_id: chain_id.to_owned(),
             metadata,
+            original: None,
         }
     }
 
@@ -254,19 +552,285 @@ impl<R> SignedRecord<R> {
         &self.hash
     }
 
+    /// Returns the identifier of the chain the signature of this `SignedRecord` instance is valid on
+    pub fn chain_id(&self) -> &str {
+        &self.chain_id
+    }
+
     // Returns a reference to the `Metadata` associated with this `SignedRecord` instance
     pub fn metadata(&self) -> &Metadata {
         &self.metadata
     }
+
+    /// Returns the address of the signer of this `SignedRecord` instance with the chain prefix `prefix`
+    pub fn signer_address(&self, prefix: &str) -> Result<Address, AddressError> {
+        self.signer.address(prefix)
+    }
+
+    /// Returns `true` if this `SignedRecord` instance was signed by the owner of `address`
+    pub fn is_signed_by(&self, address: &Address) -> bool {
+        address.matches(&self.signer)
+    }
+
+    /// Returns the record that was signed, if the record of this `SignedRecord` instance was upcast
+    /// from an older version
+    pub fn original(&self) -> Option<&OriginalRecord> {
+        self.original.as_ref()
+    }
 }
 
 impl<R: Record> SignedRecord<R> {
+    /// Signs `record` with `signer` and wraps it into a `SignedRecord` instance.
+    ///
+    /// This is the same as calling [`Record::record`] with a reference to the signer.
+    pub fn from_signer<S: Signer + ?Sized>(
+        record: R,
+        signer: &S,
+        chain_id: &str,
+        metadata: Metadata,
+    ) -> Result<Self, SigningError> {
+        record.record(signer, chain_id, metadata)
+    }
+
     /// Verifies the validity of the `DigitalSignature` within this `SignedRecord` instance for the `Record` it holds.
+    ///
+    /// The signature must have been made for the chain of this instance, see [`SignedRecord::chain_id`].
+    /// An upcast record is checked against the bytes of its original record.
     pub fn verify(&self) -> Result<(), VerificationError> {
-        self.record.verify(self.signature(), self.signer())
+        self.verify_for(self.chain_id())
+    }
+
+    /// Verifies this `SignedRecord` instance like [`SignedRecord::verify`], and that it was signed for the chain `chain_id`.
+    pub fn verify_for(&self, chain_id: &str) -> Result<(), VerificationError> {
+        match &self.original {
+            Some(original) => {
+                let domain = SigningDomain::new(chain_id, &original.type_id, SigningDomain::RECORD);
+                self.signer
+                    .verify_in(&domain, &original.signing_bytes, self.signature())
+            }
+            None => self
+                .record
+                .verify(self.signature(), self.signer(), chain_id),
+        }
+    }
+
+    /// Checks everything a block or a chain requires of a record before accepting it: the
+    /// signature, the hash, and the business rules of [`Record::validate`].
+    ///
+    /// An upcast record is rejected with `Upcast`, since its signature only covers the original
+    /// record, which is not stored along with it.
+    pub fn check(&self) -> Result<(), RecordError> {
+        self.check_for(self.chain_id())
+    }
+
+    /// Checks this `SignedRecord` instance like [`SignedRecord::check`], and that it was signed for the chain `chain_id`.
+    pub fn check_for(&self, chain_id: &str) -> Result<(), RecordError> {
+        if self.original.is_some() {
+            return Err(RecordError::Upcast);
+        }
+        if self.chain_id != chain_id {
+            return Err(RecordError::VerificationError(VerificationError::NoMatch));
+        }
+        self.verify_for(chain_id)
+            .map_err(RecordError::VerificationError)?;
+        if self.record.hash_with(self.hash.algorithm()) != self.hash {
+            return Err(RecordError::HashMismatch);
+        }
+        self.record.validate().map_err(RecordError::ValidationError)
+    }
+
+    /// Returns the hash of the record with `algorithm`, see [`Record::hash_with`], which is the
+    /// hash of the original record if the record was upcast.
+    pub fn hash_with(&self, algorithm: HashAlgorithm) -> Hash {
+        match &self.original {
+            Some(original) => original.hash_with(algorithm).clone(),
+            None => self.record.hash_with(algorithm),
+        }
+    }
+
+    /// Returns this `SignedRecord` instance with its hash computed with `algorithm`, which is how a
+    /// block hashed with that algorithm stores it.
+    ///
+    /// The hash is not signed, so this keeps the signature valid.
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        if self.hash.algorithm() != algorithm {
+            self.hash = self.hash_with(algorithm);
+        }
+        self
+    }
+
+    /// Returns the message that is signed for this `SignedRecord` instance, which is the one of the
+    /// original record if the record was upcast.
+    pub fn signed_message(&self) -> Result<Vec<u8>, SerdeError> {
+        match &self.original {
+            Some(original) => {
+                let domain =
+                    SigningDomain::new(&self.chain_id, &original.type_id, SigningDomain::RECORD);
+                Ok(domain.message(&original.signing_bytes))
+            }
+            None => self.record.signed_message(&self.chain_id),
+        }
+    }
+
+    /// Converts the record into `T` with `upcast`, keeping the signature, the hash and the
+    /// original record, so that the result still verifies.
+    pub(crate) fn upcast<T, F: FnOnce(R) -> T>(
+        self,
+        upcast: F,
+    ) -> Result<SignedRecord<T>, SerdeError> {
+        let original = match self.original {
+            Some(original) => original,
+            None => OriginalRecord {
+                version: R::VERSION,
+                type_id: R::TYPE_ID.to_owned(),
+                signing_bytes: self.record.signing_bytes()?,
+                hashes: HashAlgorithm::ALL
+                    .iter()
+                    .map(|algorithm| self.record.hash_with(*algorithm))
+                    .collect(),
+            },
+        };
+        Ok(SignedRecord {
+            signer: self.signer,
+            signature: self.signature,
+            hash: self.hash,
+            record: upcast(self.record),
+            chain_id: self.chain_id,
+            metadata: self.metadata,
+            original: Some(original),
+        })
     }
 }
 
+/// An error returned by batch verification, holding the positions of the records that failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchVerificationError {
+    failed: Vec<usize>,
+}
+
+impl BatchVerificationError {
+    /// Returns the indices of the records that failed verification, in ascending order.
+    pub fn failed(&self) -> &[usize] {
+        &self.failed
+    }
+}
+
+crate::impl_display_error!(BatchVerificationError);
+
+/// Verifies the signatures of all `records` at once.
+///
+/// Every record is serialized once, in parallel, together with its signing domain, and the signatures are checked with
+/// [`crate::batch::verify_batch`]. A record that cannot be serialized or fails
+/// [`Record::verify_conditions`] counts as failed.
+///
+/// # Examples
+///
+/// ```
+/// use blockify::{data::Metadata, record::{self, Record}};
+///
+/// let keypair = blockify::generate_ed25519_keypair();
+/// let records = (0..10)
+///     .map(|i| format!("entry {i}").record(&keypair, "journal", Metadata::empty()).unwrap())
+///     .collect::<Vec<_>>();
+///
+/// assert!(record::verify_batch(&records).is_ok());
+/// ```
+pub fn verify_batch<R: Record + Sync>(
+    records: &[SignedRecord<R>],
+) -> Result<(), BatchVerificationError> {
+    let messages = records
+        .par_iter()
+        .map(|record| {
+            record.record().verify_conditions(record.chain_id()).ok()?;
+            record.signed_message().ok()
+        })
+        .collect::<Vec<_>>();
+
+    let mut indices = vec![];
+    let mut batch = vec![];
+    let mut failed = vec![];
+    for (index, (record, msg)) in records.iter().zip(&messages).enumerate() {
+        match msg {
+            Some(msg) => {
+                indices.push(index);
+                batch.push(SignedMessage::new(msg, record.signature(), record.signer()));
+            }
+            None => failed.push(index),
+        }
+    }
+
+    failed.extend(
+        crate::batch::verify_batch(&batch)
+            .into_iter()
+            .map(|position| indices[position]),
+    );
+    if failed.is_empty() {
+        return Ok(());
+    }
+    failed.sort_unstable();
+    Err(BatchVerificationError { failed })
+}
+
+/// Aggregates the BLS12-381 signatures of `records` into one signature, see [`crate::bls`].
+///
+/// A block can carry the aggregate in place of the individual signatures, which is then checked
+/// with [`verify_aggregate`].
+///
+/// # Errors
+///
+/// * `InvalidSignature` - If `records` is empty or a record is not signed with a BLS12-381 key.
+///
+/// # Examples
+///
+/// ```
+/// use blockify::{data::Metadata, record::{self, Record}};
+///
+/// let keypairs = (0..3).map(|_| blockify::generate_bls_keypair()).collect::<Vec<_>>();
+/// let records = keypairs
+///     .iter()
+///     .map(|keypair| "present".to_owned().record(keypair, "attendance", Metadata::empty()).unwrap())
+///     .collect::<Vec<_>>();
+///
+/// let aggregate = record::aggregate_signatures(&records).unwrap();
+/// assert!(record::verify_aggregate(&records, &aggregate).is_ok());
+/// assert!(record::verify_aggregate(&records[1..], &aggregate).is_err());
+/// ```
+pub fn aggregate_signatures<R>(
+    records: &[SignedRecord<R>],
+) -> Result<DigitalSignature, VerificationError> {
+    let signatures = records
+        .iter()
+        .map(|record| record.signature().clone())
+        .collect::<Vec<_>>();
+    crate::bls::aggregate(&signatures)
+}
+
+/// Verifies an aggregate of the signatures of `records`, made with [`aggregate_signatures`].
+///
+/// Every record is checked against its signer and its signing domain, as in [`SignedRecord::verify`],
+/// with a single pairing check, and [`Record::verify_conditions`] is run for every record. Records
+/// with the same content may come from any signers, see [`crate::bls`].
+pub fn verify_aggregate<R: Record + Sync>(
+    records: &[SignedRecord<R>],
+    aggregate: &DigitalSignature,
+) -> Result<(), VerificationError> {
+    let messages = records
+        .par_iter()
+        .map(|record| {
+            record.record().verify_conditions(record.chain_id())?;
+            record
+                .signed_message()
+                .map_err(VerificationError::SerdeError)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let messages = messages.iter().map(Vec::as_slice).collect::<Vec<_>>();
+    let signers = records
+        .iter()
+        .map(|record| record.signer().clone())
+        .collect::<Vec<_>>();
+    crate::bls::verify_aggregate(&messages, &signers, aggregate)
+}
+
 impl<R> AsRef<R> for SignedRecord<R> {
     fn as_ref(&self) -> &R {
         self.record()
@@ -297,28 +861,58 @@ impl<'a, R> Records<'a, R> {
     pub fn as_slice(&self) -> &[SignedRecord<R>] {
         match self {
             Records::Owned(v) => v,
-            Records::Borrowed(u) => *u,
+            Records::Borrowed(u) => u,
         }
     }
 
-    pub fn iter(&self) -> std::slice::Iter<SignedRecord<R>> {
+    pub fn iter(&self) -> std::slice::Iter<'_, SignedRecord<R>> {
         self.as_slice().iter()
     }
 
     pub fn unwrap(&self) -> &Vec<SignedRecord<R>> {
         match self {
             Records::Owned(v) => v,
-            Records::Borrowed(u) => *u,
+            Records::Borrowed(u) => u,
         }
     }
 
     pub fn unwrap_owned(self) -> Vec<SignedRecord<R>> {
         match self {
             Records::Owned(v) => v,
-            Records::Borrowed(_) => panic!("Unwrapping not possible")
+            Records::Borrowed(_) => panic!("Unwrapping not possible"),
         }
     }
 
+    /// Verifies the signatures of all records at once, see [`verify_batch`].
+    pub fn verify_batch(&self) -> Result<(), BatchVerificationError>
+    where
+        R: Record + Sync,
+    {
+        verify_batch(self.as_slice())
+    }
+
+    /// Returns the records signed by the owner of `address`.
+    pub fn signed_by<'b>(
+        &'b self,
+        address: &'b Address,
+    ) -> impl Iterator<Item = &'b SignedRecord<R>> + 'b {
+        self.iter()
+            .filter(move |record| record.is_signed_by(address))
+    }
+
+    /// Aggregates the BLS12-381 signatures of all records, see [`aggregate_signatures`].
+    pub fn aggregate_signatures(&self) -> Result<DigitalSignature, VerificationError> {
+        aggregate_signatures(self.as_slice())
+    }
+
+    /// Verifies an aggregate of the signatures of all records, see [`verify_aggregate`].
+    pub fn verify_aggregate(&self, aggregate: &DigitalSignature) -> Result<(), VerificationError>
+    where
+        R: Record + Sync,
+    {
+        verify_aggregate(self.as_slice(), aggregate)
+    }
+
     pub fn into_inner(self) -> Vec<SignedRecord<R>>
     where
         R: Clone,
@@ -18,14 +18,19 @@ use crate::{
 // Hash
 // seal
 
-table! {
-    records {
-        id -> Integer,
-        jsonvalues -> Text,
+// Kept private so that the glob re-export of this module does not clash with the `records` table
+// of `SqliteBlock`.
+mod schema {
+    diesel::table! {
+        records {
+            id -> Integer,
+            jsonvalues -> Text,
+        }
     }
 }
 
 pub struct GenericBlock<R> {
+    #[allow(dead_code)]
     con: WrapperMut<SqliteConnection>,
     _data: PhantomData<R>,
 }
@@ -79,13 +84,13 @@ impl<R: Record + Serialize> UnchainedInstance<R> for GenericBlock<R> {
         let hash = {
             let hash = Record::hash(&*item);
             let prev_hash = self.prev_hash()?;
-            
+
             let merkle_root = ChainedInstance::merkle_root(self)?;
 
             crate::sha_all([hash, prev_hash, merkle_root])
         };
-        let json_hash = serde_json::to_string(&hash).unwrap();
-        let json_record = serde_json::to_string(&item).unwrap();
+        let _json_hash = serde_json::to_string(&hash).unwrap();
+        let _json_record = serde_json::to_string(&item).unwrap();
 
         todo!()
     }
@@ -94,17 +99,33 @@ impl<R: Record + Serialize> UnchainedInstance<R> for GenericBlock<R> {
         todo!()
     }
 
-    fn records(&self) -> Result<Records<R>, BlockError> {
+    fn records(&self) -> Result<Records<'_, R>, BlockError> {
         todo!()
     }
 
     fn merkle_root(&self) -> Result<Hash, BlockError> {
         todo!()
     }
+
+    fn remove(&mut self, _index: usize) -> Result<SignedRecord<R>, BlockError> {
+        todo!()
+    }
+
+    fn replace(
+        &mut self,
+        _index: usize,
+        _item: SignedRecord<R>,
+    ) -> Result<SignedRecord<R>, BlockError> {
+        todo!()
+    }
+
+    fn truncate(&mut self, _len: usize) -> Result<(), BlockError> {
+        todo!()
+    }
 }
 
 impl<R: Record> ChainedInstance<R> for GenericBlock<R> {
-    fn records(&self) -> Result<Records<R>, BlockError> {
+    fn records(&self) -> Result<Records<'_, R>, BlockError> {
         todo!()
     }
 
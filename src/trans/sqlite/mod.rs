@@ -1,8 +1,10 @@
+mod generic;
+mod node_store;
 mod sqlite_block;
 mod sqlite_chain;
-mod generic;
 
 pub use generic::*;
+pub use node_store::SqliteNodeStore;
 pub use sqlite_block::*;
 pub use sqlite_chain::*;
 
@@ -18,6 +20,7 @@ pub struct TempInstance {
     pub merkle_root: Hash,
     pub timestamp: Timestamp,
     pub position: Position,
+    pub state_root: Option<Hash>,
 }
 
 impl TempInstance {
@@ -28,6 +31,7 @@ impl TempInstance {
         hash: Hash,
         prev_hash: Hash,
         merkle_root: Hash,
+        state_root: Option<Hash>,
     ) -> Self {
         Self {
             nonce,
@@ -36,6 +40,7 @@ impl TempInstance {
             prev_hash,
             merkle_root,
             timestamp,
+            state_root,
         }
     }
 }
@@ -51,6 +56,7 @@ impl<T> WrapperMut<T> {
         }
     }
 
+    #[allow(clippy::mut_from_ref)]
     pub fn get_mut(&self) -> &mut T {
         unsafe { self.val.get().as_mut().unwrap() }
     }
@@ -0,0 +1,74 @@
+use diesel::{insert_or_ignore_into, prelude::*};
+
+use crate::{
+    error::{DataBaseError, SerdeError},
+    merkle::sparse::{NodeStore, SparseMerkleError, SparseNode},
+    Hash,
+};
+
+use super::WrapperMut;
+
+table! {
+    sparse_nodes (hash) {
+        hash -> Text,
+        node -> Text,
+    }
+}
+
+/// A [`NodeStore`] that keeps the nodes of sparse Merkle trees in a SQLite database.
+pub struct SqliteNodeStore {
+    con: WrapperMut<SqliteConnection>,
+}
+
+impl SqliteNodeStore {
+    /// Opens the store in the SQLite database at `url`, creating it if it is absent.
+    pub fn new(url: &str) -> Result<Self, SparseMerkleError> {
+        let mut con = SqliteConnection::establish(url).map_err(|_| {
+            SparseMerkleError::DataBaseError(DataBaseError::ConnectionCannotEstablish)
+        })?;
+
+        diesel::sql_query(
+            "
+        CREATE TABLE IF NOT EXISTS sparse_nodes (
+            hash TEXT PRIMARY KEY,
+            node TEXT
+        )
+        ",
+        )
+        .execute(&mut con)
+        .map_err(|_| SparseMerkleError::DataBaseError(DataBaseError::ConnectionFailed))?;
+
+        Ok(Self {
+            con: WrapperMut::new(con),
+        })
+    }
+}
+
+impl NodeStore for SqliteNodeStore {
+    fn get(&self, hash: &Hash) -> Result<Option<SparseNode>, SparseMerkleError> {
+        let node = sparse_nodes::table
+            .select(sparse_nodes::node)
+            .filter(sparse_nodes::hash.eq(hash.to_hex()))
+            .first::<String>(self.con.get_mut())
+            .optional()
+            .map_err(|_| SparseMerkleError::DataBaseError(DataBaseError::NoSuchTable))?;
+
+        node.map(|json| serde_json::from_str(&json))
+            .transpose()
+            .map_err(|_| SparseMerkleError::SerdeError(SerdeError::DeserializationError))
+    }
+
+    fn put(&mut self, hash: &Hash, node: &SparseNode) -> Result<(), SparseMerkleError> {
+        let json = serde_json::to_string(node)
+            .map_err(|_| SparseMerkleError::SerdeError(SerdeError::SerializationError))?;
+        // nodes are addressed by their hashes, so a stored node never changes
+        insert_or_ignore_into(sparse_nodes::table)
+            .values((
+                sparse_nodes::hash.eq(hash.to_hex()),
+                sparse_nodes::node.eq(json),
+            ))
+            .execute(self.con.get_mut())
+            .map_err(|_| SparseMerkleError::DataBaseError(DataBaseError::NoSuchTable))?;
+        Ok(())
+    }
+}
@@ -1,16 +1,17 @@
 use diesel::prelude::*;
-use diesel::sql_types::Text;
-use diesel::sqlite::Sqlite;
 use serde::{Deserialize, Serialize};
-use std::marker::PhantomData;
+use std::{marker::PhantomData, sync::Arc};
 
 use crate::data::{Nonce, Position, Timestamp};
-use crate::error::SerdeError;
+use crate::error::{DataBaseError, SerdeError};
 use crate::{
-    block::ChainedInstance,
+    block::{self, BlockData, ChainedInstance},
+    chain::ChainError,
+    merkle::{self, MerkleProof, MerkleTree},
     record::{Record, Records},
+    upcast::Upcasters,
 };
-use crate::{Hash, SqliteChainError, TempInstance};
+use crate::{Hash, HashAlgorithm, SqliteChainError, TempInstance};
 
 use super::WrapperMut;
 
@@ -21,6 +22,28 @@ table! {
     }
 }
 
+table! {
+    merkle_nodes (level, position) {
+        level -> Integer,
+        position -> Integer,
+        hash -> Text,
+    }
+}
+
+table! {
+    record_schema {
+        id -> Integer,
+        version -> Integer,
+    }
+}
+
+table! {
+    state {
+        id -> Integer,
+        root -> Text,
+    }
+}
+
 table! {
     metadata {
         id -> Integer,
@@ -35,6 +58,7 @@ table! {
 
 pub struct SqliteBlock<X> {
     con: WrapperMut<SqliteConnection>,
+    upcasters: Option<Arc<Upcasters<X>>>,
     _data: PhantomData<X>,
 }
 
@@ -45,6 +69,7 @@ pub enum SqliteBlockError {
     ConnectionFailed,
 }
 
+#[allow(clippy::from_over_into)]
 impl Into<SqliteChainError> for SqliteBlockError {
     fn into(self) -> SqliteChainError {
         match self {
@@ -55,6 +80,20 @@ impl Into<SqliteChainError> for SqliteBlockError {
     }
 }
 
+impl From<SqliteBlockError> for ChainError {
+    fn from(value: SqliteBlockError) -> Self {
+        match value {
+            SqliteBlockError::ConnectionError(_) => {
+                ChainError::DataBaseError(DataBaseError::ConnectionCannotEstablish)
+            }
+            SqliteBlockError::ConnectionFailed => {
+                ChainError::DataBaseError(DataBaseError::ConnectionFailed)
+            }
+            SqliteBlockError::SerdeError(sd) => ChainError::SerdeError(sd),
+        }
+    }
+}
+
 impl std::fmt::Display for SqliteBlockError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         std::fmt::Debug::fmt(self, f)
@@ -72,11 +111,19 @@ impl<X: Record + Serialize> SqliteBlock<X> {
         let con = SqliteConnection::establish(url)?;
         let val = Self {
             con: WrapperMut::new(con),
+            upcasters: None,
             _data: PhantomData,
         };
         Ok(val)
     }
 
+    /// Reads the records of this block with `upcasters`, for blocks written with an older version
+    /// of the record type.
+    pub fn with_upcasters(mut self, upcasters: Arc<Upcasters<X>>) -> Self {
+        self.upcasters = Some(upcasters);
+        self
+    }
+
     fn create_tables(con: &mut SqliteConnection) -> Result<(), SqliteBlockError> {
         diesel::sql_query(
             "
@@ -104,6 +151,42 @@ impl<X: Record + Serialize> SqliteBlock<X> {
         .execute(con)
         .map_err(|_| SqliteBlockError::ConnectionFailed)?;
 
+        diesel::sql_query(
+            "
+        CREATE TABLE IF NOT EXISTS merkle_nodes (
+            level INTEGER,
+            position INTEGER,
+            hash TEXT,
+            PRIMARY KEY (level, position)
+        )",
+        )
+        .execute(con)
+        .map_err(|_| SqliteBlockError::ConnectionFailed)?;
+
+        diesel::sql_query("CREATE INDEX IF NOT EXISTS merkle_nodes_hash ON merkle_nodes (hash)")
+            .execute(con)
+            .map_err(|_| SqliteBlockError::ConnectionFailed)?;
+
+        diesel::sql_query(
+            "
+        CREATE TABLE IF NOT EXISTS record_schema (
+            id INTEGER PRIMARY KEY,
+            version INTEGER
+        )",
+        )
+        .execute(con)
+        .map_err(|_| SqliteBlockError::ConnectionFailed)?;
+
+        diesel::sql_query(
+            "
+        CREATE TABLE IF NOT EXISTS state (
+            id INTEGER PRIMARY KEY,
+            root TEXT
+        )",
+        )
+        .execute(con)
+        .map_err(|_| SqliteBlockError::ConnectionFailed)?;
+
         Ok(())
     }
 
@@ -119,6 +202,7 @@ impl<X: Record + Serialize> SqliteBlock<X> {
             prev_hash,
             merkle_root,
             timestamp,
+            state_root,
         } = cc;
         let val = Self::new(url)?;
         Self::create_tables(val.con.get_mut())?;
@@ -129,6 +213,9 @@ impl<X: Record + Serialize> SqliteBlock<X> {
 
         let prev_hash = serde_json::to_string(prev_hash).unwrap();
 
+        let algorithm = merkle_root.algorithm();
+        let tree = MerkleTree::from_leaves(algorithm, block::leaves(algorithm, records));
+
         let merkle_root = { serde_json::to_string(merkle_root).unwrap() };
 
         let nonce = { serde_json::to_string(nonce).unwrap() };
@@ -145,56 +232,188 @@ impl<X: Record + Serialize> SqliteBlock<X> {
         ));
 
         for record in records {
-            let smt = diesel::insert_into(records::table)
-                .values(records::jsonvalues.eq(serde_json::to_string(record).unwrap()));
-            smt.execute(val.con.get_mut()).unwrap();
+            let json = serde_json::to_string(record)
+                .map_err(|_| SqliteBlockError::SerdeError(SerdeError::SerializationError))?;
+            diesel::insert_into(records::table)
+                .values(records::jsonvalues.eq(json))
+                .execute(val.con.get_mut())
+                .map_err(|_| SqliteBlockError::ConnectionFailed)?;
         }
 
-        smt.execute(val.con.get_mut()).unwrap();
+        smt.execute(val.con.get_mut())
+            .map_err(|_| SqliteBlockError::ConnectionFailed)?;
+
+        Self::store_merkle_tree(val.con.get_mut(), &tree)?;
+
+        diesel::insert_into(record_schema::table)
+            .values(record_schema::version.eq(X::VERSION as i32))
+            .execute(val.con.get_mut())
+            .map_err(|_| SqliteBlockError::ConnectionFailed)?;
+
+        if let Some(state_root) = state_root {
+            let state_root = serde_json::to_string(state_root).unwrap();
+            diesel::insert_into(state::table)
+                .values(state::root.eq(state_root))
+                .execute(val.con.get_mut())
+                .map_err(|_| SqliteBlockError::ConnectionFailed)?;
+        }
 
         Ok(val)
     }
-}
 
-use crate::block::BlockError;
-use crate::record::SignedRecord;
-use records::dsl::records as rq;
+    /// Stores every node of `tree` under its level and position
+    fn store_merkle_tree(
+        con: &mut SqliteConnection,
+        tree: &MerkleTree,
+    ) -> Result<(), SqliteBlockError> {
+        let rows = tree
+            .layers()
+            .iter()
+            .enumerate()
+            .flat_map(|(level, layer)| {
+                layer.iter().enumerate().map(move |(position, hash)| {
+                    (
+                        merkle_nodes::level.eq(level as i32),
+                        merkle_nodes::position.eq(position as i32),
+                        merkle_nodes::hash.eq(hash.to_hex()),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
+
+        con.transaction(|con| {
+            for rows in rows.chunks(1000) {
+                diesel::insert_into(merkle_nodes::table)
+                    .values(rows)
+                    .execute(con)?;
+            }
+            diesel::QueryResult::Ok(())
+        })
+        .map_err(|_| SqliteBlockError::ConnectionFailed)
+    }
+}
 
-#[derive(Deserialize)]
-struct RecordValue<X> {
-    s: SignedRecord<X>,
+/// Whether `err` is caused by a table that blocks stored by older versions do not have
+fn is_missing_table(err: &diesel::result::Error) -> bool {
+    matches!(
+        err,
+        diesel::result::Error::DatabaseError(_, info) if info.message().starts_with("no such table")
+    )
 }
 
-impl<X> RecordValue<X> {
-    fn new(s: SignedRecord<X>) -> Self {
-        Self { s }
+impl<X> SqliteBlock<X> {
+    /// Returns the schema version the records of this block were written with.
+    ///
+    /// Blocks stored before versions were recorded hold records of version 1.
+    pub fn schema_version(&self) -> Result<u32, BlockError> {
+        let version = record_schema::table
+            .select(record_schema::version)
+            .first::<i32>(self.con.get_mut())
+            .optional();
+        match version {
+            Ok(version) => Ok(version.map_or(1, |version| version as u32)),
+            Err(err) if is_missing_table(&err) => Ok(1),
+            Err(_) => Err(BlockError::DataBaseError(DataBaseError::NoSuchTable)),
+        }
     }
-}
 
-impl<X: for<'a> Deserialize<'a>> Queryable<Text, Sqlite> for RecordValue<X> {
-    type Row = String;
-    fn build(row: Self::Row) -> diesel::deserialize::Result<Self> {
-        let value = serde_json::from_str(&row)?;
-        Ok(RecordValue::new(value))
+    /// Returns the number of stored Merkle leaves, which is zero if the tree is not stored
+    fn stored_leaves(&self) -> Result<usize, BlockError> {
+        let count = merkle_nodes::table
+            .filter(merkle_nodes::level.eq(0))
+            .count()
+            .get_result::<i64>(self.con.get_mut());
+        match count {
+            Ok(count) => Ok(count as usize),
+            Err(err) if is_missing_table(&err) => Ok(0),
+            Err(_) => Err(BlockError::DataBaseError(DataBaseError::NoSuchTable)),
+        }
+    }
+
+    /// Returns the stored Merkle node at `level` and `position`
+    fn stored_node(
+        &self,
+        algorithm: HashAlgorithm,
+        level: usize,
+        position: usize,
+    ) -> Result<Hash, BlockError> {
+        let node = merkle_nodes::table
+            .select(merkle_nodes::hash)
+            .filter(merkle_nodes::level.eq(level as i32))
+            .filter(merkle_nodes::position.eq(position as i32))
+            .first::<String>(self.con.get_mut())
+            .optional()
+            .map_err(|_| BlockError::DataBaseError(DataBaseError::NoSuchTable))?
+            .ok_or(BlockError::NotValid(BlockData::MerkleRoot))?;
+        decode_node(algorithm, &node)
     }
+
+    /// Returns the stored layers of the Merkle tree, or `None` if the tree is not stored
+    fn stored_layers(
+        &self,
+        algorithm: HashAlgorithm,
+    ) -> Result<Option<Vec<Vec<Hash>>>, BlockError> {
+        let rows = merkle_nodes::table
+            .select((merkle_nodes::level, merkle_nodes::hash))
+            .order((merkle_nodes::level, merkle_nodes::position))
+            .load::<(i32, String)>(self.con.get_mut());
+        let rows = match rows {
+            Ok(rows) if rows.is_empty() => return Ok(None),
+            Ok(rows) => rows,
+            Err(err) if is_missing_table(&err) => return Ok(None),
+            Err(_) => return Err(BlockError::DataBaseError(DataBaseError::NoSuchTable)),
+        };
+
+        let mut layers = vec![];
+        for (level, node) in rows {
+            let level = level as usize;
+            layers.resize_with(layers.len().max(level + 1), Vec::new);
+            layers[level].push(decode_node(algorithm, &node)?);
+        }
+        Ok(Some(layers))
+    }
+}
+
+fn decode_node(algorithm: HashAlgorithm, node: &str) -> Result<Hash, BlockError> {
+    let bytes =
+        hex::decode(node).map_err(|_| BlockError::SerdeError(SerdeError::DeserializationError))?;
+    Ok(Hash::with_algorithm(bytes.into_boxed_slice(), algorithm))
 }
 
-impl<X> From<RecordValue<X>> for SignedRecord<X> {
-    fn from(value: RecordValue<X>) -> Self {
-        value.s
+impl<X: Record + for<'a> Deserialize<'a> + 'static> SqliteBlock<X> {
+    /// Checks the stored Merkle tree against the Merkle root of the block.
+    ///
+    /// Every stored inner node must be the hash of its children and the stored root must be the
+    /// Merkle root of the block. Only blocks stored without their tree have their records read.
+    pub fn verify_merkle_tree(&self) -> Result<(), BlockError> {
+        let tree = self.merkle_tree()?;
+        match *tree.root() == self.merkle_root()? {
+            true => Ok(()),
+            false => Err(BlockError::NotValid(BlockData::MerkleRoot)),
+        }
     }
 }
 
+use crate::block::BlockError;
+use crate::record::SignedRecord;
+use records::dsl::records as rq;
+
 impl<X: Record + for<'a> Deserialize<'a> + 'static> ChainedInstance<X> for SqliteBlock<X> {
-    fn records(&self) -> Result<Records<X>, BlockError> {
+    fn records(&self) -> Result<Records<'_, X>, BlockError> {
+        let version = self.schema_version()?;
         let res = rq
             .select(records::jsonvalues)
-            .load::<RecordValue<X>>(self.con.get_mut())
-            .unwrap();
+            .order(records::id)
+            .load::<String>(self.con.get_mut())
+            .map_err(|_| BlockError::DataBaseError(DataBaseError::NoSuchTable))?;
+
+        // rows of older versions are upcast, after their signatures are checked
+        let fallback = Upcasters::new();
+        let upcasters = self.upcasters.as_deref().unwrap_or(&fallback);
         let res = res
-            .into_iter()
-            .map(|record_val| record_val.into())
-            .collect::<Vec<SignedRecord<X>>>();
+            .iter()
+            .map(|row| upcasters.read(version, row))
+            .collect::<Result<Vec<SignedRecord<X>>, _>>()?;
         Ok(res.into())
     }
 
@@ -251,4 +470,80 @@ impl<X: Record + for<'a> Deserialize<'a> + 'static> ChainedInstance<X> for Sqlit
         let res = serde_json::from_str::<Timestamp>(&res).unwrap();
         Ok(res)
     }
+
+    fn state_root(&self) -> Result<Option<Hash>, BlockError> {
+        let res = state::table
+            .select(state::root)
+            .first::<String>(self.con.get_mut())
+            .optional();
+        let res = match res {
+            Ok(res) => res,
+            Err(err) if is_missing_table(&err) => None,
+            Err(_) => return Err(BlockError::DataBaseError(DataBaseError::NoSuchTable)),
+        };
+        res.map(|res| serde_json::from_str::<Hash>(&res))
+            .transpose()
+            .map_err(|_| BlockError::SerdeError(SerdeError::DeserializationError))
+    }
+
+    fn merkle_tree(&self) -> Result<MerkleTree, BlockError> {
+        let algorithm = ChainedInstance::merkle_root(self)?.algorithm();
+        match self.stored_layers(algorithm)? {
+            Some(layers) => MerkleTree::from_layers(algorithm, layers)
+                .map_err(|_| BlockError::NotValid(BlockData::MerkleRoot)),
+            None => {
+                let records = ChainedInstance::records(self)?;
+                let leaves = block::leaves(algorithm, &records);
+                Ok(MerkleTree::from_leaves(algorithm, leaves))
+            }
+        }
+    }
+
+    fn merkle_proof(&self, index: usize) -> Result<MerkleProof, BlockError> {
+        let size = self.stored_leaves()?;
+        if size == 0 {
+            return self
+                .merkle_tree()?
+                .proof(index)
+                .ok_or(BlockError::AbsentValue);
+        }
+        if index >= size {
+            return Err(BlockError::AbsentValue);
+        }
+
+        // only the siblings on the path are read
+        let algorithm = ChainedInstance::merkle_root(self)?.algorithm();
+        let mut path = vec![];
+        let (mut level, mut position, mut len) = (0, index, size);
+        while len > 1 {
+            if position ^ 1 < len {
+                path.push(self.stored_node(algorithm, level, position ^ 1)?);
+            }
+            level += 1;
+            position /= 2;
+            len = len.div_ceil(2);
+        }
+        Ok(MerkleProof::new(index, size, path))
+    }
+
+    fn record_proof(&self, record: &Hash) -> Result<MerkleProof, BlockError> {
+        let algorithm = ChainedInstance::merkle_root(self)?.algorithm();
+        let index = match self.stored_leaves()? {
+            0 => ChainedInstance::records(self)?
+                .iter()
+                .position(|item| item.hash() == record),
+            // the records of a block are hashed with its algorithm
+            _ if record.algorithm() != algorithm => None,
+            _ => merkle_nodes::table
+                .select(merkle_nodes::position)
+                .filter(merkle_nodes::level.eq(0))
+                .filter(merkle_nodes::hash.eq(merkle::leaf_hash(algorithm, record).to_hex()))
+                .order(merkle_nodes::position)
+                .first::<i32>(self.con.get_mut())
+                .optional()
+                .map_err(|_| BlockError::DataBaseError(DataBaseError::NoSuchTable))?
+                .map(|position| position as usize),
+        };
+        self.merkle_proof(index.ok_or(BlockError::AbsentValue)?)
+    }
 }
@@ -1,14 +1,16 @@
 use diesel::{insert_into, prelude::*};
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, marker::PhantomData};
+use std::{fmt::Debug, marker::PhantomData, sync::Arc};
 
 use crate::{
     block::{ChainedInstance, LocalInstance, PositionInstance, UnchainedInstance},
     chain::{Chain, ChainError},
     data::{Position, ToTimestamp},
     error::{DataBaseError, SerdeError},
-    record::Record,
-    Hash, SqliteBlock, TempInstance,
+    mmr::{MerkleMountainRange, MmrProof},
+    record::{Record, RecordError},
+    upcast::Upcasters,
+    Hash, HashAlgorithm, SqliteBlock, TempInstance,
 };
 
 use super::WrapperMut;
@@ -20,9 +22,34 @@ table! {
     }
 }
 
+table! {
+    mmr_nodes {
+        id -> Integer,
+        hash -> Text,
+    }
+}
+
+table! {
+    settings {
+        id -> Integer,
+        hash_algorithm -> Text,
+    }
+}
+
+table! {
+    chain_identity {
+        id -> Integer,
+        chain_id -> Text,
+    }
+}
+
 pub struct SqliteChain<X> {
     con: WrapperMut<SqliteConnection>,
     url: String,
+    hash_algorithm: HashAlgorithm,
+    chain_id: Option<String>,
+    mmr: WrapperMut<MerkleMountainRange>,
+    upcasters: Option<Arc<Upcasters<X>>>,
     _data: PhantomData<X>,
 }
 
@@ -31,6 +58,10 @@ pub enum SqliteChainError {
     ConnectionError(ConnectionError),
     SerdeError(SerdeError),
     ConnectionFailed,
+    /// The chain was created with a different hash algorithm than the one requested.
+    HashAlgorithmMismatch(HashAlgorithm),
+    /// The chain already has a different identifier than the one requested.
+    ChainIdMismatch(String),
 }
 
 impl From<ConnectionError> for SqliteChainError {
@@ -46,23 +77,77 @@ impl<X> SqliteChain<X> {
 }
 
 impl<X> SqliteChain<X> {
+    /// Opens the chain stored at `url`, creating it with SHA-256 hashing if it is absent.
+    ///
+    /// An existing chain keeps the hash algorithm it was created with.
     pub fn new(url: &str) -> Result<Self, SqliteChainError> {
+        Self::open(url, None)
+    }
+
+    /// Opens the chain stored at `url`, creating it with `algorithm` if it is absent.
+    ///
+    /// Fails with `HashAlgorithmMismatch` if an existing chain uses another algorithm.
+    pub fn with_hash_algorithm(
+        url: &str,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self, SqliteChainError> {
+        Self::open(url, Some(algorithm))
+    }
+
+    #[allow(clippy::redundant_closure)]
+    fn open(url: &str, algorithm: Option<HashAlgorithm>) -> Result<Self, SqliteChainError> {
         assert!(url.ends_with('/'));
         let basic = format! {"{url}chain.db"};
         let mut con = SqliteConnection::establish(&basic)
             .map_err(|e| SqliteChainError::ConnectionError(e))?;
 
         Self::create_table(&mut con)?;
+        let hash_algorithm = Self::load_hash_algorithm(&mut con, algorithm)?;
+        let chain_id =
+            Self::load_chain_id(&mut con).map_err(|_| SqliteChainError::ConnectionFailed)?;
+        let mmr = Self::load_mmr(&mut con, hash_algorithm).map_err(|err| match err {
+            ChainError::SerdeError(err) => SqliteChainError::SerdeError(err),
+            _ => SqliteChainError::ConnectionFailed,
+        })?;
 
         let value = Self {
             url: url.to_owned(),
             con: WrapperMut::new(con),
+            hash_algorithm,
+            chain_id,
+            mmr: WrapperMut::new(mmr),
+            upcasters: None,
             _data: PhantomData,
         };
 
         Ok(value)
     }
 
+    /// Sets the identifier of the chain, which every record appended to it must be signed for.
+    ///
+    /// The identifier is stored along with the next block that is appended, and the chain keeps it
+    /// from then on: opening it again checks records against it without calling this, and this fails
+    /// with `ChainIdMismatch` if the chain already has another identifier. Blocks appended before the
+    /// chain had an identifier are not checked again.
+    pub fn with_chain_id(mut self, chain_id: &str) -> Result<Self, SqliteChainError> {
+        match &self.chain_id {
+            Some(current) if current != chain_id => {
+                Err(SqliteChainError::ChainIdMismatch(current.clone()))
+            }
+            _ => {
+                self.chain_id = Some(chain_id.to_owned());
+                Ok(self)
+            }
+        }
+    }
+
+    /// Reads the blocks of this chain with `upcasters`, so that records written with an older
+    /// version of the record type are upcast to the current one.
+    pub fn with_upcasters(mut self, upcasters: Upcasters<X>) -> Self {
+        self.upcasters = Some(Arc::new(upcasters));
+        self
+    }
+
     fn create_table(con: &mut SqliteConnection) -> Result<(), SqliteChainError> {
         diesel::sql_query(
             "
@@ -75,6 +160,128 @@ impl<X> SqliteChain<X> {
         .execute(con)
         .map_err(|_| SqliteChainError::ConnectionFailed)?;
 
+        diesel::sql_query(
+            "
+        CREATE TABLE IF NOT EXISTS mmr_nodes (
+            id INTEGER PRIMARY KEY,
+            hash TEXT
+        )
+        ",
+        )
+        .execute(con)
+        .map_err(|_| SqliteChainError::ConnectionFailed)?;
+
+        diesel::sql_query(
+            "
+        CREATE TABLE IF NOT EXISTS settings (
+            id INTEGER PRIMARY KEY,
+            hash_algorithm TEXT
+        )
+        ",
+        )
+        .execute(con)
+        .map_err(|_| SqliteChainError::ConnectionFailed)?;
+
+        diesel::sql_query(
+            "
+        CREATE TABLE IF NOT EXISTS chain_identity (
+            id INTEGER PRIMARY KEY,
+            chain_id TEXT NOT NULL
+        )
+        ",
+        )
+        .execute(con)
+        .map_err(|_| SqliteChainError::ConnectionFailed)?;
+
+        Ok(())
+    }
+
+    /// Reads the hash algorithm of the chain, recording `requested` for a new chain
+    fn load_hash_algorithm(
+        con: &mut SqliteConnection,
+        requested: Option<HashAlgorithm>,
+    ) -> Result<HashAlgorithm, SqliteChainError> {
+        let stored: Option<String> = settings::table
+            .select(settings::hash_algorithm)
+            .first(con)
+            .optional()
+            .map_err(|_| SqliteChainError::ConnectionFailed)?;
+
+        match stored {
+            Some(json) => {
+                let stored = serde_json::from_str(&json)
+                    .map_err(|_| SqliteChainError::SerdeError(SerdeError::DeserializationError))?;
+                match requested {
+                    Some(requested) if requested != stored => {
+                        Err(SqliteChainError::HashAlgorithmMismatch(stored))
+                    }
+                    _ => Ok(stored),
+                }
+            }
+            None => {
+                let algorithm = requested.unwrap_or_default();
+                let json = serde_json::to_string(&algorithm)
+                    .map_err(|_| SqliteChainError::SerdeError(SerdeError::SerializationError))?;
+                insert_into(settings::table)
+                    .values(settings::hash_algorithm.eq(json))
+                    .execute(con)
+                    .map_err(|_| SqliteChainError::ConnectionFailed)?;
+                Ok(algorithm)
+            }
+        }
+    }
+
+    /// Reads the identifier of the chain, if it has one
+    fn load_chain_id(con: &mut SqliteConnection) -> Result<Option<String>, ChainError> {
+        chain_identity::table
+            .select(chain_identity::chain_id)
+            .first(con)
+            .optional()
+            .map_err(|_| ChainError::DataBaseError(DataBaseError::NoSuchTable))
+    }
+
+    /// Reads the nodes of the Merkle Mountain Range over the block hashes
+    fn load_mmr(
+        con: &mut SqliteConnection,
+        algorithm: HashAlgorithm,
+    ) -> Result<MerkleMountainRange, ChainError> {
+        let nodes = mmr_nodes::table
+            .select(mmr_nodes::hash)
+            .order(mmr_nodes::id)
+            .load::<String>(con)
+            .map_err(|_| ChainError::DataBaseError(DataBaseError::NoSuchTable))?
+            .iter()
+            .map(|json| serde_json::from_str(json))
+            .collect::<Result<Vec<Hash>, _>>()
+            .map_err(|_| ChainError::SerdeError(SerdeError::DeserializationError))?;
+        MerkleMountainRange::from_nodes(algorithm, nodes)
+            .map_err(|_| ChainError::SerdeError(SerdeError::DeserializationError))
+    }
+
+    /// Appends `hash` to the Merkle Mountain Range in memory and returns the new nodes, serialized
+    /// for `insert_mmr_nodes`.
+    ///
+    /// Until they are stored, the range has more nodes than the database, so `sync_mmr` reloads it.
+    fn push_mmr(&self, hash: &Hash) -> Result<Vec<String>, ChainError> {
+        let range = self.mmr.get_mut();
+        let stored = range.nodes().len();
+        range.push(hash);
+        range.nodes()[stored..]
+            .iter()
+            .map(|node| {
+                serde_json::to_string(node)
+                    .map_err(|_| ChainError::SerdeError(SerdeError::SerializationError))
+            })
+            .collect()
+    }
+
+    /// Stores the Merkle Mountain Range nodes returned by `push_mmr`
+    fn insert_mmr_nodes(con: &mut SqliteConnection, nodes: &[String]) -> QueryResult<()> {
+        for node in nodes {
+            insert_into(mmr_nodes::table)
+                .values(mmr_nodes::hash.eq(node))
+                .execute(con)?;
+        }
         Ok(())
     }
 
@@ -94,22 +301,45 @@ impl<X: Clone + Record + Serialize + for<'a> Deserialize<'a> + 'static> Chain<X>
 
     type ChainedInstanceType = SqliteBlock<X>;
 
+    #[allow(clippy::redundant_closure, clippy::needless_borrow)]
     fn append(
         &mut self,
         block: &Self::UnchainedInstanceType,
     ) -> Result<PositionInstance, ChainError> {
+        if block.hash_algorithm() != self.hash_algorithm {
+            return Err(ChainError::HashAlgorithmMismatch);
+        }
+
+        // the identifier set by `with_chain_id` is stored with the block
+        let stored = Self::load_chain_id(self.con.get_mut())?;
+        let chain_id = stored.as_ref().or(self.chain_id.as_ref());
+        for record in block.get_records() {
+            if record.hash().algorithm() != self.hash_algorithm {
+                return Err(ChainError::InvalidRecord(RecordError::HashMismatch));
+            }
+            match chain_id {
+                Some(chain_id) => record.check_for(chain_id),
+                None => record.check(),
+            }
+            .map_err(ChainError::InvalidRecord)?;
+        }
+        let new_chain_id = match (&stored, &self.chain_id) {
+            (None, Some(chain_id)) => Some(chain_id.clone()),
+            _ => None,
+        };
+
         let size = Self::size(self.con.get_mut()).map_err(|e| ChainError::DataBaseError(e))?;
 
-        let nonce = block.nonce().unwrap();
+        let nonce = block.nonce()?;
 
         let position = (size + 1).into();
 
         let timestamp = chrono::Utc::now().to_timestamp();
 
-        let merkle_root = block.merkle_root().unwrap().clone();
+        let merkle_root = block.merkle_root()?;
 
         let prev_hash = match self.block_at(size.into()) {
-            Err(ChainError::AbsentValue) => Hash::default(),
+            Err(ChainError::AbsentValue) => self.hash_algorithm.zero(),
             other => {
                 let other = other?;
                 other.hash()?
@@ -118,14 +348,47 @@ impl<X: Clone + Record + Serialize + for<'a> Deserialize<'a> + 'static> Chain<X>
 
         let hash = crate::hash_block(&block, &prev_hash, &timestamp, &position);
 
-        let chained = TempInstance::new(nonce, position, timestamp, hash, prev_hash, merkle_root);
+        let chained = TempInstance::new(
+            nonce,
+            position,
+            timestamp,
+            hash.clone(),
+            prev_hash,
+            merkle_root,
+            block.state_root.clone(),
+        );
 
         let gen_url = Self::gen_url(&self.url, size as _);
 
-        let smt = insert_into(blocks::table).values(blocks::block.eq(&gen_url));
-        smt.execute(self.con.get_mut()).unwrap();
+        self.sync_mmr()?;
 
-        SqliteBlock::build(&gen_url, &*block.records().unwrap(), &chained).unwrap();
+        // the block is only part of the chain once its row is written, so a file left by an
+        // append that failed before is replaced
+        let _ = std::fs::remove_file(&gen_url);
+        if let Err(err) = SqliteBlock::build(&gen_url, block.get_records(), &chained) {
+            let _ = std::fs::remove_file(&gen_url);
+            return Err(err.into());
+        }
+
+        let nodes = self.push_mmr(&hash)?;
+        let written = self.con.get_mut().transaction(|con| {
+            insert_into(blocks::table)
+                .values(blocks::block.eq(&gen_url))
+                .execute(con)?;
+            if let Some(chain_id) = &new_chain_id {
+                insert_into(chain_identity::table)
+                    .values(chain_identity::chain_id.eq(chain_id))
+                    .execute(con)?;
+            }
+            Self::insert_mmr_nodes(con, &nodes)
+        });
+        if written.is_err() {
+            let _ = std::fs::remove_file(&gen_url);
+            return Err(ChainError::DataBaseError(DataBaseError::NoSuchTable));
+        }
+        if new_chain_id.is_some() {
+            self.chain_id = new_chain_id;
+        }
 
         Ok(PositionInstance::new(position))
     }
@@ -144,12 +407,64 @@ impl<X: Clone + Record + Serialize + for<'a> Deserialize<'a> + 'static> Chain<X>
         let block = SqliteBlock::new(&url)
             .map_err(|_| ChainError::DataBaseError(DataBaseError::ConnectionCannotEstablish))?;
 
-        Ok(block)
+        Ok(match &self.upcasters {
+            Some(upcasters) => block.with_upcasters(upcasters.clone()),
+            None => block,
+        })
     }
 
+    #[allow(clippy::redundant_closure)]
     fn len(&self) -> Result<u64, ChainError> {
         Self::size(self.con.get_mut()).map_err(|e| ChainError::DataBaseError(e))
     }
+
+    fn chain_id(&self) -> Result<Option<String>, ChainError> {
+        let stored = Self::load_chain_id(self.con.get_mut())?;
+        Ok(stored.or_else(|| self.chain_id.clone()))
+    }
+
+    fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash_algorithm
+    }
+
+    fn mmr_root(&self) -> Result<Hash, ChainError> {
+        self.sync_mmr()?;
+        Ok(self.mmr.get_mut().root())
+    }
+
+    fn block_proof(&self, pos: Position) -> Result<MmrProof, ChainError> {
+        self.sync_mmr()?;
+        let index = pos.pos.checked_sub(1).ok_or(ChainError::AbsentValue)?;
+        self.mmr
+            .get_mut()
+            .proof(index)
+            .ok_or(ChainError::AbsentValue)
+    }
+}
+
+impl<X: Clone + Record + Serialize + for<'a> Deserialize<'a> + 'static> SqliteChain<X> {
+    /// Brings the Merkle Mountain Range up to date with the blocks of the chain.
+    ///
+    /// Nodes stored through other connections are loaded, and the hashes of blocks appended before
+    /// the range was stored are added to it.
+    fn sync_mmr(&self) -> Result<(), ChainError> {
+        let stored = mmr_nodes::table
+            .count()
+            .get_result::<i64>(self.con.get_mut())
+            .map_err(|_| ChainError::DataBaseError(DataBaseError::NoSuchTable))?;
+        if stored as usize != self.mmr.get_mut().nodes().len() {
+            *self.mmr.get_mut() = Self::load_mmr(self.con.get_mut(), self.hash_algorithm)?;
+        }
+
+        let len = self.len()?;
+        for pos in self.mmr.get_mut().size() + 1..=len {
+            let hash = self.block_at(pos.into())?.hash()?;
+            let nodes = self.push_mmr(&hash)?;
+            Self::insert_mmr_nodes(self.con.get_mut(), &nodes)
+                .map_err(|_| ChainError::DataBaseError(DataBaseError::NoSuchTable))?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -161,7 +476,7 @@ mod tests {
         chain::Chain,
         data::Metadata,
         record::{Record, SignedRecord},
-        SqliteChain,
+        HashAlgorithm, SqliteChain, SqliteChainError,
     };
     use serde::{Deserialize, Serialize};
 
@@ -177,6 +492,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::result_filter_map)]
     fn test_block() {
         let chain_url = "target2/tests/votestoringstring/";
         std::fs::create_dir_all(chain_url).expect("could not create chain_url");
@@ -185,13 +501,13 @@ mod tests {
         let keypair = crate::generate_ed25519_keypair();
         let records1 = datas1
             .into_iter()
-            .map(|w| Vote::new(w).record(keypair.clone(), Metadata::empty()))
+            .map(|w| Vote::new(w).record(keypair.clone(), "test", Metadata::empty()))
             .filter(|r| r.is_ok())
             .map(|v| v.unwrap())
             .collect::<Vec<SignedRecord<Vote>>>();
         let records2 = datas2
             .into_iter()
-            .map(|w| Vote::new(w).record(keypair.clone(), Metadata::empty()))
+            .map(|w| Vote::new(w).record(keypair.clone(), "test", Metadata::empty()))
             .filter(|r| r.is_ok())
             .map(|v| v.unwrap())
             .collect::<Vec<SignedRecord<Vote>>>();
@@ -200,11 +516,11 @@ mod tests {
         let mut builder2 = LocalInstance::new(Metadata::empty(), 1);
 
         for record in records1 {
-            builder1.push(record);
+            builder1.push(record).unwrap();
         }
 
         for record in records2 {
-            builder2.push(record);
+            builder2.push(record).unwrap();
         }
 
         let mut chain =
@@ -231,4 +547,153 @@ mod tests {
             &*records_from_block2
         );
     }
+
+    #[test]
+    fn test_hash_algorithm() {
+        let chain_url = "target2/tests/blake3chain/";
+        let _ = std::fs::remove_dir_all(chain_url);
+        std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+        let keypair = crate::generate_ed25519_keypair();
+
+        let mut chain = SqliteChain::with_hash_algorithm(chain_url, HashAlgorithm::Blake3)
+            .expect("sqlite connection cannot be established");
+        assert_eq!(HashAlgorithm::Blake3, chain.hash_algorithm());
+
+        let mut sha_block = LocalInstance::new(Metadata::empty(), 0);
+        let record = Vote::new("abcd")
+            .record(&keypair, "test", Metadata::empty())
+            .unwrap();
+        sha_block.push(record).unwrap();
+        assert!(chain.append(&sha_block).is_err());
+
+        let mut block =
+            LocalInstance::with_hash_algorithm(Metadata::empty(), 0, HashAlgorithm::Blake3);
+        let record = Vote::new("abcd")
+            .record(&keypair, "test", Metadata::empty())
+            .unwrap();
+        block.push(record).unwrap();
+        let block = chain
+            .append(&block)
+            .expect("block append erred")
+            .block(&chain)
+            .expect("couldn't retrieve block");
+        assert_eq!(HashAlgorithm::Blake3, block.hash().unwrap().algorithm());
+        assert_eq!(
+            HashAlgorithm::Blake3,
+            block.prev_hash().unwrap().algorithm()
+        );
+        assert_eq!(
+            HashAlgorithm::Blake3,
+            block.merkle_root().unwrap().algorithm()
+        );
+
+        // the records are hashed with the algorithm of the chain rather than SHA-256
+        let records = block.records().unwrap();
+        let leaf = records[0].hash();
+        assert_eq!(&records[0].record().hash_with(HashAlgorithm::Blake3), leaf);
+        assert!(records[0].check().is_ok());
+        let root = block.merkle_root().unwrap();
+        assert_eq!(root, crate::merkle::leaf_hash(HashAlgorithm::Blake3, leaf));
+        assert_eq!(root, block.compute_merkle_root().unwrap());
+        let proof = block.record_proof(leaf).unwrap();
+        assert!(crate::merkle::verify_proof(&root, leaf, &proof).is_ok());
+        let sha = records[0].record().hash();
+        assert!(crate::merkle::verify_proof(&root, &sha, &proof).is_err());
+        assert!(block.record_proof(&sha).is_err());
+
+        // the algorithm is kept when the chain is opened again
+        let reopened: SqliteChain<Vote> = SqliteChain::new(chain_url).unwrap();
+        assert_eq!(HashAlgorithm::Blake3, reopened.hash_algorithm());
+        let mismatch = SqliteChain::<Vote>::with_hash_algorithm(chain_url, HashAlgorithm::Sha256);
+        assert!(matches!(
+            mismatch,
+            Err(SqliteChainError::HashAlgorithmMismatch(
+                HashAlgorithm::Blake3
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_mmr() {
+        use diesel::prelude::*;
+
+        let chain_url = "target2/tests/mmrchain/";
+        let _ = std::fs::remove_dir_all(chain_url);
+        std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+        let keypair = crate::generate_ed25519_keypair();
+
+        let mut chain =
+            SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+        let mut hashes = vec![];
+        for data in ["abcd", "efgh", "ijkl", "mnop", "qrst"] {
+            let mut block = LocalInstance::new(Metadata::empty(), 0);
+            let record = Vote::new(data)
+                .record(&keypair, "test", Metadata::empty())
+                .unwrap();
+            block.push(record).unwrap();
+            let block = chain.append(&block).unwrap().block(&chain).unwrap();
+            hashes.push(block.hash().unwrap());
+        }
+
+        let root = chain.mmr_root().unwrap();
+        for (pos, hash) in (1..).zip(&hashes) {
+            let proof = chain.block_proof(pos.into()).unwrap();
+            assert_eq!(pos - 1, proof.index());
+            assert!(crate::mmr::verify_proof(&root, hash, &proof).is_ok());
+        }
+        assert!(chain.block_proof(0.into()).is_err());
+        assert!(chain.block_proof(6.into()).is_err());
+
+        // a chain without stored nodes gets them from the hashes of its blocks
+        diesel::delete(super::mmr_nodes::table)
+            .execute(chain.con.get_mut())
+            .unwrap();
+        let mut reopened: SqliteChain<Vote> = SqliteChain::new(chain_url).unwrap();
+        assert_eq!(root, reopened.mmr_root().unwrap());
+        assert_eq!(8, reopened.mmr.get_mut().nodes().len());
+
+        // nodes appended through another connection are picked up
+        let mut block = LocalInstance::new(Metadata::empty(), 0);
+        let record = Vote::new("uvwx")
+            .record(&keypair, "test", Metadata::empty())
+            .unwrap();
+        block.push(record).unwrap();
+        reopened.append(&block).unwrap();
+        assert_ne!(root, reopened.mmr_root().unwrap());
+        assert_eq!(reopened.mmr_root().unwrap(), chain.mmr_root().unwrap());
+    }
+
+    #[test]
+    fn test_failed_append() {
+        let chain_url = "target2/tests/failedappendchain/";
+        let _ = std::fs::remove_dir_all(chain_url);
+        std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+        let keypair = crate::generate_ed25519_keypair();
+
+        let mut chain =
+            SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+        let mut block = LocalInstance::new(Metadata::empty(), 0);
+        let record = Vote::new("abcd")
+            .record(&keypair, "test", Metadata::empty())
+            .unwrap();
+        block.push(record).unwrap();
+
+        // the block database cannot be created, so nothing is written
+        let block_url = SqliteChain::<Vote>::gen_url(chain_url, 0);
+        std::fs::create_dir_all(&block_url).unwrap();
+        let root = chain.mmr_root().unwrap();
+        assert!(chain.append(&block).is_err());
+        assert_eq!(0, chain.len().unwrap());
+        assert_eq!(root, chain.mmr_root().unwrap());
+
+        // a file left by an append that failed is replaced
+        std::fs::remove_dir(&block_url).unwrap();
+        std::fs::write(&block_url, b"left over").unwrap();
+        let appended = chain.append(&block).unwrap().block(&chain).unwrap();
+        assert_eq!(1, appended.records().unwrap().len());
+        assert_eq!(1, chain.len().unwrap());
+        let proof = chain.block_proof(1.into()).unwrap();
+        let hash = appended.hash().unwrap();
+        assert!(crate::mmr::verify_proof(&chain.mmr_root().unwrap(), &hash, &proof).is_ok());
+    }
 }
@@ -0,0 +1,146 @@
+//! Schema versioning of records.
+//!
+//! Every record type has a schema version, [`Record::VERSION`], and blocks store the version their
+//! records were written with. When a record type changes, its old definition is kept under the same
+//! [`Record::TYPE_ID`] with the old version, and an upcaster converting it into the new type is
+//! registered in an [`Upcasters`] registry. Reading a block written with the old version deserializes
+//! every record as the old type, checks its signature against the bytes that were signed, and then
+//! applies the upcaster.
+//!
+//! An upcast `SignedRecord` keeps the original record in memory, see [`SignedRecord::original`], so
+//! its signature still verifies and its hash, which is the Merkle leaf of the block, does not change.
+//! The original record is not serialized, so an upcast record cannot be appended to a block again:
+//! it has to be signed again in its current version, see [`SignedRecord::check`].
+//!
+//! # Examples
+//!
+//! ```
+//! use blockify::{data::Metadata, record::Record, upcast::Upcasters};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Clone, Serialize, Deserialize, Record)]
+//! #[record(type_id = "Vote")]
+//! struct VoteV1 {
+//!     choice: i32,
+//! }
+//!
+//! #[derive(Clone, Serialize, Deserialize, Record)]
+//! #[record(type_id = "Vote", version = 2)]
+//! struct Vote {
+//!     choice: i32,
+//!     weight: u32,
+//! }
+//!
+//! let upcasters = Upcasters::new().register(|old: VoteV1| Vote {
+//!     choice: old.choice,
+//!     weight: 1,
+//! });
+//!
+//! let keypair = blockify::generate_ed25519_keypair();
+//! let old = VoteV1 { choice: 2 }.record(keypair, "elections", Metadata::empty()).unwrap();
+//! let row = serde_json::to_string(&old).unwrap();
+//!
+//! let vote = upcasters.read(1, &row).unwrap();
+//! assert_eq!(1, vote.weight);
+//! assert!(vote.verify().is_ok());
+//! ```
+
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::{
+    error::SerdeError,
+    record::{Record, SignedRecord},
+    VerificationError,
+};
+
+/// An error that can occur while reading a stored record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpcastError {
+    /// No upcaster is registered for the version of the record.
+    UnknownVersion(u32),
+
+    /// The record cannot be deserialized as the type of its version.
+    SerdeError(SerdeError),
+
+    /// The signature does not match the original record.
+    VerificationError(VerificationError),
+}
+
+crate::impl_display_error!(UpcastError);
+
+type Upcaster<R> = Box<dyn Fn(&str) -> Result<SignedRecord<R>, UpcastError> + Send + Sync>;
+
+/// The upcasters of the record type `R`, keyed by the version they convert from.
+pub struct Upcasters<R> {
+    upcasters: HashMap<u32, Upcaster<R>>,
+}
+
+impl<R: Record + DeserializeOwned> Upcasters<R> {
+    /// Creates a registry without upcasters, which only reads records of version `R::VERSION`.
+    pub fn new() -> Self {
+        Self {
+            upcasters: HashMap::new(),
+        }
+    }
+
+    /// Registers `upcast` to convert records of type `O`, stored with version `O::VERSION`, into `R`.
+    ///
+    /// An upcaster converts straight into the current version, so upcasters for versions further
+    /// back usually chain the conversions of the versions in between.
+    ///
+    /// # Panics
+    ///
+    /// If `O::VERSION` is `R::VERSION`, or an upcaster for `O::VERSION` is already registered.
+    pub fn register<O, F>(mut self, upcast: F) -> Self
+    where
+        O: Record + DeserializeOwned + 'static,
+        F: Fn(O) -> R + Send + Sync + 'static,
+    {
+        assert_ne!(
+            O::VERSION,
+            R::VERSION,
+            "records of the current version are not upcast"
+        );
+        let upcaster: Upcaster<R> = Box::new(move |row| {
+            let record = serde_json::from_str::<SignedRecord<O>>(row)
+                .map_err(|_| UpcastError::SerdeError(SerdeError::DeserializationError))?;
+            record.verify().map_err(UpcastError::VerificationError)?;
+            record.upcast(&upcast).map_err(UpcastError::SerdeError)
+        });
+        let previous = self.upcasters.insert(O::VERSION, upcaster);
+        assert!(
+            previous.is_none(),
+            "an upcaster for version {} is already registered",
+            O::VERSION
+        );
+        self
+    }
+
+    /// Returns `true` if records stored with `version` can be read.
+    pub fn supports(&self, version: u32) -> bool {
+        version == R::VERSION || self.upcasters.contains_key(&version)
+    }
+
+    /// Reads a `SignedRecord` stored as JSON with the schema `version`.
+    ///
+    /// A record of an older version has its signature checked before it is upcast, while a record of
+    /// version `R::VERSION` is deserialized as is.
+    pub fn read(&self, version: u32, row: &str) -> Result<SignedRecord<R>, UpcastError> {
+        if version == R::VERSION {
+            return serde_json::from_str(row)
+                .map_err(|_| UpcastError::SerdeError(SerdeError::DeserializationError));
+        }
+        match self.upcasters.get(&version) {
+            Some(upcaster) => upcaster(row),
+            None => Err(UpcastError::UnknownVersion(version)),
+        }
+    }
+}
+
+impl<R: Record + DeserializeOwned> Default for Upcasters<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -35,8 +35,11 @@ impl MarriageContract {
     pub fn generate_records(amount: usize) -> Vec<SignedRecord<Self>> {
         let mut res = Vec::with_capacity(amount);
         (0..amount).for_each(|_| {
-            match Self::generate().record(blockify::generate_ed25519_keypair(), Default::default())
-            {
+            match Self::generate().record(
+                blockify::generate_ed25519_keypair(),
+                "test",
+                Default::default(),
+            ) {
                 Ok(v) => res.push(v),
                 Err(_) => unreachable!("Error occurs"),
             }
@@ -49,9 +52,9 @@ impl MarriageContract {
 fn start() {
     let contract = MarriageContract::new("John", "Julie");
     let keypair = blockify::generate_ed25519_keypair();
-    let signature = contract.sign(&keypair).unwrap();
+    let signature = contract.sign(&keypair, "test").unwrap();
     let hash = contract.hash();
-    let record = contract.record(keypair, Metadata::empty()).unwrap();
+    let record = contract.record(keypair, "test", Metadata::empty()).unwrap();
 
     assert_eq!(&hash, record.hash());
     assert_eq!(&signature, record.signature());
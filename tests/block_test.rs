@@ -3,6 +3,7 @@
 use blockify::block::LocalInstance;
 
 #[test]
+#[allow(clippy::result_filter_map)]
 fn test_blocks() {
     use blockify::{
         block::{ChainedInstance, UnchainedInstance},
@@ -38,13 +39,13 @@ fn test_blocks() {
         // and collect them into two vectors.
         let records1 = datas1
             .into_iter()
-            .map(|w| Data::new(w).record(keypair.clone(), Metadata::empty()))
+            .map(|w| Data::new(w).record(keypair.clone(), "test", Metadata::empty()))
             .filter(|r| r.is_ok())
             .map(|v| v.unwrap())
             .collect::<Vec<_>>();
         let records2 = datas2
             .into_iter()
-            .map(|w| Data::new(w).record(keypair.clone(), Metadata::empty()))
+            .map(|w| Data::new(w).record(keypair.clone(), "test", Metadata::empty()))
             .filter(|r| r.is_ok())
             .map(|v| v.unwrap())
             .collect::<Vec<_>>();
@@ -55,11 +56,11 @@ fn test_blocks() {
 
         // push the two vec's content into each UnchainedInstance
         for record in records1 {
-            builder1.push(record);
+            builder1.push(record).unwrap();
         }
 
         for record in records2 {
-            builder2.push(record);
+            builder2.push(record).unwrap();
         }
 
         // To build an SqliteChain (BlockChain that stores data in sqlite database),
@@ -100,3 +101,1068 @@ fn test_blocks() {
     }
     start()
 }
+
+#[test]
+fn test_multisig_blocks() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        multisig::{MultiSignedRecord, SignerPolicy},
+        Signer, SqliteChain,
+    };
+
+    let chain_url = "target2/tests/multisig/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keys = (0..3)
+        .map(|_| blockify::generate_ed25519_keypair())
+        .collect::<Vec<_>>();
+    let policy = SignerPolicy::new(2, keys.iter().map(|key| key.public_key()).collect())
+        .expect("invalid policy");
+
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for amount in [100i64, 250] {
+        let mut record = MultiSignedRecord::new(amount, policy.clone(), "test", Metadata::empty());
+        record.sign_with(&keys[0]).unwrap();
+        record.sign_with(&keys[1]).unwrap();
+        builder
+            .append(record.submit(&keys[1]).expect("couldn't submit record"))
+            .unwrap();
+    }
+
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let block = chain
+        .append(&builder)
+        .expect("builder append erred")
+        .block(&chain)
+        .expect("couldn't retrieve block");
+
+    let records = block.records().expect("couldn't retrieve records");
+    assert_eq!(builder.records().unwrap().as_slice(), &*records);
+    for record in records.iter() {
+        assert!(record.verify().is_ok());
+        assert_eq!(2, record.record().signatures().len());
+    }
+}
+
+#[test]
+fn test_encrypted_blocks() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        encrypted::EncryptedRecord,
+        encryption::EncryptionKeyPair,
+        record::Record,
+        SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct MarriageContract {
+        bride_name: String,
+        groom_name: String,
+    }
+
+    let chain_url = "target2/tests/encrypted/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let registrar = EncryptionKeyPair::generate();
+    let couple = EncryptionKeyPair::generate();
+    let keypair = blockify::generate_ed25519_keypair();
+
+    let contracts =
+        [("Julian", "Jolie"), ("Ada", "Charles")].map(|(bride, groom)| MarriageContract {
+            bride_name: bride.into(),
+            groom_name: groom.into(),
+        });
+
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for contract in &contracts {
+        let encrypted =
+            EncryptedRecord::encrypt(contract, &[registrar.public_key(), couple.public_key()])
+                .expect("couldn't encrypt record");
+        builder
+            .append(
+                encrypted
+                    .record(&keypair, "test", Metadata::empty())
+                    .unwrap(),
+            )
+            .unwrap();
+    }
+
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let block = chain
+        .append(&builder)
+        .expect("builder append erred")
+        .block(&chain)
+        .expect("couldn't retrieve block");
+
+    let records = block.records().expect("couldn't retrieve records");
+    assert_eq!(builder.records().unwrap().as_slice(), &*records);
+    for (record, contract) in records.iter().zip(&contracts) {
+        assert!(record.verify().is_ok());
+        assert_eq!(contract, &record.decrypt(&registrar).unwrap());
+        assert_eq!(contract, &record.decrypt(&couple).unwrap());
+        assert!(record.decrypt(&EncryptionKeyPair::generate()).is_err());
+    }
+}
+
+#[test]
+fn test_bls_blocks() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        record::Record,
+        SqliteChain,
+    };
+
+    let chain_url = "target2/tests/bls/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypairs = (0..4)
+        .map(|_| blockify::generate_bls_keypair())
+        .collect::<Vec<_>>();
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for (i, keypair) in keypairs.iter().enumerate() {
+        let record = (i as i64)
+            .record(keypair, "test", Metadata::empty())
+            .unwrap();
+        builder.append(record).unwrap();
+    }
+    let aggregate = builder.records().unwrap().aggregate_signatures().unwrap();
+
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let block = chain
+        .append(&builder)
+        .expect("builder append erred")
+        .block(&chain)
+        .expect("couldn't retrieve block");
+
+    let records = block.records().expect("couldn't retrieve records");
+    assert!(records.verify_aggregate(&aggregate).is_ok());
+    assert!(records.verify_batch().is_ok());
+    for record in records.iter() {
+        assert!(record.verify().is_ok());
+        assert!(record.verify_for("other").is_err());
+    }
+    assert!(blockify::record::verify_aggregate(&records[1..], &aggregate).is_err());
+}
+
+#[test]
+fn test_address_records() {
+    use blockify::{
+        address::Address,
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        record::Record,
+        Signer, SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct Transfer {
+        to: Address,
+        amount: i64,
+    }
+
+    let chain_url = "target2/tests/address/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let alice = blockify::generate_ed25519_keypair();
+    let bob = blockify::generate_ed25519_keypair();
+    let alice_address = alice.public_key().address("test").unwrap();
+    let bob_address = bob.public_key().address("test").unwrap();
+
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for (signer, to, amount) in [(&alice, &bob_address, 10), (&bob, &alice_address, 4)] {
+        let transfer = Transfer {
+            to: to.clone(),
+            amount,
+        };
+        builder
+            .append(transfer.record(signer, "test", Metadata::empty()).unwrap())
+            .unwrap();
+    }
+
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let block = chain
+        .append(&builder)
+        .expect("builder append erred")
+        .block(&chain)
+        .expect("couldn't retrieve block");
+    let records = block.records().expect("couldn't retrieve records");
+
+    let parsed = Address::parse_for(&alice_address.to_string(), "test").unwrap();
+    let sent = records.signed_by(&parsed).collect::<Vec<_>>();
+    assert_eq!(1, sent.len());
+    assert_eq!(bob_address, sent[0].to);
+    assert_eq!(alice_address, sent[0].signer_address("test").unwrap());
+
+    let received = records
+        .iter()
+        .filter(|record| record.to == parsed)
+        .collect::<Vec<_>>();
+    assert_eq!(1, received.len());
+    assert!(received[0].is_signed_by(&bob_address));
+}
+
+#[test]
+fn test_key_rotation() {
+    use blockify::{
+        block::UnchainedInstance,
+        chain::Chain,
+        data::Metadata,
+        keys::{KeyEvent, KeyHistory, KeyHistoryError, KeyStatus},
+        record::Record,
+        Signer, SqliteChain,
+    };
+
+    let chain_url = "target2/tests/keys/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let [old, new, recovery, thief] = [(); 4].map(|_| blockify::generate_ed25519_keypair());
+    let events = [
+        // block 1
+        vec![KeyEvent::SetRecovery {
+            key: old.public_key(),
+            recovery: recovery.public_key(),
+        }
+        .record(&old, "test", Metadata::empty())
+        .unwrap()],
+        // block 2, with a revocation by an unrelated key that is skipped
+        vec![
+            KeyEvent::Revoke {
+                key: old.public_key(),
+            }
+            .record(&thief, "test", Metadata::empty())
+            .unwrap(),
+            KeyEvent::Rotate {
+                old: old.public_key(),
+                new: new.public_key(),
+            }
+            .record(&recovery, "test", Metadata::empty())
+            .unwrap(),
+        ],
+    ];
+
+    let mut chain = SqliteChain::new(chain_url)
+        .and_then(|chain| chain.with_chain_id("test"))
+        .expect("sqlite connection cannot be established");
+    for (nonce, records) in events.into_iter().enumerate() {
+        let mut builder = LocalInstance::new(Metadata::empty(), nonce as u64);
+        for record in records {
+            builder.append(record).unwrap();
+        }
+        chain.append(&builder).expect("builder append erred");
+    }
+
+    let history = KeyHistory::from_chain(&chain).expect("couldn't read key history");
+    assert_eq!(
+        KeyStatus::Rotated {
+            height: 2,
+            new: new.public_key()
+        },
+        history.status_at(&old.public_key(), 2)
+    );
+    assert_eq!(Some(&new.public_key()), history.current(&old.public_key()));
+    assert_eq!(2, history.history(&old.public_key()).count());
+
+    // the revocation by the unrelated key is reported rather than dropped
+    let rejected = history.rejected();
+    assert_eq!(1, rejected.len());
+    assert_eq!(2, rejected[0].0);
+    assert_eq!(&thief.public_key(), rejected[0].1.signer());
+    assert!(matches!(rejected[0].2, KeyHistoryError::Unauthorized));
+
+    let early = String::from("early")
+        .record(&old, "test", Metadata::empty())
+        .unwrap();
+    let late = String::from("late")
+        .record(&old, "test", Metadata::empty())
+        .unwrap();
+    assert!(history.verify_at(&early, 1).is_ok());
+    assert!(history.verify_at(&late, 2).is_err());
+}
+
+#[test]
+fn test_key_events_with_records() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        keys::{KeyEvent, KeyEventRecord, KeyHistory, KeyHistoryError},
+        record::Record,
+        Signer, SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq)]
+    enum Ledger {
+        Payment(i64),
+        Key(KeyEvent),
+    }
+
+    impl KeyEventRecord for Ledger {
+        fn key_event(&self) -> Option<&KeyEvent> {
+            match self {
+                Ledger::Key(event) => Some(event),
+                Ledger::Payment(_) => None,
+            }
+        }
+    }
+
+    let chain_url = "target2/tests/ledger_keys/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let [old, new] = [(); 2].map(|_| blockify::generate_ed25519_keypair());
+    let rotation = Ledger::Key(KeyEvent::Rotate {
+        old: old.public_key(),
+        new: new.public_key(),
+    });
+    let blocks = [
+        // block 1
+        vec![Ledger::Payment(10).record(&old, "ledger", Metadata::empty())],
+        // block 2, where the payment after the rotation is signed with the retired key
+        vec![
+            rotation.record(&old, "ledger", Metadata::empty()),
+            Ledger::Payment(20).record(&old, "ledger", Metadata::empty()),
+        ],
+        // block 3
+        vec![Ledger::Payment(30).record(&new, "ledger", Metadata::empty())],
+    ];
+
+    let mut chain = SqliteChain::new(chain_url)
+        .and_then(|chain| chain.with_chain_id("ledger"))
+        .expect("sqlite connection cannot be established");
+    for (nonce, records) in blocks.into_iter().enumerate() {
+        let mut builder = LocalInstance::new(Metadata::empty(), nonce as u64);
+        for record in records {
+            builder.append(record.unwrap()).unwrap();
+        }
+        chain.append(&builder).expect("builder append erred");
+    }
+
+    // the rotation is found among the payments of the same chain
+    let history = KeyHistory::from_chain(&chain).expect("couldn't read key history");
+    assert_eq!("ledger", history.chain_id());
+    assert_eq!(Some(&new.public_key()), history.current(&old.public_key()));
+    assert_eq!(1, history.history(&old.public_key()).count());
+    assert!(history.rejected().is_empty());
+
+    let mut valid = vec![];
+    for height in 1..=chain.len().unwrap() {
+        let block = chain.block_at(height.into()).unwrap();
+        for record in block.records().unwrap().iter() {
+            if let Ledger::Payment(amount) = record.record() {
+                valid.push((*amount, history.verify_at(record, height).is_ok()));
+            }
+        }
+    }
+    assert_eq!(vec![(10, true), (20, false), (30, true)], valid);
+
+    // records without an event are not applied
+    let mut history = KeyHistory::new("ledger");
+    let payment = Ledger::Payment(40)
+        .record(&new, "ledger", Metadata::empty())
+        .unwrap();
+    assert!(matches!(
+        history.apply(1, &payment),
+        Err(KeyHistoryError::NotKeyEvent)
+    ));
+}
+
+#[test]
+fn test_merkle_roots() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        record::Record,
+        SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct Vote {
+        data: Vec<u8>,
+    }
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let records = (0..5u8)
+        .map(|i| {
+            Vote { data: vec![i; 4] }
+                .record(&keypair, "test", Metadata::empty())
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let mut roots = vec![];
+    for node in ["a", "b"] {
+        let chain_url = format!("target2/tests/merkle/{node}/");
+        let _ = std::fs::remove_dir_all(&chain_url);
+        std::fs::create_dir_all(&chain_url).expect("could not create chain_url");
+
+        let mut builder = LocalInstance::new(Metadata::empty(), 0);
+        for record in &records {
+            builder.append(record.clone()).unwrap();
+        }
+
+        let mut chain =
+            SqliteChain::new(&chain_url).expect("sqlite connection cannot be established");
+        let block = chain
+            .append(&builder)
+            .expect("builder append erred")
+            .block(&chain)
+            .expect("couldn't retrieve block");
+
+        let root = block.merkle_root().unwrap();
+        assert_eq!(builder.get_merkle_root(), &root);
+        assert_eq!(root, block.compute_merkle_root().unwrap());
+        roots.push(root);
+    }
+    assert_eq!(roots[0], roots[1]);
+}
+
+#[test]
+fn test_merkle_proofs() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        merkle::{self, MerkleProof},
+        record::Record,
+        SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct Order {
+        item: String,
+        quantity: u32,
+    }
+
+    let chain_url = "target2/tests/merkle_proofs/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    for items in [&["bread", "milk"][..], &["eggs", "flour", "sugar"]] {
+        let mut builder = LocalInstance::new(Metadata::empty(), 0);
+        for (quantity, item) in items.iter().enumerate() {
+            let order = Order {
+                item: item.to_string(),
+                quantity: quantity as u32,
+            };
+            let record = order.record(&keypair, "test", Metadata::empty()).unwrap();
+            builder.append(record).unwrap();
+        }
+        chain.append(&builder).expect("builder append erred");
+    }
+
+    let block = chain.block_at(2.into()).expect("couldn't retrieve block");
+    let records = block.records().expect("couldn't retrieve records");
+    let record = &records[1];
+
+    // the receipt only holds the proof, the record hash and the root of the block
+    let proof = block.record_proof(record.hash()).unwrap();
+    assert_eq!(proof, block.merkle_proof(1).unwrap());
+    let receipt = serde_json::to_string(&proof).unwrap();
+    let proof = serde_json::from_str::<MerkleProof>(&receipt).unwrap();
+
+    let root = block.merkle_root().unwrap();
+    assert!(merkle::verify_proof(&root, record.hash(), &proof).is_ok());
+    assert!(merkle::verify_proof(&root, records[0].hash(), &proof).is_err());
+
+    let other = chain.block_at(1.into()).unwrap().merkle_root().unwrap();
+    assert!(merkle::verify_proof(&other, record.hash(), &proof).is_err());
+    assert!(block.merkle_proof(3).is_err());
+
+    // several records are proven at once
+    let proof = block.merkle_multi_proof(&[2, 0]).unwrap();
+    let leaves = [records[0].hash().clone(), records[2].hash().clone()];
+    assert!(merkle::verify_multi_proof(&root, &leaves, &proof).is_ok());
+    assert!(merkle::verify_multi_proof(&root, &leaves[..1], &proof).is_err());
+    assert!(block.merkle_multi_proof(&[1, 3]).is_err());
+}
+
+#[test]
+fn test_mempool_updates() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        record::Record,
+        SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct Payment {
+        amount: u64,
+    }
+
+    let chain_url = "target2/tests/mempool/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let payments = (0..7)
+        .map(|amount| {
+            Payment { amount }
+                .record(&keypair, "test", Metadata::empty())
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for payment in &payments[..6] {
+        builder.append(payment.clone()).unwrap();
+    }
+
+    // drop the second payment in order, swap the last into the place of the third, replace the
+    // fourth and leave out the last
+    let removed = builder.remove(1).unwrap();
+    assert_eq!(payments[1], removed);
+    let removed = builder.swap_remove(1).unwrap();
+    assert_eq!(payments[2], removed);
+    let replaced = builder.replace(2, payments[6].clone()).unwrap();
+    assert_eq!(payments[3], replaced);
+    builder.truncate(3).unwrap();
+    assert!(builder.remove(3).is_err());
+    assert!(builder.swap_remove(3).is_err());
+
+    let kept = [&payments[0], &payments[5], &payments[6]];
+    assert_eq!(kept.map(Clone::clone).to_vec(), builder.records);
+
+    let mut expected = LocalInstance::new(Metadata::empty(), 0);
+    for payment in kept {
+        expected.append(payment.clone()).unwrap();
+    }
+    assert_eq!(expected.get_merkle_root(), builder.get_merkle_root());
+
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let block = chain
+        .append(&builder)
+        .expect("builder append erred")
+        .block(&chain)
+        .expect("couldn't retrieve block");
+    assert_eq!(builder.get_merkle_root(), &block.merkle_root().unwrap());
+    assert_eq!(
+        block.merkle_root().unwrap(),
+        block.compute_merkle_root().unwrap()
+    );
+}
+
+#[test]
+fn test_block_proofs() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        mmr::{self, MmrProof},
+        record::Record,
+        SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct Reading {
+        value: i64,
+    }
+
+    let chain_url = "target2/tests/block_proofs/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    for value in 0..7 {
+        let mut builder = LocalInstance::new(Metadata::empty(), 0);
+        let record = Reading { value }
+            .record(&keypair, "test", Metadata::empty())
+            .unwrap();
+        builder.append(record).unwrap();
+        chain.append(&builder).expect("builder append erred");
+    }
+
+    // a light client trusting the root only needs the block hash and a small proof
+    let root = chain.mmr_root().unwrap();
+    let proof = chain.block_proof(3.into()).unwrap();
+    assert_eq!(7, proof.size());
+    let proof = serde_json::from_str::<MmrProof>(&serde_json::to_string(&proof).unwrap()).unwrap();
+
+    let block = chain.block_at(3.into()).unwrap();
+    assert_eq!(2, proof.index());
+    assert!(mmr::verify_proof(&root, &block.hash().unwrap(), &proof).is_ok());
+
+    let other = chain.block_at(4.into()).unwrap();
+    assert!(mmr::verify_proof(&root, &other.hash().unwrap(), &proof).is_err());
+}
+
+#[test]
+fn test_state_roots() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        merkle::sparse::{self, SparseMerkleTree},
+        record::Record,
+        HashAlgorithm, SqliteChain, SqliteNodeStore,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct Registration {
+        name: String,
+        owner: String,
+    }
+
+    let chain_url = "target2/tests/state_roots/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let store = SqliteNodeStore::new(&format!("{chain_url}state.db")).unwrap();
+    let mut state = SparseMerkleTree::new(store, HashAlgorithm::Sha256);
+
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for (name, owner) in [("example.org", "alice"), ("example.com", "bob")] {
+        let registration = Registration {
+            name: name.into(),
+            owner: owner.into(),
+        };
+        state
+            .insert(&blockify::hash(&name), owner.as_bytes())
+            .unwrap();
+        let record = registration
+            .record(&keypair, "test", Metadata::empty())
+            .unwrap();
+        builder.append(record).unwrap();
+    }
+    builder.set_state_root(state.root().clone());
+
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let block = chain
+        .append(&builder)
+        .expect("builder append erred")
+        .block(&chain)
+        .expect("couldn't retrieve block");
+    let root = block
+        .state_root()
+        .unwrap()
+        .expect("block has no state root");
+    assert_eq!(state.root(), &root);
+
+    // the block hash commits to the state root
+    let (prev_hash, timestamp, position) = (
+        block.prev_hash().unwrap(),
+        block.timestamp().unwrap(),
+        block.position().unwrap(),
+    );
+    let hash = blockify::hash_block(&builder, &prev_hash, &timestamp, &position);
+    assert_eq!(block.hash().unwrap(), hash);
+    builder.state_root = None;
+    let hash = blockify::hash_block(&builder, &prev_hash, &timestamp, &position);
+    assert_ne!(block.hash().unwrap(), hash);
+
+    // the state can be reopened from its store and proven against the block
+    let store = SqliteNodeStore::new(&format!("{chain_url}state.db")).unwrap();
+    let state = SparseMerkleTree::open(store, root.clone());
+    let name = blockify::hash(&"example.org");
+    let proof = state.prove(&name).unwrap();
+    assert!(sparse::verify_membership(&root, &name, b"alice", &proof).is_ok());
+    let name = blockify::hash(&"example.net");
+    let proof = state.prove(&name).unwrap();
+    assert!(sparse::verify_non_membership(&root, &name, &proof).is_ok());
+}
+
+#[test]
+fn test_stored_merkle_tree() {
+    use blockify::{
+        block::{ChainedInstance, UnchainedInstance},
+        chain::Chain,
+        data::Metadata,
+        merkle::{self, MerkleTree},
+        record::Record,
+        SqliteChain,
+    };
+    use diesel::{Connection, RunQueryDsl, SqliteConnection};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    struct Reading {
+        sensor: u32,
+        value: i64,
+    }
+
+    let chain_url = "target2/tests/stored_merkle_tree/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for sensor in 0..37 {
+        let reading = Reading { sensor, value: -7 };
+        let record = reading.record(&keypair, "test", Metadata::empty()).unwrap();
+        builder.append(record).unwrap();
+    }
+    let block = chain.append(&builder).unwrap().block(&chain).unwrap();
+
+    // the stored tree is the one built from the records
+    let records = block.records().unwrap();
+    let leaves = records.iter().map(|record| record.hash());
+    let tree = MerkleTree::from_leaves(block.merkle_root().unwrap().algorithm(), leaves);
+    assert_eq!(tree, block.merkle_tree().unwrap());
+    assert!(block.verify_merkle_tree().is_ok());
+
+    let root = block.merkle_root().unwrap();
+    for (index, record) in records.iter().enumerate() {
+        let proof = block.merkle_proof(index).unwrap();
+        assert_eq!(Some(&proof), tree.proof(index).as_ref());
+        assert_eq!(proof, block.record_proof(record.hash()).unwrap());
+        assert!(merkle::verify_proof(&root, record.hash(), &proof).is_ok());
+    }
+    assert!(block.merkle_proof(37).is_err());
+    assert!(block.record_proof(&blockify::hash(&"absent")).is_err());
+
+    // a tampered node is caught without reading the records
+    let mut con = SqliteConnection::establish(&format!("{chain_url}block1.db")).unwrap();
+    diesel::sql_query("UPDATE merkle_nodes SET hash = '00' WHERE level = 1 AND position = 3")
+        .execute(&mut con)
+        .unwrap();
+    assert!(block.verify_merkle_tree().is_err());
+    let proof = block.merkle_proof(4).unwrap();
+    assert!(merkle::verify_proof(&root, records[4].hash(), &proof).is_err());
+}
+
+#[test]
+fn test_record_upcasting() {
+    use blockify::{
+        block::{BlockData, BlockError, ChainedInstance, UnchainedInstance},
+        chain::{Chain, ChainError},
+        data::Metadata,
+        record::{Record, RecordError, SignedRecord},
+        upcast::Upcasters,
+        SqliteChain,
+    };
+    use diesel::{Connection, RunQueryDsl, SqliteConnection};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    #[record(type_id = "Shipment")]
+    struct ShipmentV1 {
+        parcel: String,
+        grams: u32,
+    }
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq)]
+    #[record(type_id = "Shipment", version = 2)]
+    struct Shipment {
+        parcel: String,
+        kilograms: f64,
+        insured: bool,
+    }
+
+    let chain_url = "target2/tests/record_upcasting/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    for (parcel, grams) in [("a", 1500), ("b", 250)] {
+        let shipment = ShipmentV1 {
+            parcel: parcel.to_owned(),
+            grams,
+        };
+        let record = shipment
+            .record(&keypair, "post", Metadata::empty())
+            .unwrap();
+        builder.append(record).unwrap();
+    }
+    chain.append(&builder).unwrap();
+    let old = chain
+        .block_at(1.into())
+        .unwrap()
+        .records()
+        .unwrap()
+        .to_vec();
+    drop(chain);
+
+    // the struct changed, so the old rows only read with an upcaster
+    let chain = SqliteChain::<Shipment>::new(chain_url).unwrap();
+    let block = chain.block_at(1.into()).unwrap();
+    assert_eq!(1, block.schema_version().unwrap());
+    assert!(matches!(block.records(), Err(BlockError::SerdeError(_))));
+
+    let upcasters = Upcasters::new().register(|old: ShipmentV1| Shipment {
+        parcel: old.parcel,
+        kilograms: old.grams as f64 / 1000.0,
+        insured: false,
+    });
+    let mut chain = SqliteChain::new(chain_url)
+        .unwrap()
+        .with_upcasters(upcasters);
+    let block = chain.block_at(1.into()).unwrap();
+    let records = block.records().unwrap();
+    assert_eq!(1.5, records[0].kilograms);
+    assert_eq!(0.25, records[1].kilograms);
+    for (record, old) in records.iter().zip(&old) {
+        assert_eq!(old.hash(), record.hash());
+        assert_eq!(1, record.original().unwrap().version());
+        assert!(record.verify().is_ok());
+    }
+    assert!(blockify::record::verify_batch(&records).is_ok());
+    assert_eq!(
+        block.merkle_root().unwrap(),
+        block.compute_merkle_root().unwrap()
+    );
+
+    // the original record is not serialized, and cannot be smuggled into a record from outside
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    assert!(matches!(
+        builder.append(records[0].clone()),
+        Err(BlockError::InvalidRecord(RecordError::Upcast))
+    ));
+    let mut forged = serde_json::to_value(&records[0]).unwrap();
+    assert!(forged.get("original").is_none());
+    forged["record"]["insured"] = true.into();
+    forged["original"] = serde_json::json!({
+        "version": 1,
+        "type_id": "Shipment",
+        "signing_bytes": old[0].record().signing_bytes().unwrap(),
+    });
+    let forged: SignedRecord<Shipment> = serde_json::from_value(forged).unwrap();
+    assert!(forged.original().is_none());
+    assert!(forged.verify().is_err());
+
+    // new blocks are written with the current version
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    let shipment = Shipment {
+        parcel: String::from("c"),
+        kilograms: 3.0,
+        insured: true,
+    };
+    let record = shipment
+        .record(&keypair, "post", Metadata::empty())
+        .unwrap();
+    builder.append(record).unwrap();
+    let block = chain.append(&builder).unwrap().block(&chain).unwrap();
+    assert_eq!(2, block.schema_version().unwrap());
+    assert!(block.records().unwrap()[0].original().is_none());
+
+    // the signature is checked against the original row, and blocks without a stored version
+    // hold records of version 1
+    let mut con = SqliteConnection::establish(&format!("{chain_url}block1.db")).unwrap();
+    diesel::sql_query("DROP TABLE record_schema")
+        .execute(&mut con)
+        .unwrap();
+    let block = chain.block_at(1.into()).unwrap();
+    assert_eq!(1, block.schema_version().unwrap());
+    assert!(block.records().is_ok());
+    diesel::sql_query("UPDATE records SET jsonvalues = replace(jsonvalues, '1500', '1600')")
+        .execute(&mut con)
+        .unwrap();
+    assert!(matches!(
+        block.records(),
+        Err(BlockError::NotValid(BlockData::Records))
+    ));
+    assert!(matches!(
+        block.records().map_err(ChainError::from),
+        Err(ChainError::NotValid(BlockData::Records))
+    ));
+}
+
+#[test]
+fn test_record_validation() {
+    use blockify::{
+        block::{BlockError, UnchainedInstance},
+        chain::{Chain, ChainError},
+        data::Metadata,
+        record::{Record, RecordError, SignedRecord, ValidationError},
+        SqliteChain,
+    };
+    use serde::{Deserialize, Serialize};
+
+    fn check_choice(vote: &Vote) -> Result<(), ValidationError> {
+        match vote.choice {
+            0..=2 => Ok(()),
+            _ => Err(ValidationError::new("choice out of range")),
+        }
+    }
+
+    #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
+    #[record(validate = check_choice)]
+    struct Vote {
+        session: u32,
+        choice: u32,
+    }
+
+    let chain_url = "target2/tests/record_validation/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let vote = |choice| {
+        Vote { session: 1, choice }
+            .record(&keypair, "elections", Metadata::empty())
+            .unwrap()
+    };
+    let valid = vote(2);
+    let out_of_range = vote(3);
+    assert!(out_of_range.verify().is_ok());
+    let forged = SignedRecord::new(
+        Vote {
+            session: 1,
+            choice: 0,
+        },
+        valid.signature().clone(),
+        valid.signer().clone(),
+        valid.hash().clone(),
+        "elections",
+        Metadata::empty(),
+    );
+    let rehashed = SignedRecord::new(
+        valid.record().clone(),
+        valid.signature().clone(),
+        valid.signer().clone(),
+        blockify::hash(&"other"),
+        "elections",
+        Metadata::empty(),
+    );
+
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    assert!(builder.append(valid.clone()).is_ok());
+    let rule = ValidationError::new("choice out of range");
+    assert!(matches!(
+        builder.append(out_of_range.clone()),
+        Err(BlockError::InvalidRecord(RecordError::ValidationError(err))) if err == rule
+    ));
+    assert!(matches!(
+        builder.append(forged.clone()),
+        Err(BlockError::InvalidRecord(RecordError::VerificationError(_)))
+    ));
+    assert!(matches!(
+        builder.push(forged.clone()),
+        Err(BlockError::InvalidRecord(RecordError::VerificationError(_)))
+    ));
+    assert!(matches!(
+        builder.append(rehashed),
+        Err(BlockError::InvalidRecord(RecordError::HashMismatch))
+    ));
+    assert!(builder.replace(0, out_of_range.clone()).is_err());
+    assert_eq!(&valid, &builder.get_records()[0]);
+    assert_eq!(1, builder.get_records().len());
+
+    // records added to the fields of the block without checks are still rejected by the chain
+    let mut chain = SqliteChain::new(chain_url).expect("sqlite connection cannot be established");
+    builder.merkle.push(forged.hash());
+    builder.records.push(forged);
+    assert!(matches!(
+        chain.append(&builder),
+        Err(ChainError::InvalidRecord(RecordError::VerificationError(_)))
+    ));
+    assert_eq!(0, chain.len().unwrap());
+
+    builder.truncate(1).unwrap();
+    assert!(chain.append(&builder).is_ok());
+    assert_eq!(1, chain.len().unwrap());
+}
+
+#[test]
+fn test_cross_chain_replay() {
+    use blockify::{
+        block::{BlockError, UnchainedInstance},
+        chain::{Chain, ChainError},
+        data::Metadata,
+        record::{Record, RecordError},
+        SqliteChain, SqliteChainError,
+    };
+
+    let chain_url = "target2/tests/cross_chain/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let payment = |chain_id| {
+        String::from("pay 100 to bob")
+            .record(&keypair, chain_id, Metadata::empty())
+            .unwrap()
+    };
+
+    // an instance bound to a chain rejects records signed for another one
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    builder.set_chain_id("mainnet");
+    assert!(matches!(
+        builder.append(payment("testnet")),
+        Err(BlockError::InvalidRecord(RecordError::VerificationError(_)))
+    ));
+    builder.append(payment("mainnet")).unwrap();
+
+    let mut chain = SqliteChain::new(chain_url)
+        .and_then(|chain| chain.with_chain_id("mainnet"))
+        .expect("sqlite connection cannot be established");
+    assert_eq!(Some(String::from("mainnet")), chain.chain_id().unwrap());
+    chain.append(&builder).unwrap();
+
+    // so does the chain, even for records that were added to the block without checks
+    let mut replayed = LocalInstance::new(Metadata::empty(), 1);
+    let record = payment("testnet");
+    replayed.merkle.push(record.hash());
+    replayed.records.push(record);
+    assert!(matches!(
+        chain.append(&replayed),
+        Err(ChainError::InvalidRecord(RecordError::VerificationError(_)))
+    ));
+    assert_eq!(1, chain.len().unwrap());
+
+    let reopened = SqliteChain::<String>::new(chain_url).unwrap();
+    assert!(matches!(
+        reopened.with_chain_id("testnet"),
+        Err(SqliteChainError::ChainIdMismatch(id)) if id == "mainnet"
+    ));
+
+    // the identifier is stored with the first block, so an empty chain keeps none
+    let chain_url = "target2/tests/cross_chain_explicit/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+    let chain = SqliteChain::<String>::new(chain_url)
+        .and_then(|chain| chain.with_chain_id("mainnet"))
+        .unwrap();
+    assert_eq!(Some(String::from("mainnet")), chain.chain_id().unwrap());
+    drop(chain);
+    let mut chain = SqliteChain::new(chain_url)
+        .and_then(|chain| chain.with_chain_id("testnet"))
+        .unwrap();
+
+    let mut builder = LocalInstance::new(Metadata::empty(), 0);
+    builder.append(payment("testnet")).unwrap();
+    chain.append(&builder).unwrap();
+    let reopened = SqliteChain::<String>::new(chain_url).unwrap();
+    assert_eq!(Some(String::from("testnet")), reopened.chain_id().unwrap());
+
+    // a chain without an identifier does not take one from the records appended to it
+    let chain_url = "target2/tests/cross_chain_unbound/";
+    let _ = std::fs::remove_dir_all(chain_url);
+    std::fs::create_dir_all(chain_url).expect("could not create chain_url");
+    let mut chain = SqliteChain::new(chain_url).unwrap();
+    for (nonce, chain_id) in ["testnet", "mainnet"].into_iter().enumerate() {
+        let mut builder = LocalInstance::new(Metadata::empty(), nonce as u64);
+        builder.append(payment(chain_id)).unwrap();
+        chain.append(&builder).unwrap();
+    }
+    assert_eq!(None, chain.chain_id().unwrap());
+    assert_eq!(2, chain.len().unwrap());
+}
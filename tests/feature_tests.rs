@@ -3,7 +3,7 @@
 #[test]
 fn test() {
     use blockify::{data::Metadata, record::Record};
-    
+
     use serde::{Deserialize, Serialize};
 
     #[derive(Clone, Serialize, Deserialize, Record)]
@@ -28,13 +28,15 @@ fn test() {
     let my_record_hash = blockify::hash(&my_record);
 
     // sign my_record with the AuthKeyPair instance and obtain a digital signature
-    let signature = my_record.sign(&keypair).unwrap();
+    let signature = my_record.sign(&keypair, "test").unwrap();
 
     // verify the authencity of the digital signature
-    assert!(my_record.verify(&signature, &pub_key).is_ok());
+    assert!(my_record.verify(&signature, &pub_key, "test").is_ok());
 
     // record the my_vote (convert it into a SignedRecord instance)
-    let signed_record = my_record.record(keypair, Metadata::empty()).unwrap();
+    let signed_record = my_record
+        .record(keypair, "test", Metadata::empty())
+        .unwrap();
 
     // Compare the signature of `my_record` with that inside the `SignedRecord` instance
     assert_eq!(&signature, signed_record.signature());
@@ -1,9 +1,10 @@
 #![cfg(test)]
 
-use blockify::{record::Record, data::Metadata};
+use blockify::{data::Metadata, record::Record, HashAlgorithm};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Record)]
+#[record(type_id = "Detail")]
 struct Detail<T: Serialize + for<'d> Deserialize<'d>> {
     val: T,
 }
@@ -15,8 +16,8 @@ fn test_derive() {
         val: String::from("Hello, World!"),
     };
     let hash = value.hash();
-    let signature = value.sign(&keypair).unwrap();
-    let record = value.record(keypair, Metadata::empty()).unwrap();
+    let signature = value.sign(&keypair, "test").unwrap();
+    let record = value.record(keypair, "test", Metadata::empty()).unwrap();
 
     assert_eq!(&hash, record.hash());
     assert_eq!(&signature, record.signature());
@@ -31,11 +32,159 @@ fn test_record() {
         val: String::from("Hello, World!"),
     };
     let hash = value.hash();
-    let signature = value.sign(&keypair).unwrap();
-    let record = value.record(keypair, Metadata::empty()).unwrap();
+    let signature = value.sign(&keypair, "test").unwrap();
+    let record = value.record(keypair, "test", Metadata::empty()).unwrap();
 
     assert_eq!(&hash, record.hash());
     assert_eq!(&signature, record.signature());
     assert_eq!(&Metadata::empty(), record.metadata());
     assert!(record.verify().is_ok());
 }
+
+#[test]
+fn test_verify_batch() {
+    let ed25519 = blockify::generate_ed25519_keypair();
+    let ecdsa =
+        blockify::generate_ecdsa_keypair(blockify::EcdsaSigningAlgorithm::P256_SHA256_FIXED);
+    let mut records = (0..20)
+        .map(|i| {
+            let value = Detail { val: i };
+            match i % 2 {
+                0 => value.record(&ed25519, "test", Metadata::empty()).unwrap(),
+                _ => value.record(&ecdsa, "test", Metadata::empty()).unwrap(),
+            }
+        })
+        .collect::<Vec<_>>();
+    assert!(blockify::record::verify_batch(&records).is_ok());
+
+    // replace two records with copies signed over different values
+    for i in [4, 7] {
+        let forged = &records[i + 2];
+        records[i] = blockify::record::SignedRecord::new(
+            Detail { val: i as i32 },
+            forged.signature().clone(),
+            forged.signer().clone(),
+            forged.hash().clone(),
+            "test",
+            Metadata::empty(),
+        );
+    }
+
+    // and replay a valid record on another chain
+    let replayed = &records[10];
+    records[10] = blockify::record::SignedRecord::new(
+        Detail { val: 10 },
+        replayed.signature().clone(),
+        replayed.signer().clone(),
+        replayed.hash().clone(),
+        "other",
+        Metadata::empty(),
+    );
+    assert!(records[10].verify().is_err());
+    assert!(records[10].verify_for("test").is_ok());
+
+    let records = blockify::record::Records::new_owned(records);
+    let error = records.verify_batch().unwrap_err();
+    assert_eq!(&[4, 7, 10], error.failed());
+}
+
+fn checksum(value: &Tagged, algorithm: blockify::HashAlgorithm) -> blockify::Hash {
+    blockify::hash_with(algorithm, &(value.val, "checksum"))
+}
+
+#[derive(Serialize, Record)]
+#[record(type_id = "tests/Tagged", version = 4)]
+#[record(hash_with = checksum)]
+struct Tagged {
+    val: u64,
+    #[record(skip)]
+    note: String,
+}
+
+#[derive(Serialize, Record)]
+#[record(type_id = "tests/Skipped")]
+struct Skipped<T: Serialize> {
+    val: T,
+    #[record(skip)]
+    cache: Vec<u8>,
+}
+
+#[derive(Serialize, Record)]
+#[serde(rename_all = "camelCase")]
+struct Renamed {
+    account_id: u64,
+    #[serde(rename = "sum")]
+    amount: u64,
+    #[allow(dead_code)]
+    #[serde(skip)]
+    cache: u64,
+    #[record(skip)]
+    received_at: u64,
+}
+
+#[derive(Serialize)]
+struct RenamedPayload {
+    #[serde(rename = "accountId")]
+    account_id: u64,
+    sum: u64,
+}
+
+#[test]
+fn test_derive_attributes() {
+    assert_eq!("Detail", Detail::<String>::TYPE_ID);
+    assert_eq!(1, Detail::<String>::VERSION);
+    assert_eq!("tests/Tagged", Tagged::TYPE_ID);
+    assert_eq!(4, Tagged::VERSION);
+
+    let keypair = blockify::generate_ed25519_keypair();
+    let mut value = Tagged {
+        val: 7,
+        note: String::from("first"),
+    };
+    assert_eq!(checksum(&value, HashAlgorithm::Sha256), value.hash());
+    assert_eq!(
+        checksum(&value, HashAlgorithm::Blake3),
+        value.hash_with(HashAlgorithm::Blake3)
+    );
+    let signature = value.sign(&keypair, "test").unwrap();
+
+    // the skipped field is neither signed nor hashed
+    value.note = String::from("second");
+    let pubkey = keypair.clone().into_public_key();
+    assert!(value.verify(&signature, &pubkey, "test").is_ok());
+    let record = value.record(&keypair, "test", Metadata::empty()).unwrap();
+    assert_eq!(&signature, record.signature());
+    assert!(record.verify().is_ok());
+
+    // the signing domain holds the type id
+    let domain = blockify::SigningDomain::record::<Tagged>("test");
+    assert_eq!("tests/Tagged", domain.record_type());
+
+    // the payload is the one of a type with only the remaining fields
+    let skipped = Skipped {
+        val: 7u64,
+        cache: vec![1, 2],
+    };
+    let detail = Detail { val: 7u64 };
+    assert_eq!(
+        detail.signing_bytes().unwrap(),
+        skipped.signing_bytes().unwrap()
+    );
+    assert_eq!(detail.hash(), skipped.hash());
+
+    // the remaining fields keep their serde names
+    let renamed = Renamed {
+        account_id: 3,
+        amount: 40,
+        cache: 1,
+        received_at: 17,
+    };
+    let payload = RenamedPayload {
+        account_id: 3,
+        sum: 40,
+    };
+    assert_eq!(
+        blockify::canonical::encode(&payload).unwrap(),
+        renamed.signing_bytes().unwrap()
+    );
+}
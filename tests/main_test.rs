@@ -10,6 +10,7 @@ use blockify::{
 use serde::{Deserialize, Serialize};
 
 #[test]
+#[allow(clippy::result_filter_map)]
 fn test_blocks() {
     #[derive(Debug, Clone, Record, Serialize, Deserialize, PartialEq, Eq)]
     struct Data {
@@ -36,13 +37,13 @@ fn test_blocks() {
         // and collect them into two vectors.
         let records1 = datas1
             .into_iter()
-            .map(|w| Data::new(w).record(keypair.clone(), Metadata::empty()))
+            .map(|w| Data::new(w).record(keypair.clone(), "test", Metadata::empty()))
             .filter(|r| r.is_ok())
             .map(|v| v.unwrap())
             .collect::<Vec<_>>();
         let records2 = datas2
             .into_iter()
-            .map(|w| Data::new(w).record(keypair.clone(), Metadata::empty()))
+            .map(|w| Data::new(w).record(keypair.clone(), "test", Metadata::empty()))
             .filter(|r| r.is_ok())
             .map(|v| v.unwrap())
             .collect::<Vec<_>>();
@@ -53,11 +54,11 @@ fn test_blocks() {
 
         // push the two vec's content into each UnchainedInstance
         for record in records1 {
-            builder1.push(record);
+            builder1.push(record).unwrap();
         }
 
         for record in records2 {
-            builder2.push(record);
+            builder2.push(record).unwrap();
         }
 
         // To build an SqliteChain (BlockChain that stores data in sqlite database),
@@ -136,9 +137,11 @@ fn test_2() {
         pub fn generate_records(amount: usize) -> Vec<SignedRecord<Self>> {
             let mut res = Vec::with_capacity(amount);
             (0..amount).for_each(|_| {
-                match Self::generate()
-                    .record(blockify::generate_ed25519_keypair(), Default::default())
-                {
+                match Self::generate().record(
+                    blockify::generate_ed25519_keypair(),
+                    "test",
+                    Default::default(),
+                ) {
                     Ok(v) => res.push(v),
                     Err(_) => unreachable!("Error occurs"),
                 }
@@ -150,9 +153,9 @@ fn test_2() {
     fn main() {
         let contract = MarriageContract::new("John", "Julie");
         let keypair = blockify::generate_ed25519_keypair();
-        let signature = contract.sign(&keypair).unwrap();
+        let signature = contract.sign(&keypair, "test").unwrap();
         let hash = contract.hash();
-        let record = contract.record(keypair, Metadata::empty()).unwrap();
+        let record = contract.record(keypair, "test", Metadata::empty()).unwrap();
 
         assert_eq!(&hash, record.hash());
         assert_eq!(&signature, record.signature());
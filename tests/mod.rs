@@ -5,4 +5,4 @@ mod feature_tests;
 mod gen_tests;
 mod main_test;
 
-mod all_test;
\ No newline at end of file
+mod all_test;